@@ -2,7 +2,7 @@
 jmp init
 ORG 003h
 cpl P2.2
-clr IEO
+clr IE0
 reti
 
 init:
@@ -0,0 +1,312 @@
+use {
+    crate::{
+        cpu::{
+            diagnostic::{lexer_diagnostics, Diagnostic},
+            main::CPU,
+            set_capture, set_verbosity,
+            state::{State, VarValue},
+            take_global_output, take_messages, verbosity,
+            vm::{Pause, Stop},
+            CPUType, Message, RuntimeError, Verbosity,
+        },
+        dialect::Dialect,
+    },
+    std::time::Duration,
+};
+
+/*
+ * The library API (see the example in `lib.rs`). Nothing is printed,
+ * the output of the program and the messages of the interpreter are
+ * collected by the `Cpu`
+ */
+
+/*
+ * Run `f` with the output and the messages collected instead of printed
+ */
+fn captured<T>(f: impl FnOnce() -> T) -> (T, String, Vec<Message>) {
+    let capture = set_capture(true);
+    let output = verbosity();
+    set_verbosity(Verbosity::Quiet);
+    let result = f();
+    set_verbosity(output);
+    set_capture(capture);
+    (result, take_global_output(), take_messages())
+}
+
+/*
+ * The errors among `messages` with their line (0 if they have none)
+ */
+fn errors(messages: &[Message]) -> Vec<Diagnostic> {
+    messages
+        .iter()
+        .filter(|message| message.kind == "error")
+        .map(|message| Diagnostic::new(&message.text, message.line.unwrap_or(0), ""))
+        .collect()
+}
+
+/*
+ * A parsed and compiled program
+ */
+#[derive(Debug, Clone)]
+pub struct Program {
+    // a CPU with the program loaded, every `Cpu` starts as a copy of it
+    cpu: CPU<CPUType>,
+}
+
+impl Program {
+    /*
+     * Parse a russembly program, a program with errors is rejected
+     * with the errors and the lines they are in
+     */
+    pub fn parse(source: &str) -> Result<Program, Vec<Diagnostic>> {
+        Program::parse_as(source, Dialect::Russembly)
+    }
+    /*
+     * Parse a program written in the given dialect
+     */
+    pub fn parse_as(source: &str, dialect: Dialect) -> Result<Program, Vec<Diagnostic>> {
+        let mut cpu = CPU::new().map_err(|error| vec![Diagnostic::new(error, 0, "")])?;
+        let (loaded, _, messages) = captured(|| cpu.load_string_as(source, dialect));
+        let mut diagnostics = lexer_diagnostics(&messages, source);
+        if loaded.is_some() {
+            diagnostics.extend(cpu.compile_diagnostics());
+        } else if diagnostics.is_empty() {
+            diagnostics.push(Diagnostic::new("No functions found (empty)", 0, ""));
+        }
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        match diagnostics.is_empty() {
+            true => Ok(Program { cpu }),
+            false => Err(diagnostics),
+        }
+    }
+    /*
+     * Load a program in the `.rusmc` format (see `to_rusmc`)
+     */
+    pub fn from_rusmc(bytes: &[u8]) -> Result<Program, Vec<Diagnostic>> {
+        let mut cpu = CPU::new().map_err(|error| vec![Diagnostic::new(error, 0, "")])?;
+        let (loaded, _, messages) = captured(|| cpu.load_rusmc(bytes));
+        match loaded {
+            Some(()) => Ok(Program { cpu }),
+            None => Err(errors(&messages)),
+        }
+    }
+    pub fn to_rusmc(&self) -> Vec<u8> {
+        self.cpu.clone().compile_to_rusmc()
+    }
+    /*
+     * The names of the functions
+     */
+    pub fn functions(&self) -> Vec<String> {
+        self.cpu.functions.iter().map(|f| f.name.clone()).collect()
+    }
+}
+
+/*
+ * Why `step` or `run_for` returned
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Paused,
+    Breakpoint(usize),
+    Watchpoint(usize),
+    Finished,
+}
+
+impl From<Stop> for Status {
+    fn from(stop: Stop) -> Self {
+        match stop {
+            Stop::Paused | Stop::Start => Status::Paused,
+            Stop::Breakpoint(id) => Status::Breakpoint(id),
+            Stop::Watchpoint(id) => Status::Watchpoint(id),
+            Stop::Finished => Status::Finished,
+        }
+    }
+}
+
+/*
+ * A CPU running a `Program`
+ */
+#[derive(Debug, Clone)]
+pub struct Cpu {
+    cpu: CPU<CPUType>,
+    status: Status,
+    // printed by the program since the last `take_output`
+    output: String,
+    messages: Vec<Message>,
+}
+
+impl Cpu {
+    /*
+     * A CPU with the program loaded, paused before the first line of `main`
+     */
+    pub fn new(program: &Program) -> Cpu {
+        let mut cpu = Cpu {
+            cpu: program.cpu.clone(),
+            status: Status::Finished,
+            output: String::new(),
+            messages: vec![],
+        };
+        let stop = cpu.capture(|cpu| cpu.debug_main());
+        cpu.status = stop.into();
+        cpu
+    }
+    fn capture<T>(&mut self, f: impl FnOnce(&mut CPU<CPUType>) -> T) -> T {
+        let cpu = &mut self.cpu;
+        let (result, output, messages) = captured(|| f(cpu));
+        self.output.push_str(&output);
+        self.messages.extend(messages);
+        result
+    }
+    fn result(&self) -> Result<Status, RuntimeError> {
+        match &self.cpu.runtime_error {
+            Some(error) => Err(error.clone()),
+            None => Ok(self.status),
+        }
+    }
+    /*
+     * Run to the end of the program, breakpoints are ignored
+     */
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        if self.cpu.frames.is_empty() {
+            return self.result().map(|_| ());
+        }
+        let program = self.cpu.program();
+        self.capture(|cpu| cpu.run_frames(&program, 0, Pause::Never));
+        self.status = Status::Finished;
+        self.result().map(|_| ())
+    }
+    /*
+     * Execute the line the program is paused at
+     */
+    pub fn step(&mut self) -> Result<Status, RuntimeError> {
+        let stop = self.capture(|cpu| cpu.step());
+        self.status = stop.into();
+        self.result()
+    }
+    /*
+     * Run until a breakpoint, a watchpoint or the end but at most `lines` lines
+     */
+    pub fn run_for(&mut self, lines: usize) -> Result<Status, RuntimeError> {
+        let stop = self.capture(|cpu| cpu.resume_for(lines));
+        self.status = stop.into();
+        self.result()
+    }
+    pub fn status(&self) -> Status {
+        self.status
+    }
+    /*
+     * The runtime error which stopped the program
+     */
+    pub fn error(&self) -> Option<&RuntimeError> {
+        self.cpu.runtime_error.as_ref()
+    }
+    /*
+     * The line (1-based) the program is paused at
+     */
+    pub fn line(&self) -> Option<usize> {
+        let pc = self.cpu.frames.last()?.pc;
+        self.cpu.program.as_ref()?.file_line(pc)
+    }
+    /*
+     * A breakpoint at a line, function or label with an optional
+     * condition (`P1 == 3`), returns its id
+     */
+    pub fn add_breakpoint(
+        &mut self,
+        location: &str,
+        condition: Option<&str>,
+    ) -> Result<usize, String> {
+        self.cpu.add_breakpoint(location, condition)
+    }
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.cpu.remove_breakpoint(id)
+    }
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.cpu.set_instruction_limit(limit);
+    }
+    pub fn set_time_limit(&mut self, limit: Option<Duration>) {
+        self.cpu.set_time_limit(limit);
+    }
+    /*
+     * The output of the program since the last call
+     */
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+    /*
+     * The messages of the interpreter (mostly errors) since the last call
+     */
+    pub fn take_messages(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /*
+     * --------------------------------------------------------------
+     * State
+     * --------------------------------------------------------------
+     */
+
+    pub fn state(&self) -> State {
+        self.cpu.state()
+    }
+    pub fn accumulator(&self) -> CPUType {
+        self.cpu.accumulator
+    }
+    pub fn port(&self, port: usize) -> Option<CPUType> {
+        self.cpu.port.get(port).copied()
+    }
+    /*
+     * Change an input pin, the value is cut to the word width
+     */
+    pub fn set_port(&mut self, port: usize, value: CPUType) -> Result<(), String> {
+        if port >= self.cpu.port.len() {
+            return Err(format!("Port: {port} out of bounds (0 - 7)"));
+        }
+        self.cpu.port[port] = value & self.cpu.word_mask;
+        Ok(())
+    }
+    pub fn stack(&self) -> &[CPUType] {
+        &self.cpu.stack
+    }
+    /*
+     * R0 - R7 of the selected register bank
+     */
+    pub fn register(&self, register: usize) -> Option<u8> {
+        (register < 8).then(|| self.cpu.get_register(register))
+    }
+    /*
+     * The value of a variable of the running functions
+     */
+    pub fn var(&self, name: &str) -> Option<VarValue> {
+        self.cpu
+            .vars
+            .iter()
+            .rev()
+            .find(|var| var.name() == name)
+            .map(VarValue::from)
+    }
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles
+    }
+    pub fn instructions(&self) -> u64 {
+        self.cpu.get_instructions()
+    }
+    /*
+     * The whole state as JSON, see `restore`
+     */
+    pub fn snapshot(&self) -> String {
+        self.cpu.get_json()
+    }
+    /*
+     * Load the state of a `snapshot`, the breakpoints stay. A snapshot
+     * with a corrupted program or pointers into nowhere is rejected.
+     */
+    pub fn restore(&mut self, json: &str) -> Result<(), String> {
+        self.cpu.restore(json)?;
+        self.status = match self.cpu.frames.is_empty() {
+            true => Status::Finished,
+            false => Status::Paused,
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    asm::opcodes::{decode, Opcode, Operand},
+    cpu::sfr::{bit_name, sfr_by_address},
+};
+
+/*
+ * A decoded instruction of the code memory
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassembledLine {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+    pub mnemonic: String,
+    pub operands: Vec<String>,
+}
+
+impl DisassembledLine {
+    /*
+     * The instruction in the syntax of the 8051 dialect
+     */
+    pub fn instruction(&self) -> String {
+        match self.operands.is_empty() {
+            true => self.mnemonic.clone(),
+            false => format!("{} {}", self.mnemonic, self.operands.join(", ")),
+        }
+    }
+}
+
+/*
+ * Disassemble the code from `start` up to (excluding) `end`. Jump and call
+ * targets inside the range get a label `Lxxxx`, bytes which are no
+ * instruction (A5h or a cut off instruction) are shown as `db`.
+ */
+pub fn disassemble(code: &[u8], start: usize, end: usize) -> Vec<DisassembledLine> {
+    let end = end.min(code.len());
+    let mut decoded: Vec<(usize, Option<Opcode>)> = vec![];
+    let mut address = start;
+    while address < end {
+        match decode(code[address]).filter(|opcode| address + opcode.size() <= end) {
+            Some(opcode) => {
+                let size = opcode.size();
+                decoded.push((address, Some(opcode)));
+                address += size;
+            }
+            None => {
+                decoded.push((address, None));
+                address += 1;
+            }
+        }
+    }
+    // first pass: recover the labels
+    let mut targets: Vec<usize> = decoded
+        .iter()
+        .filter_map(|(address, opcode)| Some((*address, opcode.as_ref()?)))
+        .flat_map(|(address, opcode)| {
+            let operands = operand_values(code, address, opcode);
+            opcode
+                .operands
+                .iter()
+                .zip(operands)
+                .filter(|(operand, _)| {
+                    matches!(operand, Operand::Rel | Operand::Addr11 | Operand::Addr16)
+                })
+                .map(|(_, target)| target)
+                .collect::<Vec<usize>>()
+        })
+        .filter(|target| decoded.iter().any(|(address, _)| address == target))
+        .collect();
+    targets.sort();
+    targets.dedup();
+    let label = |address: usize| {
+        targets
+            .binary_search(&address)
+            .ok()
+            .map(|_| format!("L{address:04X}"))
+    };
+
+    // second pass: format the instructions
+    decoded
+        .into_iter()
+        .map(|(address, opcode)| match opcode {
+            Some(opcode) => {
+                let values = operand_values(code, address, &opcode);
+                let mut operands: Vec<String> = opcode
+                    .operands
+                    .iter()
+                    .zip(values)
+                    .map(|(operand, value)| format_operand(*operand, value, &label))
+                    .collect();
+                // mov direct, direct stores the source address first
+                if opcode.opcode == 0x85 {
+                    operands.swap(0, 1);
+                }
+                DisassembledLine {
+                    address,
+                    bytes: code[address..address + opcode.size()].to_vec(),
+                    label: label(address),
+                    mnemonic: opcode.mnemonic.to_string(),
+                    operands,
+                }
+            }
+            None => DisassembledLine {
+                address,
+                bytes: vec![code[address]],
+                label: label(address),
+                mnemonic: "db".to_string(),
+                operands: vec![hex(code[address] as usize)],
+            },
+        })
+        .collect()
+}
+
+/*
+ * Listing with address, machine code and instruction of every line
+ */
+pub fn format_disassembly(lines: &[DisassembledLine]) -> String {
+    let mut output = String::new();
+    for line in lines {
+        if let Some(label) = &line.label {
+            output.push_str(&format!("{label}:\n"));
+        }
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{b:02X}")).collect();
+        output.push_str(&format!(
+            "{:04X}  {:<9} {}\n",
+            line.address,
+            bytes.join(" "),
+            line.instruction()
+        ));
+    }
+    output
+}
+
+/*
+ * The values of the operands, relative and 11 bit addresses
+ * are resolved to absolute addresses
+ */
+fn operand_values(code: &[u8], address: usize, opcode: &Opcode) -> Vec<usize> {
+    let next = address + opcode.size();
+    let mut position = address + 1;
+    let mut values = vec![];
+    for operand in &opcode.operands {
+        let value = match operand.size() {
+            0 => 0,
+            1 => code[position] as usize,
+            _ => (code[position] as usize) << 8 | code[position + 1] as usize,
+        };
+        position += operand.size();
+        values.push(match operand {
+            Operand::Rel => (next as isize + value as u8 as i8 as isize) as usize & 0xFFFF,
+            Operand::Addr11 => (next & 0xF800) | ((opcode.opcode as usize >> 5) << 8) | value,
+            _ => value,
+        });
+    }
+    values
+}
+
+fn format_operand(
+    operand: Operand,
+    value: usize,
+    label: &impl Fn(usize) -> Option<String>,
+) -> String {
+    match operand {
+        Operand::A => "A".to_string(),
+        Operand::AB => "AB".to_string(),
+        Operand::C => "C".to_string(),
+        Operand::Dptr => "DPTR".to_string(),
+        Operand::AtDptr => "@DPTR".to_string(),
+        Operand::AtADptr => "@A+DPTR".to_string(),
+        Operand::AtAPc => "@A+PC".to_string(),
+        Operand::Register(n) => format!("R{n}"),
+        Operand::Indirect(n) => format!("@R{n}"),
+        Operand::Immediate | Operand::Immediate16 => format!("#{}", hex(value)),
+        Operand::Direct => match sfr_by_address(value as u8) {
+            Some(sfr) if value >= 0x80 => sfr.name.to_string(),
+            _ => hex(value),
+        },
+        Operand::Bit => bit_name(value as u8),
+        Operand::NotBit => format!("/{}", bit_name(value as u8)),
+        Operand::Rel | Operand::Addr11 | Operand::Addr16 => {
+            label(value).unwrap_or_else(|| hex(value))
+        }
+    }
+}
+
+/*
+ * A hex number in assembler syntax (`30h`, `0FFh`)
+ */
+fn hex(value: usize) -> String {
+    let digits = format!("{value:02X}");
+    match digits.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        true => format!("0{digits}h"),
+        false => format!("{digits}h"),
+    }
+}
@@ -0,0 +1,99 @@
+/*
+ * Intel HEX records of the code memory (16 data bytes per record)
+ */
+const BYTES_PER_RECORD: usize = 16;
+const RECORD_DATA: u8 = 0x00;
+const RECORD_END_OF_FILE: u8 = 0x01;
+const RECORD_EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const RECORD_START_SEGMENT_ADDRESS: u8 = 0x03;
+const RECORD_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const RECORD_START_LINEAR_ADDRESS: u8 = 0x05;
+
+/*
+ * Write contiguous `(address, bytes)` segments as Intel HEX
+ */
+pub fn write_intel_hex(segments: &[(usize, Vec<u8>)]) -> String {
+    let mut output = String::new();
+    for (address, bytes) in segments {
+        for (i, chunk) in bytes.chunks(BYTES_PER_RECORD).enumerate() {
+            let record_address = address + i * BYTES_PER_RECORD;
+            output.push_str(&record(RECORD_DATA, record_address as u16, chunk));
+        }
+    }
+    output.push_str(&record(RECORD_END_OF_FILE, 0, &[]));
+    output
+}
+
+fn record(record_type: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![
+        data.len() as u8,
+        (address >> 8) as u8,
+        address as u8,
+        record_type,
+    ];
+    bytes.extend_from_slice(data);
+    // the checksum is the two's complement of the sum of all bytes
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(sum.wrapping_neg());
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+    format!(":{hex}\n")
+}
+
+/*
+ * Read the data records of an Intel HEX file as `(address, bytes)` segments,
+ * extended linear and segment address records are applied to the addresses
+ */
+pub fn read_intel_hex(hex: &str) -> Result<Vec<(usize, Vec<u8>)>, String> {
+    let mut segments = vec![];
+    let mut base = 0;
+    for (i, line) in hex.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = parse_record(line).map_err(|error| format!("{error} at line {}", i + 1))?;
+        let (length, record_type) = (bytes[0] as usize, bytes[3]);
+        let address = (bytes[1] as usize) << 8 | bytes[2] as usize;
+        let data = &bytes[4..4 + length];
+        match record_type {
+            RECORD_DATA => segments.push((base + address, data.to_vec())),
+            RECORD_END_OF_FILE => return Ok(segments),
+            RECORD_EXTENDED_SEGMENT_ADDRESS if length == 2 => {
+                base = ((data[0] as usize) << 8 | data[1] as usize) << 4
+            }
+            RECORD_EXTENDED_LINEAR_ADDRESS if length == 2 => {
+                base = ((data[0] as usize) << 8 | data[1] as usize) << 16
+            }
+            // start addresses do not matter for the 8051
+            RECORD_START_SEGMENT_ADDRESS | RECORD_START_LINEAR_ADDRESS => {}
+            _ => {
+                return Err(format!(
+                    "invalid record type {record_type:02X} at line {}",
+                    i + 1
+                ))
+            }
+        }
+    }
+    Err("missing end of file record".to_string())
+}
+
+fn parse_record(line: &str) -> Result<Vec<u8>, String> {
+    let digits = line
+        .strip_prefix(':')
+        .ok_or_else(|| "a record has to start with `:`".to_string())?;
+    if digits.len() % 2 != 0 || !digits.is_ascii() {
+        return Err("invalid record".to_string());
+    }
+    let bytes = (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| "invalid hex digits".to_string())?;
+    if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+        return Err("invalid record length".to_string());
+    }
+    if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+        return Err("checksum mismatch".to_string());
+    }
+    Ok(bytes)
+}
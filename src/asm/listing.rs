@@ -0,0 +1,151 @@
+use {
+    crate::{
+        asm::Assembly,
+        lexer_new::{Lexer, Token},
+    },
+    serde::{Deserialize, Serialize},
+};
+
+/*
+ * Kind of an entry of the symbol table
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SymbolKind {
+    Label,
+    Const,
+    Global,
+}
+
+impl std::fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SymbolKind::Label => f.pad("label"),
+            SymbolKind::Const => f.pad("const"),
+            SymbolKind::Global => f.pad("global"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub value: String,
+}
+
+impl Assembly {
+    /*
+     * Labels with their address and the consts and globals the
+     * lexer finds in `const NAME = value` and `global NAME = value`
+     */
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self
+            .labels
+            .iter()
+            .map(|(name, address)| Symbol {
+                name: name.clone(),
+                kind: SymbolKind::Label,
+                value: format!("{address:04X}h"),
+            })
+            .collect();
+        let code = self
+            .lines
+            .iter()
+            .map(|line| line.source.split(';').next().unwrap_or("").trim())
+            .take_while(|code| !code.eq_ignore_ascii_case("end"));
+        symbols.extend(definitions(code));
+        symbols
+    }
+    /*
+     * The `.lst` listing: every source line with its address, machine code
+     * and diagnostics (of the lexer and the assembler), followed by the
+     * symbol table. The data of `DB` and `DW` continues in lines of 3 bytes.
+     */
+    pub fn to_listing(&self) -> String {
+        let mut output = String::from("Line  Addr  Code      Source\n");
+        for line in &self.lines {
+            let mut chunks = line.bytes.chunks(3);
+            let bytes = |chunk: &[u8]| -> String {
+                let bytes: Vec<String> = chunk.iter().map(|b| format!("{b:02X}")).collect();
+                bytes.join(" ")
+            };
+            let address = match line.bytes.is_empty() {
+                true => String::new(),
+                false => format!("{:04X}", line.address),
+            };
+            output.push_str(
+                format!(
+                    "{:>4}  {:<4}  {:<8}  {}",
+                    line.line,
+                    address,
+                    chunks.next().map(bytes).unwrap_or_default(),
+                    line.source
+                )
+                .trim_end(),
+            );
+            output.push('\n');
+            for (i, chunk) in chunks.enumerate() {
+                let address = line.address + 3 * (i + 1);
+                output.push_str(&format!("      {address:04X}  {}\n", bytes(chunk)));
+            }
+            for error in &line.errors {
+                output.push_str(&format!("*** error: {error}\n"));
+            }
+        }
+        output.push_str(&format!("\n{} errors\n", self.error_count()));
+
+        let mut symbols = self.symbols();
+        symbols.sort_by_key(|symbol| symbol.name.to_lowercase());
+        output.push_str("\nSymbols:\n");
+        let width = symbols
+            .iter()
+            .map(|s| s.name.len())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+        output.push_str(&format!("{:<width$}  Kind    Value\n", "Name"));
+        for symbol in symbols {
+            output.push_str(&format!(
+                "{:<width$}  {:<6}  {}\n",
+                symbol.name, symbol.kind, symbol.value
+            ));
+        }
+        output
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_listing(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_listing())
+    }
+}
+
+/*
+ * The consts and globals the lexer finds in `const NAME = value`
+ * and `global NAME = value` lines
+ */
+pub fn definitions<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<Symbol> {
+    let definitions: Vec<&str> = lines
+        .map(|line| line.trim())
+        .filter(|code| code.starts_with("const ") || code.starts_with("global "))
+        .collect();
+    if definitions.is_empty() {
+        return vec![];
+    }
+    let mut lexer = Lexer::new();
+    lexer.top_level(definitions.join("\n"));
+    lexer
+        .tmp_ast
+        .into_iter()
+        .filter_map(|token| {
+            let (kind, definition) = match token {
+                Token::Const(definition) => (SymbolKind::Const, definition),
+                Token::Global(definition) => (SymbolKind::Global, definition),
+                _ => return None,
+            };
+            Some(Symbol {
+                name: definition.name,
+                kind,
+                value: definition.value,
+            })
+        })
+        .collect()
+}
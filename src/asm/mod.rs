@@ -0,0 +1,539 @@
+#![allow(dead_code)]
+pub mod disasm;
+pub mod hex;
+pub mod listing;
+pub mod opcodes;
+
+use {
+    crate::{
+        asm::opcodes::{decode, Opcode, Operand},
+        cpu::{printx, set_capture, set_source_line, CPUType, PrintT, LEXER_ERROR_COUNT, MESSAGES},
+        dialect::translate_8051,
+        lexer::{parse_number, Lexer, Token, TokenType},
+    },
+    hex::write_intel_hex,
+};
+
+/*
+ * Size of the code memory of the 8051
+ */
+pub const CODE_SIZE: usize = 0x10000;
+
+/*
+ * An operand as it is written in the source
+ */
+#[derive(Debug, Clone, PartialEq)]
+enum Argument {
+    A,
+    AB,
+    C,
+    Dptr,
+    AtDptr,
+    Register(u8),
+    Indirect(u8),
+    Immediate(CPUType),
+    Direct(CPUType),
+    Bit(u8),
+    Label(String),
+    // a quoted string after `DB`
+    Text(String),
+}
+
+/*
+ * What a line assembles to
+ */
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Instruction(Opcode, Vec<Argument>),
+    // `DB` (1 byte per value) or `DW` (2 bytes per value)
+    Data(usize, Vec<Argument>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(opcode, _) => opcode.size(),
+            Statement::Data(width, values) => values
+                .iter()
+                .map(|value| match value {
+                    Argument::Text(text) => text.len(),
+                    _ => *width,
+                })
+                .sum(),
+        }
+    }
+}
+
+/*
+ * A source line with its address and machine code
+ */
+#[derive(Debug, Clone, Default)]
+pub struct AssembledLine {
+    pub line: usize,
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    pub source: String,
+    pub errors: Vec<String>,
+}
+
+/*
+ * The result of assembling a 8051 program
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+    pub lines: Vec<AssembledLine>,
+    pub labels: Vec<(String, usize)>,
+}
+
+/*
+ * Assemble a program written in the 8051 dialect into machine code. The
+ * first pass assigns addresses to the lines and labels, the second pass
+ * encodes the instructions. Generic `jmp` and `call` become `ljmp`/`lcall`,
+ * `$` is the address of the instruction it is used in. The errors of the
+ * lexer become errors of their line.
+ */
+pub fn assemble(source: &str) -> Assembly {
+    let program = translate_8051(source);
+    let line_count = program.lines.len();
+    let mut lexer = Lexer::new();
+    let mut lexer_errors: Vec<Vec<String>> = vec![];
+    let mut data: Vec<Option<(usize, &str)>> = vec![];
+    for (i, line) in program.lines.iter().enumerate() {
+        // the lexer knows no data, it only reads the label of a data line
+        let (code, directive) = match data_directive(line) {
+            Some((label, width, values)) => (label, Some((width, values))),
+            None => (line.as_str(), None),
+        };
+        data.push(directive);
+        lexer_errors.push(lexer_errors_of(i + 1, || {
+            lexer.run(code.to_string(), line_count.max(1))
+        }));
+    }
+    let mut assembly = Assembly::default();
+    let lines = match lexer.get_lines() {
+        Some(lines) => lines,
+        None => return assembly,
+    };
+    let sources: Vec<&str> = source.lines().collect();
+    let opcodes: Vec<Opcode> = (0..=255).filter_map(decode).collect();
+
+    // first pass
+    let mut statements: Vec<Option<Statement>> = vec![];
+    let mut address = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if let Some((origin, _)) = program.origins.iter().find(|(_, l)| *l == i) {
+            address = *origin;
+        }
+        let mut assembled = AssembledLine {
+            line: i + 1,
+            address,
+            source: sources.get(i).unwrap_or(&"").to_string(),
+            errors: lexer_errors.get(i).cloned().unwrap_or_default(),
+            ..Default::default()
+        };
+        let mut statement = None;
+        if let Some(Some((width, values))) = data.get(i) {
+            match parse_data(*width, values) {
+                Ok(values) => statement = Some(Statement::Data(*width, values)),
+                Err(error) => assembled.errors.push(error),
+            }
+        }
+        for (position, token) in line.tokens.iter().enumerate() {
+            match &token.token_type {
+                TokenType::JumpLocation(location) => {
+                    if assembly
+                        .labels
+                        .iter()
+                        .any(|(name, _)| *name == location.name)
+                    {
+                        assembled
+                            .errors
+                            .push(format!("label `{}` is defined twice", location.name));
+                    } else {
+                        assembly.labels.push((location.name.clone(), address));
+                    }
+                }
+                // the operands of a line the lexer failed on are incomplete
+                TokenType::OpCode if assembled.errors.is_empty() => {
+                    let tokens = &line.tokens[position + 1..];
+                    match parse_instruction(&token.value, tokens, &opcodes) {
+                        Ok((opcode, arguments)) => {
+                            statement = Some(Statement::Instruction(opcode, arguments))
+                        }
+                        Err(error) => assembled.errors.push(error),
+                    }
+                    break;
+                }
+                _ => {}
+            }
+        }
+        address += statement.as_ref().map_or(0, Statement::size);
+        statements.push(statement);
+        assembly.lines.push(assembled);
+    }
+
+    // second pass
+    let mut used: Vec<(usize, usize)> = vec![];
+    for (assembled, statement) in assembly.lines.iter_mut().zip(statements) {
+        if let Some(statement) = statement {
+            let (start, end) = (assembled.address, assembled.address + statement.size());
+            if end > CODE_SIZE {
+                assembled
+                    .errors
+                    .push("the code does not fit into 64K".to_string());
+            } else if let Some((other, _)) = used.iter().find(|(s, e)| start < *e && *s < end) {
+                assembled
+                    .errors
+                    .push(format!("the code overlaps the code at 0x{other:04x}"));
+            } else {
+                let bytes = match &statement {
+                    Statement::Instruction(opcode, arguments) => {
+                        encode(opcode, arguments, start, &assembly.labels)
+                    }
+                    Statement::Data(width, values) => {
+                        encode_data(*width, values, start, &assembly.labels)
+                    }
+                };
+                match bytes {
+                    Ok(bytes) => {
+                        assembled.bytes = bytes;
+                        used.push((start, end));
+                    }
+                    Err(error) => assembled.errors.push(error),
+                }
+            }
+        }
+        for error in &assembled.errors {
+            printx(
+                PrintT::Error,
+                &format!("{error} at line {}", assembled.line),
+            );
+        }
+    }
+    assembly
+}
+
+impl Assembly {
+    pub fn error_count(&self) -> usize {
+        self.lines.iter().map(|line| line.errors.len()).sum()
+    }
+    /*
+     * Contiguous `(address, bytes)` blocks of code sorted by their address
+     */
+    pub fn segments(&self) -> Vec<(usize, Vec<u8>)> {
+        let mut lines: Vec<&AssembledLine> =
+            self.lines.iter().filter(|l| !l.bytes.is_empty()).collect();
+        lines.sort_by_key(|line| line.address);
+        let mut segments: Vec<(usize, Vec<u8>)> = vec![];
+        for line in lines {
+            match segments.last_mut() {
+                Some((start, bytes)) if *start + bytes.len() == line.address => {
+                    bytes.extend_from_slice(&line.bytes)
+                }
+                _ => segments.push((line.address, line.bytes.clone())),
+            }
+        }
+        segments
+    }
+    pub fn to_intel_hex(&self) -> String {
+        write_intel_hex(&self.segments())
+    }
+    /*
+     * Raw image from address 0 up to the last byte of code,
+     * gaps are filled with FFh like erased flash memory
+     */
+    pub fn to_binary(&self) -> Vec<u8> {
+        let segments = self.segments();
+        let size = match segments.last() {
+            Some((address, bytes)) => address + bytes.len(),
+            None => 0,
+        };
+        let mut image = vec![0xFF; size];
+        for (address, bytes) in segments {
+            image[address..address + bytes.len()].copy_from_slice(&bytes);
+        }
+        image
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_hex(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_intel_hex())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_bin(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_binary())
+    }
+}
+
+/*
+ * Run the lexer on a line and return the errors it reported, the
+ * " at line N" of the messages is cut off
+ */
+fn lexer_errors_of(line: usize, run: impl FnOnce()) -> Vec<String> {
+    let error_count = || LEXER_ERROR_COUNT.with(|count| *count.borrow());
+    let before = (
+        error_count(),
+        MESSAGES.with(|messages| messages.borrow().len()),
+    );
+    let capture = set_capture(true);
+    set_source_line(Some(line));
+    run();
+    set_source_line(None);
+    set_capture(capture);
+    let messages = MESSAGES.with(|messages| messages.borrow_mut().split_off(before.1));
+    let mut errors: Vec<String> = messages
+        .iter()
+        .filter(|message| message.kind == "error")
+        .map(|message| match message.text.rsplit_once(" at line ") {
+            Some((text, _)) => text.to_string(),
+            None => message.text.clone(),
+        })
+        .collect();
+    // the messages are gone if the verbosity mutes them, the count is not
+    for _ in errors.len()..error_count() - before.0 {
+        errors.push("the lexer could not read the line".to_string());
+    }
+    errors
+}
+
+/*
+ * Split a translated `label: db 1, 2` line into the label part,
+ * the size of a value (1 for `db`, 2 for `dw`) and the values
+ */
+fn data_directive(line: &str) -> Option<(&str, usize, &str)> {
+    let code = match line.split_once(": ") {
+        Some((_, code)) => code,
+        None => line,
+    };
+    let (directive, values) = code.split_once(' ').unwrap_or((code, ""));
+    let width = match directive {
+        "db" => 1,
+        "dw" => 2,
+        _ => return None,
+    };
+    Some((&line[..line.len() - code.len()], width, values))
+}
+
+/*
+ * The values after `DB` or `DW`: numbers, labels, `$` and strings (only `DB`)
+ */
+fn parse_data(width: usize, values: &str) -> Result<Vec<Argument>, String> {
+    let directive = match width {
+        1 => "db",
+        _ => "dw",
+    };
+    if values.trim().is_empty() {
+        return Err(format!("`{directive}` needs at least one value"));
+    }
+    values
+        .split(", ")
+        .map(str::trim)
+        .map(|value| {
+            let quoted = value.len() >= 2
+                && (value.starts_with('\'') && value.ends_with('\'')
+                    || value.starts_with('"') && value.ends_with('"'));
+            if quoted && width == 1 {
+                Ok(Argument::Text(value[1..value.len() - 1].to_string()))
+            } else if let Some(number) = parse_number(value) {
+                Ok(Argument::Direct(number))
+            } else if value == "$"
+                || value.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                Ok(Argument::Label(value.to_string()))
+            } else {
+                Err(format!("invalid value `{value}` for `{directive}`"))
+            }
+        })
+        .collect()
+}
+
+/*
+ * Find the opcode for a mnemonic and the tokens of its operands
+ */
+fn parse_instruction(
+    mnemonic: &str,
+    tokens: &[Token],
+    opcodes: &[Opcode],
+) -> Result<(Opcode, Vec<Argument>), String> {
+    let arguments = tokens
+        .iter()
+        .filter(|token| token.token_type != TokenType::Comma)
+        .map(argument)
+        .collect::<Result<Vec<Argument>, String>>()?;
+    let mnemonic = match mnemonic {
+        "jmp" => "ljmp",
+        "call" => "lcall",
+        mnemonic => mnemonic,
+    };
+    match opcodes.iter().find(|opcode| {
+        opcode.mnemonic == mnemonic
+            && opcode.operands.len() == arguments.len()
+            && opcode
+                .operands
+                .iter()
+                .zip(&arguments)
+                .all(|(operand, argument)| matches(*operand, argument))
+    }) {
+        Some(opcode) => Ok((opcode.clone(), arguments)),
+        None if opcodes.iter().any(|opcode| opcode.mnemonic == mnemonic) => {
+            Err(format!("invalid operands for `{mnemonic}`"))
+        }
+        None => Err(format!("`{mnemonic}` is no 8051 instruction")),
+    }
+}
+
+fn argument(token: &Token) -> Result<Argument, String> {
+    Ok(match &token.token_type {
+        TokenType::Accumulator => Argument::A,
+        TokenType::Register(n) => Argument::Register(*n as u8),
+        TokenType::Indirect(i) => Argument::Indirect(*i as u8),
+        TokenType::Dptr => Argument::Dptr,
+        TokenType::IndirectDptr => Argument::AtDptr,
+        TokenType::Number(value) if token.value.starts_with('#') => Argument::Immediate(*value),
+        TokenType::Number(value) | TokenType::Address(value) => Argument::Direct(*value),
+        TokenType::Sfr(address) => Argument::Direct(*address as CPUType),
+        TokenType::Port => match token.value.as_str() {
+            "P0" => Argument::Direct(0x80),
+            "P1" => Argument::Direct(0x90),
+            "P2" => Argument::Direct(0xA0),
+            "P3" => Argument::Direct(0xB0),
+            port => return Err(format!("`{port}` is no 8051 port")),
+        },
+        TokenType::Bit(_) if token.value == "CY" => Argument::C,
+        TokenType::Bit(address) => Argument::Bit(*address),
+        TokenType::Generic if token.value == "AB" => Argument::AB,
+        // the lexer reads the operand of a call as a function name
+        TokenType::Generic | TokenType::FunctionName => Argument::Label(token.value.clone()),
+        _ => return Err(format!("unsupported operand `{}`", token.value)),
+    })
+}
+
+fn matches(operand: Operand, argument: &Argument) -> bool {
+    match (operand, argument) {
+        (Operand::A, Argument::A)
+        | (Operand::AB, Argument::AB)
+        | (Operand::C, Argument::C)
+        | (Operand::Dptr, Argument::Dptr)
+        | (Operand::AtDptr, Argument::AtDptr) => true,
+        (Operand::Register(n), Argument::Register(m)) => n == *m,
+        (Operand::Indirect(n), Argument::Indirect(m)) => n == *m,
+        (Operand::Immediate | Operand::Immediate16, Argument::Immediate(_)) => true,
+        (Operand::Direct, Argument::Direct(_)) => true,
+        (Operand::Bit, Argument::Bit(_)) => true,
+        (
+            Operand::Rel | Operand::Addr11 | Operand::Addr16,
+            Argument::Label(_) | Argument::Direct(_),
+        ) => true,
+        _ => false,
+    }
+}
+
+/*
+ * Encode an instruction at `address`
+ */
+fn encode(
+    opcode: &Opcode,
+    arguments: &[Argument],
+    address: usize,
+    labels: &[(String, usize)],
+) -> Result<Vec<u8>, String> {
+    let next = address + opcode.size();
+    let mut first = opcode.opcode;
+    let mut bytes = vec![];
+    for (operand, argument) in opcode.operands.iter().zip(arguments) {
+        match (operand, argument) {
+            (Operand::Immediate16, Argument::Immediate(value)) => {
+                if *value > 0xFFFF {
+                    return Err(format!("#{value} does not fit into 16 bits"));
+                }
+                bytes.extend_from_slice(&[(value >> 8) as u8, *value as u8]);
+            }
+            (_, Argument::Immediate(value) | Argument::Direct(value))
+                if *operand == Operand::Immediate || *operand == Operand::Direct =>
+            {
+                bytes.push(byte(*value)?);
+            }
+            (_, Argument::Bit(address)) => bytes.push(*address),
+            (Operand::Rel, _) => {
+                let offset = target(argument, address, labels)? as isize - next as isize;
+                if !(-128..=127).contains(&offset) {
+                    return Err(format!("jump target is out of range ({offset} bytes)"));
+                }
+                bytes.push(offset as i8 as u8);
+            }
+            (Operand::Addr11, _) => {
+                let target = target(argument, address, labels)?;
+                if target & 0xF800 != next & 0xF800 {
+                    return Err(format!("0x{target:04x} is not inside the current 2K page"));
+                }
+                first |= (((target >> 8) & 0x07) as u8) << 5;
+                bytes.push(target as u8);
+            }
+            (Operand::Addr16, _) => {
+                let target = target(argument, address, labels)?;
+                bytes.extend_from_slice(&[(target >> 8) as u8, target as u8]);
+            }
+            _ => {}
+        }
+    }
+    // mov direct, direct stores the source address first
+    if opcode.opcode == 0x85 {
+        bytes.swap(0, 1);
+    }
+    bytes.insert(0, first);
+    Ok(bytes)
+}
+
+/*
+ * Encode the values of a `DB` or `DW` at `address`, words are stored
+ * with the high byte first
+ */
+fn encode_data(
+    width: usize,
+    values: &[Argument],
+    address: usize,
+    labels: &[(String, usize)],
+) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    for value in values {
+        let number = match value {
+            Argument::Text(text) => {
+                bytes.extend_from_slice(text.as_bytes());
+                continue;
+            }
+            Argument::Label(_) => target(value, address, labels)?,
+            Argument::Direct(number) => *number,
+            _ => return Err("invalid value".to_string()),
+        };
+        match width {
+            1 => bytes.push(byte(number)?),
+            _ if number > 0xFFFF => return Err(format!("{number} does not fit into 16 bits")),
+            _ => bytes.extend_from_slice(&[(number >> 8) as u8, number as u8]),
+        }
+    }
+    Ok(bytes)
+}
+
+fn byte(value: CPUType) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("{value} does not fit into a byte"))
+}
+
+/*
+ * The address of a label, `$` is the address of the current instruction
+ */
+fn target(
+    argument: &Argument,
+    address: usize,
+    labels: &[(String, usize)],
+) -> Result<usize, String> {
+    match argument {
+        Argument::Label(name) if name == "$" => Ok(address),
+        Argument::Label(name) => labels
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, address)| *address)
+            .ok_or_else(|| format!("unknown label `{name}`")),
+        Argument::Direct(address) if *address < CODE_SIZE => Ok(*address),
+        _ => Err("invalid jump target".to_string()),
+    }
+}
@@ -0,0 +1,197 @@
+/*
+ * Operands of a 8051 instruction as they are encoded
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    A,
+    AB,
+    C,
+    Dptr,
+    // @DPTR
+    AtDptr,
+    // @A+DPTR
+    AtADptr,
+    // @A+PC
+    AtAPc,
+    Register(u8),
+    Indirect(u8),
+    // #data
+    Immediate,
+    // #data16
+    Immediate16,
+    Direct,
+    Bit,
+    // /bit
+    NotBit,
+    // relative jump offset
+    Rel,
+    // address inside the current 2K page
+    Addr11,
+    Addr16,
+}
+
+impl Operand {
+    /*
+     * Bytes this operand takes after the opcode byte
+     */
+    pub fn size(&self) -> usize {
+        match self {
+            Operand::Immediate
+            | Operand::Direct
+            | Operand::Bit
+            | Operand::NotBit
+            | Operand::Rel
+            | Operand::Addr11 => 1,
+            Operand::Immediate16 | Operand::Addr16 => 2,
+            _ => 0,
+        }
+    }
+}
+
+/*
+ * An entry of the 8051 opcode table
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opcode {
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    pub cycles: usize,
+}
+
+impl Opcode {
+    pub fn size(&self) -> usize {
+        1 + self.operands.iter().map(|o| o.size()).sum::<usize>()
+    }
+}
+
+/*
+ * Decode an opcode byte, `None` for the reserved opcode A5h
+ */
+pub fn decode(opcode: u8) -> Option<Opcode> {
+    use Operand::*;
+    let r = Register(opcode & 0x07);
+    let i = Indirect(opcode & 0x01);
+    let (mnemonic, operands, cycles): (&str, Vec<Operand>, usize) = match opcode {
+        0x00 => ("nop", vec![], 1),
+        op if op & 0x1F == 0x01 => ("ajmp", vec![Addr11], 2),
+        op if op & 0x1F == 0x11 => ("acall", vec![Addr11], 2),
+        0x02 => ("ljmp", vec![Addr16], 2),
+        0x03 => ("rr", vec![A], 1),
+        0x04 => ("inc", vec![A], 1),
+        0x05 => ("inc", vec![Direct], 1),
+        0x06..=0x07 => ("inc", vec![i], 1),
+        0x08..=0x0F => ("inc", vec![r], 1),
+        0x10 => ("jbc", vec![Bit, Rel], 2),
+        0x12 => ("lcall", vec![Addr16], 2),
+        0x13 => ("rrc", vec![A], 1),
+        0x14 => ("dec", vec![A], 1),
+        0x15 => ("dec", vec![Direct], 1),
+        0x16..=0x17 => ("dec", vec![i], 1),
+        0x18..=0x1F => ("dec", vec![r], 1),
+        0x20 => ("jb", vec![Bit, Rel], 2),
+        0x22 => ("ret", vec![], 2),
+        0x23 => ("rl", vec![A], 1),
+        0x24 => ("add", vec![A, Immediate], 1),
+        0x25 => ("add", vec![A, Direct], 1),
+        0x26..=0x27 => ("add", vec![A, i], 1),
+        0x28..=0x2F => ("add", vec![A, r], 1),
+        0x30 => ("jnb", vec![Bit, Rel], 2),
+        0x32 => ("reti", vec![], 2),
+        0x33 => ("rlc", vec![A], 1),
+        0x34 => ("addc", vec![A, Immediate], 1),
+        0x35 => ("addc", vec![A, Direct], 1),
+        0x36..=0x37 => ("addc", vec![A, i], 1),
+        0x38..=0x3F => ("addc", vec![A, r], 1),
+        0x40 => ("jc", vec![Rel], 2),
+        0x42 => ("orl", vec![Direct, A], 1),
+        0x43 => ("orl", vec![Direct, Immediate], 2),
+        0x44 => ("orl", vec![A, Immediate], 1),
+        0x45 => ("orl", vec![A, Direct], 1),
+        0x46..=0x47 => ("orl", vec![A, i], 1),
+        0x48..=0x4F => ("orl", vec![A, r], 1),
+        0x50 => ("jnc", vec![Rel], 2),
+        0x52 => ("anl", vec![Direct, A], 1),
+        0x53 => ("anl", vec![Direct, Immediate], 2),
+        0x54 => ("anl", vec![A, Immediate], 1),
+        0x55 => ("anl", vec![A, Direct], 1),
+        0x56..=0x57 => ("anl", vec![A, i], 1),
+        0x58..=0x5F => ("anl", vec![A, r], 1),
+        0x60 => ("jz", vec![Rel], 2),
+        0x62 => ("xrl", vec![Direct, A], 1),
+        0x63 => ("xrl", vec![Direct, Immediate], 2),
+        0x64 => ("xrl", vec![A, Immediate], 1),
+        0x65 => ("xrl", vec![A, Direct], 1),
+        0x66..=0x67 => ("xrl", vec![A, i], 1),
+        0x68..=0x6F => ("xrl", vec![A, r], 1),
+        0x70 => ("jnz", vec![Rel], 2),
+        0x72 => ("orl", vec![C, Bit], 2),
+        0x73 => ("jmp", vec![AtADptr], 2),
+        0x74 => ("mov", vec![A, Immediate], 1),
+        0x75 => ("mov", vec![Direct, Immediate], 2),
+        0x76..=0x77 => ("mov", vec![i, Immediate], 1),
+        0x78..=0x7F => ("mov", vec![r, Immediate], 1),
+        0x80 => ("sjmp", vec![Rel], 2),
+        0x82 => ("anl", vec![C, Bit], 2),
+        0x83 => ("movc", vec![A, AtAPc], 2),
+        0x84 => ("div", vec![AB], 4),
+        0x85 => ("mov", vec![Direct, Direct], 2),
+        0x86..=0x87 => ("mov", vec![Direct, i], 2),
+        0x88..=0x8F => ("mov", vec![Direct, r], 2),
+        0x90 => ("mov", vec![Dptr, Immediate16], 2),
+        0x92 => ("mov", vec![Bit, C], 2),
+        0x93 => ("movc", vec![A, AtADptr], 2),
+        0x94 => ("subb", vec![A, Immediate], 1),
+        0x95 => ("subb", vec![A, Direct], 1),
+        0x96..=0x97 => ("subb", vec![A, i], 1),
+        0x98..=0x9F => ("subb", vec![A, r], 1),
+        0xA0 => ("orl", vec![C, NotBit], 2),
+        0xA2 => ("mov", vec![C, Bit], 1),
+        0xA3 => ("inc", vec![Dptr], 2),
+        0xA4 => ("mul", vec![AB], 4),
+        0xA5 => return None,
+        0xA6..=0xA7 => ("mov", vec![i, Direct], 2),
+        0xA8..=0xAF => ("mov", vec![r, Direct], 2),
+        0xB0 => ("anl", vec![C, NotBit], 2),
+        0xB2 => ("cpl", vec![Bit], 1),
+        0xB3 => ("cpl", vec![C], 1),
+        0xB4 => ("cjne", vec![A, Immediate, Rel], 2),
+        0xB5 => ("cjne", vec![A, Direct, Rel], 2),
+        0xB6..=0xB7 => ("cjne", vec![i, Immediate, Rel], 2),
+        0xB8..=0xBF => ("cjne", vec![r, Immediate, Rel], 2),
+        0xC0 => ("push", vec![Direct], 2),
+        0xC2 => ("clr", vec![Bit], 1),
+        0xC3 => ("clr", vec![C], 1),
+        0xC4 => ("swap", vec![A], 1),
+        0xC5 => ("xch", vec![A, Direct], 1),
+        0xC6..=0xC7 => ("xch", vec![A, i], 1),
+        0xC8..=0xCF => ("xch", vec![A, r], 1),
+        0xD0 => ("pop", vec![Direct], 2),
+        0xD2 => ("setb", vec![Bit], 1),
+        0xD3 => ("setb", vec![C], 1),
+        0xD4 => ("da", vec![A], 1),
+        0xD5 => ("djnz", vec![Direct, Rel], 2),
+        0xD6..=0xD7 => ("xchd", vec![A, i], 1),
+        0xD8..=0xDF => ("djnz", vec![r, Rel], 2),
+        0xE0 => ("movx", vec![A, AtDptr], 2),
+        0xE2..=0xE3 => ("movx", vec![A, i], 2),
+        0xE4 => ("clr", vec![A], 1),
+        0xE5 => ("mov", vec![A, Direct], 1),
+        0xE6..=0xE7 => ("mov", vec![A, i], 1),
+        0xE8..=0xEF => ("mov", vec![A, r], 1),
+        0xF0 => ("movx", vec![AtDptr, A], 2),
+        0xF2..=0xF3 => ("movx", vec![i, A], 2),
+        0xF4 => ("cpl", vec![A], 1),
+        0xF5 => ("mov", vec![Direct, A], 1),
+        0xF6..=0xF7 => ("mov", vec![i, A], 1),
+        0xF8..=0xFF => ("mov", vec![r, A], 1),
+        // the opcodes x1h are matched by ajmp and acall above
+        _ => return None,
+    };
+    Some(Opcode {
+        opcode,
+        mnemonic,
+        operands,
+        cycles,
+    })
+}
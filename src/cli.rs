@@ -0,0 +1,546 @@
+use {
+    crate::{
+        asm::assemble,
+        cpu::{
+            main::CPU, set_verbosity, tracer::Tracer, CPUType, Verbosity, CPU_ERROR_COUNT,
+            LEXER_ERROR_COUNT,
+        },
+        debug::{self, Session},
+        dialect::{translate_8051, Dialect},
+        lexer::{parse_number, Lexer, Line},
+        repl::{self, Repl},
+        Program,
+    },
+    std::{path::Path, time::Duration},
+};
+
+pub(crate) const USAGE: &str = "\
+Usage: russembly <command> [options] <file>
+
+Commands:
+  run <file>          Run a program (.rusm, .asm/.a51, .rusmc, .hex/.ihx/.bin)
+  repl                Start an interactive session
+  debug <file>        Debug a program with breakpoints, `help` lists the commands
+  check <file>        Parse and compile a program and report its errors
+  dump-tokens <file>  Print the tokens of every line
+  dump-ast <file>     Print the functions with their lines and tokens
+  compile <file>      Compile a program into <file>.rusmc
+  asm <file.asm>      Assemble a 8051 program into <file>.hex, <file>.bin and <file>.lst
+  disasm <image> [start] [end]
+                      Print the disassembly of an image
+  help                Print this message
+
+Options:
+  -p, --port <n>=<value>    Initial value of a port, e.g. `-p 1=0FFh` (repeatable)
+  -w, --width <bits>        Word width of the Accumulator, the Ports and the Stack
+  -s, --step-limit <n>      Stop the program after n steps, the lines of a program
+                            or the instructions of an image (also `-i`,
+                            `--instruction-limit`), images stop after 1000000
+                            steps without a limit
+  -l, --cycle-limit <n>     Stop the program after n machine cycles
+      --time-limit <seconds>
+                            Stop the program after this wall-clock time (an error)
+  -q, --quiet               Only print errors and the output of the program
+  -v, --verbose             Print the state of the CPU after the program
+  -j, --json                Print the state of the CPU as JSON after the program
+  -t, --trace <file>        Record every executed line with its changes, as JSON
+                            lines for `.jsonl` and `.json` files, as text otherwise
+                            (`-` prints the text)
+
+Numbers can be written as 255, 0FFh or 11111111b. A program which is stopped
+by a limit exits with 1.";
+
+/*
+ * Images run until they jump to themselves with the interrupts disabled,
+ * a main loop which waits for interrupts is stopped after this many steps
+ */
+pub(crate) const IMAGE_STEP_LIMIT: u64 = 1_000_000;
+
+/*
+ * The parsed command line
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Options {
+    pub command: String,
+    // the file and the other positional arguments of the command
+    pub arguments: Vec<String>,
+    pub ports: Vec<(usize, CPUType)>,
+    pub width: Option<u32>,
+    pub cycle_limit: Option<u64>,
+    pub instruction_limit: Option<u64>,
+    pub time_limit: Option<Duration>,
+    pub verbosity: Verbosity,
+    pub json: bool,
+    pub trace: Option<String>,
+}
+
+/*
+ * Parse the arguments (without the program name), options can be
+ * written as `--port 1=3`, `--port=1=3` or `-p 1=3`
+ */
+pub(crate) fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            match options.command.is_empty() {
+                true => options.command = arg.clone(),
+                false => options.arguments.push(arg.clone()),
+            }
+            continue;
+        }
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || match inline.clone().or_else(|| args.next().cloned()) {
+            Some(value) => Ok(value),
+            None => Err(format!("`{flag}` expects a value")),
+        };
+        match flag {
+            "-p" | "--port" => options.ports.push(parse_port(&value()?)?),
+            "-w" | "--width" => match value()?.parse::<u32>() {
+                Ok(bits) if bits > 0 && bits <= CPUType::BITS => options.width = Some(bits),
+                _ => {
+                    return Err(format!(
+                        "The word width has to be between 1 and {} bits",
+                        CPUType::BITS
+                    ))
+                }
+            },
+            "-l" | "--cycle-limit" => {
+                let limit = value()?;
+                match parse_number(&limit) {
+                    Some(limit) => options.cycle_limit = Some(limit as u64),
+                    None => return Err(format!("Invalid cycle limit `{limit}`")),
+                }
+            }
+            "-s" | "--step-limit" | "-i" | "--instruction-limit" => {
+                let limit = value()?;
+                match parse_number(&limit) {
+                    Some(limit) => options.instruction_limit = Some(limit as u64),
+                    None => return Err(format!("Invalid step limit `{limit}`")),
+                }
+            }
+            "--time-limit" => {
+                let limit = value()?;
+                // negative, NaN and too large values are no duration
+                match limit
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                {
+                    Some(duration) => options.time_limit = Some(duration),
+                    None => return Err(format!("Invalid time limit `{limit}`")),
+                }
+            }
+            "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => options.verbosity = Verbosity::Verbose,
+            "-j" | "--json" => options.json = true,
+            "-t" | "--trace" => options.trace = Some(value()?),
+            "-h" | "--help" => options.command = "help".to_string(),
+            _ => return Err(format!("Unknown option `{arg}`")),
+        }
+    }
+    Ok(options)
+}
+
+/*
+ * `1=3` or `P1=0FFh`
+ */
+fn parse_port(assignment: &str) -> Result<(usize, CPUType), String> {
+    let invalid = || format!("Expected <port>=<value> but found `{assignment}`");
+    let (port, value) = assignment.split_once('=').ok_or_else(invalid)?;
+    let port = port.trim_start_matches(['P', 'p']);
+    match (port.parse::<usize>(), parse_number(value)) {
+        (Ok(port), Some(value)) if port < 8 => Ok((port, value)),
+        (Ok(port), _) if port >= 8 => Err(format!("Port: {port} out of bounds (0 - 7)")),
+        _ => Err(invalid()),
+    }
+}
+
+/*
+ * Run the command line and return the exit code: 0 on success,
+ * 1 if the program had errors and 2 for invalid arguments
+ */
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error}\n\n{USAGE}");
+            return 2;
+        }
+    };
+    set_verbosity(options.verbosity);
+    let mut cpu = match CPU::new() {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            eprintln!("{err}");
+            return 1;
+        }
+    };
+    let file = options.arguments.first().map(|file| file.as_str());
+    match (options.command.as_str(), file) {
+        ("" | "help", _) => {
+            println!("{USAGE}");
+            0
+        }
+        ("asm", Some(path)) => assemble_file(path),
+        ("disasm", Some(path)) => disassemble(&mut cpu, path, &options.arguments[1..]),
+        ("compile", Some(path)) => compile_file(&mut cpu, path),
+        ("run", Some(path)) => run_file(&mut cpu, path, &options),
+        ("repl", _) => {
+            // the lexer would report every line
+            if options.verbosity != Verbosity::Verbose {
+                set_verbosity(Verbosity::Quiet);
+            }
+            match configure(&mut cpu, &options) {
+                Ok(()) => repl::run(Repl::new(cpu)),
+                Err(error) => {
+                    eprintln!("{error}");
+                    2
+                }
+            }
+        }
+        ("check", Some(path)) => check_file(path),
+        ("debug", Some(path)) => {
+            if options.verbosity != Verbosity::Verbose {
+                set_verbosity(Verbosity::Quiet);
+            }
+            if load_program(&mut cpu, path).is_none() {
+                return 1;
+            }
+            match configure(&mut cpu, &options) {
+                Ok(()) => debug::run(Session::new(cpu)),
+                Err(error) => {
+                    eprintln!("{error}");
+                    2
+                }
+            }
+        }
+        ("dump-tokens", Some(path)) => dump_tokens(path),
+        ("dump-ast", Some(path)) => dump_ast(&mut cpu, path),
+        (
+            command @ ("asm" | "disasm" | "compile" | "run" | "check" | "debug" | "dump-tokens"
+            | "dump-ast"),
+            None,
+        ) => {
+            eprintln!("`{command}` expects a file\n\n{USAGE}");
+            2
+        }
+        (command, _) => {
+            eprintln!("Unknown command `{command}`\n\n{USAGE}");
+            2
+        }
+    }
+}
+
+/*
+ * Programs in `.asm` and `.a51` files are written in the 8051 dialect
+ */
+fn dialect_of(path: &str) -> Dialect {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("asm" | "a51") => Dialect::I8051,
+        _ => Dialect::Russembly,
+    }
+}
+
+/*
+ * Load a compiled program (`.rusmc`) or the source of a program
+ */
+fn load_program(cpu: &mut CPU<CPUType>, path: &str) -> Option<()> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("rusmc") => cpu.load_rusmc_file(path),
+        _ => cpu.load_file_as(path, dialect_of(path)),
+    }
+}
+
+fn error_count() -> usize {
+    CPU_ERROR_COUNT.with(|count| *count.borrow()) + LEXER_ERROR_COUNT.with(|count| *count.borrow())
+}
+
+/*
+ * Apply the word width, the initial ports and the limits
+ */
+fn configure(cpu: &mut CPU<CPUType>, options: &Options) -> Result<(), String> {
+    if let Some(bits) = options.width {
+        cpu.set_word_width(bits)?;
+    }
+    for &(port, value) in &options.ports {
+        cpu.port[port] = value & cpu.word_mask;
+    }
+    cpu.set_cycle_limit(options.cycle_limit);
+    cpu.set_instruction_limit(options.instruction_limit);
+    cpu.set_time_limit(options.time_limit);
+    Ok(())
+}
+
+/*
+ * Run a program, an image or a compiled program with the given ports,
+ * word width and limits, a runtime error (also a limit) is a failure
+ */
+fn run_file(cpu: &mut CPU<CPUType>, path: &str, options: &Options) -> i32 {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str());
+    let image = matches!(extension, Some("hex" | "ihx" | "bin"));
+    let loaded = match image {
+        true => cpu.load_image_file(path),
+        false => load_program(cpu, path),
+    };
+    if loaded.is_none() {
+        return 1;
+    }
+    if let Err(error) = configure(cpu, options) {
+        eprintln!("{error}");
+        return 2;
+    }
+    let limited = options.instruction_limit.is_some()
+        || options.cycle_limit.is_some()
+        || options.time_limit.is_some();
+    if image && !limited {
+        cpu.set_instruction_limit(Some(IMAGE_STEP_LIMIT));
+    }
+    if options.trace.is_some() {
+        if image {
+            eprintln!("Only programs can be traced, images have no source lines");
+            return 2;
+        }
+        cpu.start_trace();
+    }
+    match image {
+        true => cpu.run_image(),
+        false => cpu.run_main(),
+    }
+    if let (Some(path), Some(trace)) = (&options.trace, cpu.take_trace()) {
+        if let Err(error) = write_trace(path, &trace) {
+            eprintln!("Unable to write `{path}`: {error}");
+            return 1;
+        }
+    }
+    if options.verbosity == Verbosity::Verbose {
+        println!("{cpu}");
+    }
+    if options.json {
+        println!("{}", cpu.get_json());
+    }
+    // the CPU only reports why it stopped in the normal verbosity
+    if let (Some(error), Verbosity::Quiet) = (&cpu.runtime_error, options.verbosity) {
+        eprintln!("Program stopped: {error}");
+    }
+    match error_count() != 0 || cpu.runtime_error.is_some() {
+        true => 1,
+        false => 0,
+    }
+}
+
+/*
+ * JSON lines for `.jsonl` and `.json` files, text for other files and `-`
+ */
+fn write_trace(path: &str, trace: &Tracer) -> std::io::Result<()> {
+    if path == "-" {
+        print!("{}", trace.to_text());
+        return Ok(());
+    }
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("jsonl" | "json") => std::fs::write(path, trace.to_json_lines()),
+        _ => std::fs::write(path, trace.to_text()),
+    }
+}
+
+/*
+ * Parse and compile a program without running it, the errors the
+ * lexer and the compiler find are reported with their line
+ */
+fn check_file(path: &str) -> i32 {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Unable to read `{path}`: {error}");
+            return 1;
+        }
+    };
+    let diagnostics = match Program::parse_as(&source, dialect_of(path)) {
+        Ok(_) => vec![],
+        Err(diagnostics) => diagnostics,
+    };
+    let lines: Vec<&str> = source.lines().collect();
+    for diagnostic in &diagnostics {
+        match lines.get(diagnostic.line.wrapping_sub(1)) {
+            Some(line) => println!(
+                "line {}: {}\n    {}",
+                diagnostic.line,
+                diagnostic.message,
+                line.trim()
+            ),
+            None => println!("{}", diagnostic.message),
+        }
+        if let Some(syntax) = &diagnostic.syntax {
+            println!("    syntax: {syntax}");
+        }
+    }
+    println!("{path}: {} errors", diagnostics.len());
+    match diagnostics.len() {
+        0 => 0,
+        _ => 1,
+    }
+}
+
+/*
+ * Print every line of the file with its tokens
+ */
+fn dump_tokens(path: &str) -> i32 {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Unable to read `{path}`: {error}");
+            return 1;
+        }
+    };
+    let lines: Vec<String> = match dialect_of(path) {
+        Dialect::I8051 => translate_8051(&source).lines,
+        Dialect::Russembly => source.lines().map(|line| line.to_string()).collect(),
+    };
+    let errors = LEXER_ERROR_COUNT.with(|count| *count.borrow());
+    let mut lexer = Lexer::new();
+    let line_count = lines.len();
+    lines
+        .into_iter()
+        .for_each(|line| lexer.run(line, line_count));
+    for (number, line) in lexer.get_lines().unwrap_or_default().iter().enumerate() {
+        if !line.tokens.is_empty() {
+            println!("{:>4}  {}", number + 1, format_tokens(line));
+        }
+    }
+    // like `check`, a line the lexer rejected is a failure
+    match LEXER_ERROR_COUNT.with(|count| *count.borrow()) - errors {
+        0 => 0,
+        _ => 1,
+    }
+}
+
+/*
+ * Print the functions the lexer builds with their arguments, labels and lines
+ */
+fn dump_ast(cpu: &mut CPU<CPUType>, path: &str) -> i32 {
+    if cpu.load_file_as(path, dialect_of(path)).is_none() {
+        return 1;
+    }
+    for function in &cpu.functions {
+        let arguments: Vec<&str> = function
+            .arguments
+            .iter()
+            .map(|argument| argument.value.as_str())
+            .collect();
+        println!("fn {}({})", function.name, arguments.join(", "));
+        for (number, line) in function.lines.iter().enumerate() {
+            if !line.tokens.is_empty() {
+                println!("  {number:>4}  {}", format_tokens(line));
+            }
+        }
+    }
+    0
+}
+
+fn format_tokens(line: &Line) -> String {
+    line.tokens
+        .iter()
+        .map(|token| format!("{:?} {:?}", token.token_type, token.value))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/*
+ * Assemble a 8051 program into `<file>.hex`, the raw image `<file>.bin`
+ * and the listing `<file>.lst`
+ */
+fn assemble_file(path: &str) -> i32 {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Unable to read `{path}`: {error}");
+            return 1;
+        }
+    };
+    let assembly = assemble(&source);
+    let stem = Path::new(path).with_extension("");
+    let stem = stem.to_string_lossy();
+    let mut code = 0;
+    for (file, result) in [
+        (
+            format!("{stem}.lst"),
+            assembly.write_listing(&format!("{stem}.lst")),
+        ),
+        (
+            format!("{stem}.hex"),
+            assembly.write_hex(&format!("{stem}.hex")),
+        ),
+        (
+            format!("{stem}.bin"),
+            assembly.write_bin(&format!("{stem}.bin")),
+        ),
+    ] {
+        match result {
+            Ok(()) => println!("Wrote {file}"),
+            Err(error) => {
+                eprintln!("Unable to write `{file}`: {error}");
+                code = 1;
+            }
+        }
+    }
+    println!("{} errors", assembly.error_count());
+    match assembly.error_count() {
+        0 => code,
+        _ => 1,
+    }
+}
+
+/*
+ * Print the disassembly of an image, the range defaults to the whole image
+ */
+fn disassemble(cpu: &mut CPU<CPUType>, path: &str, range: &[String]) -> i32 {
+    if range.len() > 2 {
+        eprintln!("`disasm` expects at most a start and an end address\n\n{USAGE}");
+        return 2;
+    }
+    let mut addresses = vec![];
+    for arg in range {
+        match parse_number(arg) {
+            Some(address) => addresses.push(address),
+            None => {
+                eprintln!("Invalid address `{arg}`");
+                return 2;
+            }
+        }
+    }
+    if cpu.load_image_file(path).is_none() {
+        return 1;
+    }
+    let start = addresses.first().copied().unwrap_or(0);
+    let end = addresses.get(1).copied().unwrap_or(cpu.code_end());
+    if start > end {
+        eprintln!("The start address 0x{start:04x} is behind the end 0x{end:04x}");
+        return 2;
+    }
+    print!("{}", cpu.disassemble(start, end));
+    0
+}
+
+/*
+ * Compile a program into `<file>.rusmc`, 8051 programs (`.asm`, `.a51`)
+ * are compiled in the 8051 dialect
+ */
+fn compile_file(cpu: &mut CPU<CPUType>, path: &str) -> i32 {
+    if cpu.load_file_as(path, dialect_of(path)).is_none() {
+        return 1;
+    }
+    let file = Path::new(path).with_extension("rusmc");
+    match cpu.save_rusmc_file(&file.to_string_lossy()) {
+        Ok(()) => {
+            println!("Wrote {}", file.display());
+            0
+        }
+        Err(error) => {
+            eprintln!("Unable to write `{}`: {error}", file.display());
+            1
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::cpu::{
+    main::CPU,
+    sfr::{PSW_AC, PSW_CY, PSW_OV},
+    CPUType,
+};
+
+/*
+ * Byte arithmetic of the 8051 on the accumulator, the flags
+ * CY, AC and OV of the PSW are updated like on the real chip
+ */
+impl CPU<CPUType> {
+    /*
+     * add (addc with `with_carry`)
+     */
+    pub fn add_accumulator(&mut self, value: CPUType, with_carry: bool) {
+        let a = self.accumulator & 0xFF;
+        let b = value & 0xFF;
+        let carry = (with_carry && self.psw_flag(PSW_CY)) as CPUType;
+        let sum = a + b + carry;
+        self.set_psw_flag(PSW_CY, sum > 0xFF);
+        self.set_psw_flag(PSW_AC, (a & 0x0F) + (b & 0x0F) + carry > 0x0F);
+        self.set_psw_flag(PSW_OV, (a ^ sum) & (b ^ sum) & 0x80 != 0);
+        self.accumulator = sum & 0xFF;
+    }
+    /*
+     * subb, the carry is the borrow
+     */
+    pub fn subb_accumulator(&mut self, value: CPUType) {
+        let a = self.accumulator & 0xFF;
+        let b = value & 0xFF;
+        let borrow = self.psw_flag(PSW_CY) as CPUType;
+        let difference = a.wrapping_sub(b + borrow);
+        self.set_psw_flag(PSW_CY, a < b + borrow);
+        self.set_psw_flag(PSW_AC, (a & 0x0F) < (b & 0x0F) + borrow);
+        self.set_psw_flag(PSW_OV, (a ^ b) & (a ^ difference) & 0x80 != 0);
+        self.accumulator = difference & 0xFF;
+    }
+    /*
+     * da A corrects the accumulator after adding two BCD numbers
+     */
+    pub fn decimal_adjust(&mut self) {
+        let mut a = self.accumulator & 0xFF;
+        if a & 0x0F > 9 || self.psw_flag(PSW_AC) {
+            a += 0x06;
+        }
+        if a > 0x9F || self.psw_flag(PSW_CY) {
+            a += 0x60;
+        }
+        if a > 0xFF {
+            self.set_psw_flag(PSW_CY, true);
+        }
+        self.accumulator = a & 0xFF;
+    }
+    /*
+     * rl, rr, rlc and rrc
+     */
+    pub fn rotate_accumulator(&mut self, left: bool, through_carry: bool) {
+        let a = self.accumulator & 0xFF;
+        let carry = self.psw_flag(PSW_CY) as CPUType;
+        self.accumulator = match (left, through_carry) {
+            (true, false) => ((a << 1) | (a >> 7)) & 0xFF,
+            (false, false) => ((a >> 1) | (a << 7)) & 0xFF,
+            (true, true) => {
+                self.set_psw_flag(PSW_CY, a & 0x80 != 0);
+                ((a << 1) | carry) & 0xFF
+            }
+            (false, true) => {
+                self.set_psw_flag(PSW_CY, a & 0x01 != 0);
+                (a >> 1) | (carry << 7)
+            }
+        };
+    }
+    /*
+     * swap A exchanges the nibbles of the accumulator
+     */
+    pub fn swap_nibbles(&mut self) {
+        let a = self.accumulator & 0xFF;
+        self.accumulator = ((a << 4) | (a >> 4)) & 0xFF;
+    }
+    /*
+     * mul AB, the high byte of the product is stored in B
+     */
+    pub fn mul_ab(&mut self) {
+        let product = (self.accumulator & 0xFF) * self.sfr.b as CPUType;
+        self.accumulator = product & 0xFF;
+        self.sfr.b = (product >> 8) as u8;
+        self.set_psw_flag(PSW_CY, false);
+        self.set_psw_flag(PSW_OV, product > 0xFF);
+    }
+    /*
+     * div AB, the remainder is stored in B (OV is set on a division by zero)
+     */
+    pub fn div_ab(&mut self) {
+        let a = self.accumulator & 0xFF;
+        let b = self.sfr.b as CPUType;
+        self.set_psw_flag(PSW_CY, false);
+        self.set_psw_flag(PSW_OV, b == 0);
+        if let (Some(quotient), Some(remainder)) = (a.checked_div(b), a.checked_rem(b)) {
+            self.accumulator = quotient;
+            self.sfr.b = remainder as u8;
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use {
+    crate::cpu::{main::CPU, Budget, CPUType, RuntimeError},
+    std::time::Duration,
+};
+
+/*
+ * The clock is only read every this many instructions
+ */
+pub const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/*
+ * Milliseconds since the Unix epoch, `Instant` is not available in the browser
+ */
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn date_now() -> f64;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> f64 {
+    date_now()
+}
+
+impl CPU<CPUType> {
+    /*
+     * Stop a run once it executed `limit` instructions (lines of a program,
+     * machine instructions of an image), the count starts again with every
+     * run of `main`, an image or a function
+     */
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.instruction_limit = limit;
+    }
+    /*
+     * Stop every run (`run_main`, `run_image`, a call or a resumed
+     * debugger) which takes longer than `limit`
+     */
+    pub fn set_time_limit(&mut self, limit: Option<Duration>) {
+        self.time_limit = limit;
+    }
+    pub fn get_instructions(&self) -> u64 {
+        self.instructions
+    }
+    /*
+     * The wall-clock time a run which starts now has to end
+     */
+    pub fn deadline(&self) -> Option<f64> {
+        self.time_limit
+            .map(|limit| now_ms() + limit.as_secs_f64() * 1000.0)
+    }
+    /*
+     * Count an instruction before it is executed, returns false (and sets
+     * the runtime error) once the budget of the run is exhausted
+     */
+    #[inline]
+    pub fn spend_instruction(&mut self, deadline: Option<f64>) -> bool {
+        // runs without a limit only count
+        if self.instruction_limit.is_none() && deadline.is_none() {
+            self.instructions += 1;
+            return true;
+        }
+        let out_of_time = |deadline: f64| {
+            self.instructions % DEADLINE_CHECK_INTERVAL == 0 && now_ms() >= deadline
+        };
+        let exhausted = if self
+            .instruction_limit
+            .is_some_and(|limit| self.instructions >= limit)
+        {
+            Some(Budget::Instructions)
+        } else if deadline.is_some_and(out_of_time) {
+            Some(Budget::Time)
+        } else {
+            None
+        };
+        match exhausted {
+            Some(budget) => {
+                self.runtime_error = Some(RuntimeError::BudgetExhausted(budget));
+                false
+            }
+            None => {
+                self.instructions += 1;
+                true
+            }
+        }
+    }
+}
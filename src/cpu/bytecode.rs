@@ -0,0 +1,862 @@
+use {
+    crate::{
+        cpu::{clock::line_cycles, CPUType, NumberVar, StringVar, Var},
+        dialect::Dialect,
+        lexer::{Function, Line, Token, TokenType},
+    },
+    serde::{Deserialize, Serialize},
+    std::{iter::Peekable, slice::Iter},
+};
+
+/*
+ * A value operand, ports and variables are already resolved
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Operand {
+    Number(CPUType),
+    Accumulator,
+    Register(usize),
+    Indirect(usize),
+    Address(CPUType),
+    Port(usize),
+    InvalidPort(String),
+    Stack,
+    Sfr(u8),
+    Bit(u8),
+    Dptr,
+    // everything else is the name of a variable
+    Var(usize),
+}
+
+/*
+ * A single bit (`setb P1.3`, `jnb TF0, wait`, ...)
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BitOperand {
+    Bit(u8),
+    // a bit of a wide port which is no SFR (`P5.3`)
+    PortBit(usize, usize),
+    Unknown(String),
+}
+
+/*
+ * A jump location inside the current function
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Target {
+    Line(usize),
+    Missing(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Logic {
+    And,
+    Or,
+    Xor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    Zero,
+    NotZero,
+    Carry,
+    NotCarry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StackOp {
+    Adds,
+    Subs,
+    Muls,
+    Divs,
+    Mods,
+    Cmp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PrintArg {
+    String(String),
+    Number(CPUType),
+    Accumulator,
+    Stack,
+    Port(Option<usize>),
+    // a variable, the source line is needed for the error message
+    Var(usize, String, usize),
+    Missing(String, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Callee {
+    Function(usize),
+    MissingFunction(String),
+    // 8051 subroutines are labels in code memory
+    Label(usize),
+    MissingLabel(String),
+}
+
+/*
+ * An instruction of the bytecode, every source line starts with `Begin`
+ * and ends with `End` like a line of the interpreter did before
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Instruction {
+    // check the cycle limit and poll the interrupts
+    Begin,
+    // count the machine cycles of the line, then return or jump
+    End(usize),
+    // the end of a function
+    Return,
+    Push(Operand),
+    Pop(Option<Operand>),
+    Peek(Option<Operand>),
+    Dup,
+    Swap,
+    SwapNibbles,
+    Over,
+    Rot,
+    Drop,
+    Stack(StackOp),
+    Cjne(Operand, Operand, Target),
+    Mov(Operand, Operand),
+    MovxWrite(Option<usize>),
+    MovxRead(Option<usize>),
+    Djnz(Operand, Target),
+    Jump(Target),
+    JumpIf(Condition, Target),
+    JumpBit(BitOperand, bool, Target),
+    Jbc(BitOperand, Target),
+    Xch(Operand, Operand),
+    Xchd(Operand, Operand),
+    Da,
+    IncDec(Operand, bool),
+    Add(Operand, bool),
+    Subb(Operand),
+    Logic(Logic, Operand, Operand),
+    BitLogic(Logic, BitOperand, BitOperand),
+    Rotate(bool, bool),
+    Mul,
+    Div,
+    ClearA,
+    ComplementA,
+    WriteBit(BitOperand, Option<bool>),
+    Ret,
+    Reti,
+    Print(PrintArg),
+    Call(Callee),
+    // `None` if the value is neither a string nor a number
+    Let(usize, Option<Var<CPUType>>),
+    NewLine,
+    // a diagnostic which was found while compiling, reported when it is reached
+    Fail {
+        count: bool,
+        error: String,
+        syntax: Option<String>,
+    },
+}
+
+/*
+ * A compiled function, `lines` holds the `Begin` of every source line
+ * and `source` the line itself
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompiledFunction {
+    pub name: String,
+    pub start: usize,
+    pub lines: Vec<usize>,
+    pub source: Vec<String>,
+    // index of the first line inside the source file
+    pub first_line: usize,
+    // (name, line) of every jump location
+    pub labels: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Program {
+    pub code: Vec<Instruction>,
+    pub functions: Vec<CompiledFunction>,
+    // the name of every variable slot
+    pub variables: Vec<String>,
+}
+
+impl Program {
+    pub fn function(&self, name: &str) -> Option<&CompiledFunction> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+    /*
+     * Source map: the function and line an instruction was compiled from
+     */
+    pub fn source_line(&self, address: usize) -> Option<(&CompiledFunction, usize)> {
+        let function = self
+            .functions
+            .iter()
+            .rev()
+            .find(|f| f.start <= address && address < self.code.len())?;
+        let line = function.lines.partition_point(|start| *start <= address);
+        Some((function, line.checked_sub(1)?))
+    }
+    /*
+     * The line in the file (1-based) of the instruction at `address`
+     */
+    pub fn file_line(&self, address: usize) -> Option<usize> {
+        let (function, line) = self.source_line(address)?;
+        Some(function.first_line + line + 1)
+    }
+    /*
+     * Check that all addresses, slots and registers are in range, a
+     * program which was not compiled by `compile` could be corrupted
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        if !matches!(self.code.last(), Some(Instruction::Return)) {
+            return Err("the code has to end with a return".to_string());
+        }
+        let address = |address: usize| match address < self.code.len() {
+            true => Ok(()),
+            false => Err(format!("address {address} out of range")),
+        };
+        for function in &self.functions {
+            address(function.start)?;
+            function.lines.iter().try_for_each(|line| address(*line))?;
+            // every line needs its source text for the diagnostics
+            if function.source.len() != function.lines.len() {
+                return Err(format!(
+                    "function `{}` has {} lines but {} source lines",
+                    function.name,
+                    function.lines.len(),
+                    function.source.len()
+                ));
+            }
+            if let Some((name, line)) = function
+                .labels
+                .iter()
+                .find(|(_, line)| *line >= function.lines.len())
+            {
+                return Err(format!("label `{name}` points to line {line} out of range"));
+            }
+        }
+        let operand = |operand: &Operand| match operand {
+            Operand::Register(r) | Operand::Indirect(r) if *r > 7 => {
+                Err(format!("register R{r} out of range"))
+            }
+            Operand::Port(port) if *port > 7 => Err(format!("port P{port} out of range")),
+            Operand::Var(slot) if *slot >= self.variables.len() => {
+                Err(format!("variable slot {slot} out of range"))
+            }
+            _ => Ok(()),
+        };
+        let bit = |bit: &BitOperand| match bit {
+            BitOperand::PortBit(port, bit) if *port > 7 || *bit >= CPUType::BITS as usize => {
+                Err(format!("bit P{port}.{bit} out of range"))
+            }
+            _ => Ok(()),
+        };
+        for instruction in &self.code {
+            if let Some(Target::Line(line)) = target(instruction) {
+                address(*line)?;
+            }
+            match instruction {
+                Instruction::Push(o) | Instruction::Djnz(o, _) | Instruction::IncDec(o, _) => {
+                    operand(o)?
+                }
+                Instruction::Add(o, _) | Instruction::Subb(o) => operand(o)?,
+                Instruction::Pop(Some(o)) | Instruction::Peek(Some(o)) => operand(o)?,
+                Instruction::Cjne(a, b, _)
+                | Instruction::Mov(a, b)
+                | Instruction::Xch(a, b)
+                | Instruction::Xchd(a, b)
+                | Instruction::Logic(_, a, b) => {
+                    operand(a)?;
+                    operand(b)?;
+                }
+                Instruction::MovxWrite(Some(r)) | Instruction::MovxRead(Some(r)) => {
+                    operand(&Operand::Register(*r))?
+                }
+                Instruction::JumpBit(b, _, _) | Instruction::Jbc(b, _) => bit(b)?,
+                Instruction::WriteBit(b, _) => bit(b)?,
+                Instruction::BitLogic(_, a, b) => {
+                    bit(a)?;
+                    bit(b)?;
+                }
+                Instruction::Print(PrintArg::Port(Some(port))) if *port > 7 => {
+                    return Err(format!("port P{port} out of range"));
+                }
+                Instruction::Print(PrintArg::Var(slot, _, _)) | Instruction::Let(slot, _)
+                    if *slot >= self.variables.len() =>
+                {
+                    return Err(format!("variable slot {slot} out of range"));
+                }
+                Instruction::Call(Callee::Function(index)) if *index >= self.functions.len() => {
+                    return Err(format!("function {index} out of range"));
+                }
+                Instruction::Call(Callee::Label(line)) => address(*line)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/*
+ * Compile the functions of the lexer into bytecode. Jump locations, functions,
+ * ports and variables are resolved once instead of on every executed line.
+ */
+pub fn compile(functions: &[Function], dialect: Dialect) -> Program {
+    let mut compiler = Compiler {
+        program: Program::default(),
+        functions,
+        dialect,
+        lines: &[],
+        line_starts: vec![],
+    };
+    for function in functions {
+        compiler.compile_function(function);
+    }
+    compiler.program
+}
+
+struct Compiler<'a> {
+    program: Program,
+    functions: &'a [Function],
+    dialect: Dialect,
+    lines: &'a [Line],
+    // index of the `Begin` of every line of the current function
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, instruction: Instruction) {
+        self.program.code.push(instruction);
+    }
+    fn fail(&mut self, error: &str, syntax: Option<&str>) {
+        self.emit(Instruction::Fail {
+            count: true,
+            error: error.to_string(),
+            syntax: syntax.map(|s| s.to_string()),
+        });
+    }
+
+    fn compile_function(&mut self, function: &'a Function) {
+        self.lines = &function.lines;
+        let start = self.program.code.len();
+        // the jump targets are patched once all lines have their address
+        self.line_starts = vec![];
+        for (i, line) in function.lines.iter().enumerate() {
+            self.line_starts.push(self.program.code.len());
+            self.emit(Instruction::Begin);
+            self.compile_line(line, i);
+            self.emit(Instruction::End(line_cycles(line)));
+        }
+        self.emit(Instruction::Return);
+        let lines = std::mem::take(&mut self.line_starts);
+        for instruction in &mut self.program.code[start..] {
+            if let Some(Target::Line(line)) = target_mut(instruction) {
+                *line = lines[*line];
+            }
+            if let Instruction::Call(Callee::Label(line)) = instruction {
+                *line = lines[*line];
+            }
+        }
+        let labels = function
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                line.tokens
+                    .iter()
+                    .filter_map(move |token| match &token.token_type {
+                        TokenType::JumpLocation(location) => Some((location.name.clone(), i)),
+                        _ => None,
+                    })
+            })
+            .collect();
+        self.program.functions.push(CompiledFunction {
+            name: function.name.clone(),
+            start,
+            lines,
+            source: function
+                .lines
+                .iter()
+                .map(|line| line.as_string.clone())
+                .collect(),
+            first_line: function.line,
+            labels,
+        });
+    }
+
+    /*
+     * Compile the tokens of a line like the interpreter read them
+     */
+    fn compile_line(&mut self, line: &Line, line_number: usize) {
+        let mut token_iter = line.tokens.iter().peekable();
+        while let Some(token) = token_iter.next() {
+            match &token.token_type {
+                TokenType::OpCode => {
+                    self.compile_opcode(&mut token_iter, token, &line.as_string, line_number)
+                }
+                TokenType::Keyword => self.compile_keyword(&mut token_iter, token),
+                TokenType::JumpLocation(_)
+                | TokenType::Bracket
+                | TokenType::String
+                | TokenType::Comment => {}
+                // Prints a new Line
+                TokenType::NewLine => self.emit(Instruction::NewLine),
+                _ => self.emit(Instruction::Fail {
+                    count: false,
+                    error: format!("unexpected token '{}' at line {line_number}", token.value),
+                    syntax: None,
+                }),
+            }
+        }
+    }
+
+    fn compile_keyword(&mut self, token_iter: &mut Peekable<Iter<Token>>, token: &Token) {
+        if token.value != "let" {
+            return;
+        }
+        let name = match token_iter.next() {
+            Some(name) if name.token_type == TokenType::VarName => name,
+            Some(_) => return self.fail("Expected variable name", None),
+            None => return self.fail("Expected Arguments after let", None),
+        };
+        if !matches!(token_iter.next(), Some(t) if t.token_type == TokenType::Comma) {
+            self.fail("Expected Comma", None);
+        }
+        let slot = self.variable(&name.value);
+        let name = name.value.clone();
+        match token_iter.next() {
+            Some(value) => match value.token_type {
+                TokenType::String => self.emit(Instruction::Let(
+                    slot,
+                    Some(Var::String(StringVar {
+                        name,
+                        value: value.value.clone(),
+                    })),
+                )),
+                TokenType::Number(x) => self.emit(Instruction::Let(
+                    slot,
+                    Some(Var::Number(NumberVar { name, value: x })),
+                )),
+                _ => self.emit(Instruction::Let(slot, None)),
+            },
+            None => self.fail("Expected value for let", None),
+        }
+    }
+
+    fn compile_opcode(
+        &mut self,
+        token_iter: &mut Peekable<Iter<Token>>,
+        token: &Token,
+        line: &str,
+        line_number: usize,
+    ) {
+        let opcode = token.value.as_str();
+        match opcode {
+            "push" => match token_iter.next() {
+                Some(nt) if matches!(nt.token_type, TokenType::Stack | TokenType::Comma) => {
+                    self.fail("You can only push Numbers to the Stack!", None)
+                }
+                Some(nt) => {
+                    let operand = self.operand(nt);
+                    self.emit(Instruction::Push(operand));
+                }
+                None => self.fail("Expected Number after push", None),
+            },
+            "pop" | "peek" => {
+                let destination = token_iter.next().map(|t| self.operand(t));
+                match opcode {
+                    "pop" => self.emit(Instruction::Pop(destination)),
+                    _ => self.emit(Instruction::Peek(destination)),
+                }
+            }
+            "dup" => self.emit(Instruction::Dup),
+            "swap" => {
+                if let Some(TokenType::Accumulator) = token_iter.peek().map(|t| &t.token_type) {
+                    token_iter.next();
+                    self.emit(Instruction::SwapNibbles);
+                } else {
+                    self.emit(Instruction::Swap);
+                }
+            }
+            "over" => self.emit(Instruction::Over),
+            "rot" => self.emit(Instruction::Rot),
+            "drop" => self.emit(Instruction::Drop),
+            "cmp" | "adds" | "subs" | "muls" | "divs" | "mods" => {
+                self.emit(Instruction::Stack(match opcode {
+                    "cmp" => StackOp::Cmp,
+                    "adds" => StackOp::Adds,
+                    "subs" => StackOp::Subs,
+                    "muls" => StackOp::Muls,
+                    "divs" => StackOp::Divs,
+                    _ => StackOp::Mods,
+                }))
+            }
+            "cjne" => {
+                if let (Some(left), Some(_), Some(right), Some(_), Some(location)) = (
+                    token_iter.next(),
+                    token_iter.next(),
+                    token_iter.next(),
+                    token_iter.next(),
+                    token_iter.next(),
+                ) {
+                    let (left, right) = (self.operand(left), self.operand(right));
+                    let target = self.target(&location.value);
+                    self.emit(Instruction::Cjne(left, right, target));
+                } else {
+                    self.fail(
+                        "Expected more Tokens after cjne",
+                        Some("cjne <value> <,> <value> <,> <jump location>"),
+                    );
+                }
+            }
+            "mov" => {
+                if let (Some(destination), Some(comma), Some(value)) =
+                    (token_iter.next(), token_iter.next(), token_iter.next())
+                {
+                    if comma.token_type != TokenType::Comma {
+                        self.fail("Expected Comma", None);
+                    }
+                    match destination.token_type {
+                        TokenType::Port
+                        | TokenType::Accumulator
+                        | TokenType::Register(_)
+                        | TokenType::Indirect(_)
+                        | TokenType::Address(_)
+                        | TokenType::Number(_)
+                        | TokenType::Sfr(_)
+                        | TokenType::Bit(_)
+                        | TokenType::Dptr => {
+                            let destination = self.operand(destination);
+                            let value = self.operand(value);
+                            self.emit(Instruction::Mov(destination, value));
+                        }
+                        _ => self.fail("Expected Port or Accu!", None),
+                    }
+                } else {
+                    self.fail(
+                        "Expected more Tokens after mov",
+                        Some("mov <Port or Accu> <,> <value>"),
+                    );
+                }
+            }
+            "movx" => {
+                if let (Some(destination), Some(_), Some(source)) =
+                    (token_iter.next(), token_iter.next(), token_iter.next())
+                {
+                    match (&destination.token_type, &source.token_type) {
+                        (TokenType::Indirect(r), TokenType::Accumulator) => {
+                            self.emit(Instruction::MovxWrite(Some(*r)))
+                        }
+                        (TokenType::Accumulator, TokenType::Indirect(r)) => {
+                            self.emit(Instruction::MovxRead(Some(*r)))
+                        }
+                        (TokenType::IndirectDptr, TokenType::Accumulator) => {
+                            self.emit(Instruction::MovxWrite(None))
+                        }
+                        (TokenType::Accumulator, TokenType::IndirectDptr) => {
+                            self.emit(Instruction::MovxRead(None))
+                        }
+                        _ => self.fail("movx can only move between A and @R0, @R1 or @DPTR", None),
+                    }
+                } else {
+                    self.fail(
+                        "Expected more Tokens after movx",
+                        Some("movx <A or @Ri or @DPTR> <,> <A or @Ri or @DPTR>"),
+                    );
+                }
+            }
+            "djnz" => {
+                if let (Some(operand), Some(_), Some(location)) =
+                    (token_iter.next(), token_iter.next(), token_iter.next())
+                {
+                    let operand = self.operand(operand);
+                    let target = self.target(&location.value);
+                    self.emit(Instruction::Djnz(operand, target));
+                } else {
+                    self.fail(
+                        "Expected more Tokens after djnz",
+                        Some("djnz <register or address> <,> <jump location>"),
+                    );
+                }
+            }
+            "jz" | "jnz" | "jc" | "jnc" => match token_iter.next() {
+                Some(location) => {
+                    let condition = match opcode {
+                        "jz" => Condition::Zero,
+                        "jnz" => Condition::NotZero,
+                        "jc" => Condition::Carry,
+                        _ => Condition::NotCarry,
+                    };
+                    let target = self.target(&location.value);
+                    self.emit(Instruction::JumpIf(condition, target));
+                }
+                None => self.fail(&format!("Expected jump location after {opcode}"), None),
+            },
+            "nop" | "end" => {}
+            "xch" | "xchd" => {
+                if let (Some(accu), Some(_), Some(operand)) =
+                    (token_iter.next(), token_iter.next(), token_iter.next())
+                {
+                    let (accu, operand) = (self.operand(accu), self.operand(operand));
+                    match opcode {
+                        "xch" => self.emit(Instruction::Xch(accu, operand)),
+                        _ => self.emit(Instruction::Xchd(accu, operand)),
+                    }
+                } else {
+                    self.fail(
+                        &format!("Expected more Tokens after {opcode}"),
+                        Some(&format!("{opcode} A <,> <value>")),
+                    );
+                }
+            }
+            "da" => {
+                token_iter.next();
+                self.emit(Instruction::Da);
+            }
+            "inc" | "dec" => match token_iter.next() {
+                Some(operand) => {
+                    let operand = self.operand(operand);
+                    self.emit(Instruction::IncDec(operand, opcode == "inc"));
+                }
+                None => self.fail(&format!("Expected operand after {opcode}"), None),
+            },
+            "add" | "addc" | "subb" => {
+                if let (Some(accu), Some(_), Some(source)) =
+                    (token_iter.next(), token_iter.next(), token_iter.next())
+                {
+                    if accu.token_type != TokenType::Accumulator {
+                        return self.fail(&format!("{opcode} only works on the accumulator"), None);
+                    }
+                    let source = self.operand(source);
+                    match opcode {
+                        "subb" => self.emit(Instruction::Subb(source)),
+                        _ => self.emit(Instruction::Add(source, opcode == "addc")),
+                    }
+                } else {
+                    self.fail(
+                        &format!("Expected more Tokens after {opcode}"),
+                        Some(&format!("{opcode} A <,> <value>")),
+                    );
+                }
+            }
+            "anl" | "orl" | "xrl" => {
+                if let (Some(destination), Some(_), Some(source)) =
+                    (token_iter.next(), token_iter.next(), token_iter.next())
+                {
+                    let logic = match opcode {
+                        "anl" => Logic::And,
+                        "orl" => Logic::Or,
+                        _ => Logic::Xor,
+                    };
+                    if let TokenType::Bit(_) = destination.token_type {
+                        let (destination, source) = (bit_operand(destination), bit_operand(source));
+                        self.emit(Instruction::BitLogic(logic, destination, source));
+                    } else {
+                        let (destination, source) =
+                            (self.operand(destination), self.operand(source));
+                        self.emit(Instruction::Logic(logic, destination, source));
+                    }
+                } else {
+                    self.fail(
+                        &format!("Expected more Tokens after {opcode}"),
+                        Some(&format!("{opcode} <destination> <,> <value>")),
+                    );
+                }
+            }
+            "rl" | "rr" | "rlc" | "rrc" => match token_iter.next().map(|t| &t.token_type) {
+                Some(TokenType::Accumulator) => self.emit(Instruction::Rotate(
+                    opcode.starts_with("rl"),
+                    opcode.ends_with('c'),
+                )),
+                _ => self.fail(&format!("{opcode} only works on the accumulator"), None),
+            },
+            "mul" | "div" => {
+                if token_iter.peek().is_some_and(|t| t.value == "AB") {
+                    token_iter.next();
+                }
+                match opcode {
+                    "mul" => self.emit(Instruction::Mul),
+                    _ => self.emit(Instruction::Div),
+                }
+            }
+            "jmp" | "sjmp" | "ljmp" | "ajmp" => match token_iter.next() {
+                Some(location) => {
+                    let target = self.target(&location.value);
+                    self.emit(Instruction::Jump(target));
+                }
+                None => self.fail(&format!("Expected jump location after {opcode}"), None),
+            },
+            "setb" | "clr" | "cpl" => match token_iter.next() {
+                Some(nt) => match (opcode, &nt.token_type) {
+                    ("clr", TokenType::Accumulator) => self.emit(Instruction::ClearA),
+                    ("cpl", TokenType::Accumulator) => self.emit(Instruction::ComplementA),
+                    _ => {
+                        let value = match opcode {
+                            "setb" => Some(true),
+                            "clr" => Some(false),
+                            _ => None,
+                        };
+                        self.emit(Instruction::WriteBit(bit_operand(nt), value));
+                    }
+                },
+                None => self.fail(
+                    &format!("Expected bit after {opcode}"),
+                    Some(&format!("{opcode} <Port.bit or bit name>")),
+                ),
+            },
+            "jb" | "jnb" | "jbc" => {
+                if let (Some(bit), Some(_), Some(location)) =
+                    (token_iter.next(), token_iter.next(), token_iter.next())
+                {
+                    let target = self.target(&location.value);
+                    match opcode {
+                        "jbc" => self.emit(Instruction::Jbc(bit_operand(bit), target)),
+                        _ => self.emit(Instruction::JumpBit(
+                            bit_operand(bit),
+                            opcode == "jb",
+                            target,
+                        )),
+                    }
+                } else {
+                    self.fail(
+                        &format!("Expected more Tokens after {opcode}"),
+                        Some(&format!("{opcode} <bit> <,> <jump location>")),
+                    );
+                }
+            }
+            "ret" => self.emit(Instruction::Ret),
+            "reti" => self.emit(Instruction::Reti),
+            "prnt" => {
+                let line = line.trim().to_string();
+                let argument = match token_iter.next() {
+                    Some(nt) => match nt.token_type {
+                        TokenType::String => PrintArg::String(nt.value.clone()),
+                        TokenType::Number(x) => PrintArg::Number(x),
+                        TokenType::Accumulator => PrintArg::Accumulator,
+                        TokenType::Stack => PrintArg::Stack,
+                        TokenType::Port => PrintArg::Port(
+                            nt.value[1..].parse().ok().filter(|port: &usize| *port < 8),
+                        ),
+                        _ => PrintArg::Var(self.variable(&nt.value), line, line_number),
+                    },
+                    None => PrintArg::Missing(line, line_number),
+                };
+                self.emit(Instruction::Print(argument));
+            }
+            "call" | "lcall" | "acall" => match token_iter.next() {
+                Some(name) => {
+                    let callee = match self.dialect {
+                        Dialect::Russembly => {
+                            match self.functions.iter().position(|f| f.name == name.value) {
+                                Some(function) => Callee::Function(function),
+                                None => Callee::MissingFunction(name.value.clone()),
+                            }
+                        }
+                        Dialect::I8051 => match find_jump_location(self.lines, &name.value) {
+                            Some(line) => Callee::Label(line),
+                            None => Callee::MissingLabel(name.value.clone()),
+                        },
+                    };
+                    self.emit(Instruction::Call(callee));
+                }
+                None => self.emit(Instruction::Fail {
+                    count: false,
+                    error: "Expected function name after call statement".to_string(),
+                    syntax: None,
+                }),
+            },
+            _ => {}
+        }
+    }
+
+    fn operand(&mut self, token: &Token) -> Operand {
+        match &token.token_type {
+            TokenType::Number(x) => Operand::Number(*x),
+            TokenType::Accumulator => Operand::Accumulator,
+            TokenType::Register(r) => Operand::Register(*r),
+            TokenType::Indirect(r) => Operand::Indirect(*r),
+            TokenType::Address(address) => Operand::Address(*address),
+            TokenType::Port => match token.value[1..].parse::<usize>() {
+                Ok(port) if port < 8 => Operand::Port(port),
+                _ => Operand::InvalidPort(token.value.clone()),
+            },
+            TokenType::Stack => Operand::Stack,
+            TokenType::Sfr(address) => Operand::Sfr(*address),
+            TokenType::Bit(address) => Operand::Bit(*address),
+            TokenType::Dptr => Operand::Dptr,
+            _ => Operand::Var(self.variable(&token.value)),
+        }
+    }
+    /*
+     * Slot of a variable, every name gets exactly one slot
+     */
+    fn variable(&mut self, name: &str) -> usize {
+        match self.program.variables.iter().position(|v| v == name) {
+            Some(slot) => slot,
+            None => {
+                self.program.variables.push(name.to_string());
+                self.program.variables.len() - 1
+            }
+        }
+    }
+    /*
+     * The line of a jump location, patched to its address later
+     */
+    fn target(&self, name: &str) -> Target {
+        match find_jump_location(self.lines, name) {
+            Some(line) => Target::Line(line),
+            None => Target::Missing(name.to_string()),
+        }
+    }
+}
+
+fn bit_operand(token: &Token) -> BitOperand {
+    if let TokenType::Bit(address) = token.token_type {
+        return BitOperand::Bit(address);
+    }
+    let port_bit = token
+        .value
+        .strip_prefix('P')
+        .and_then(|port_bit| port_bit.split_once(['.', '^']))
+        .and_then(|(port, bit)| Some((port.parse::<usize>().ok()?, bit.parse::<usize>().ok()?)));
+    match port_bit {
+        Some((port, bit)) if port < 8 && bit < CPUType::BITS as usize => {
+            BitOperand::PortBit(port, bit)
+        }
+        _ => BitOperand::Unknown(token.value.clone()),
+    }
+}
+
+fn target(instruction: &Instruction) -> Option<&Target> {
+    match instruction {
+        Instruction::Cjne(_, _, target)
+        | Instruction::Djnz(_, target)
+        | Instruction::Jump(target)
+        | Instruction::JumpIf(_, target)
+        | Instruction::JumpBit(_, _, target)
+        | Instruction::Jbc(_, target) => Some(target),
+        _ => None,
+    }
+}
+
+fn target_mut(instruction: &mut Instruction) -> Option<&mut Target> {
+    match instruction {
+        Instruction::Cjne(_, _, target)
+        | Instruction::Djnz(_, target)
+        | Instruction::Jump(target)
+        | Instruction::JumpIf(_, target)
+        | Instruction::JumpBit(_, _, target)
+        | Instruction::Jbc(_, target) => Some(target),
+        _ => None,
+    }
+}
+
+/*
+ * Find the index of the line which declares the given jump location
+ */
+pub fn find_jump_location(lines: &[Line], name: &str) -> Option<usize> {
+    lines.iter().position(|line| {
+        line.tokens.iter().any(|token| match &token.token_type {
+            TokenType::JumpLocation(location) => location.name == name,
+            _ => false,
+        })
+    })
+}
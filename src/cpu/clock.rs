@@ -0,0 +1,129 @@
+use crate::{
+    cpu::{main::CPU, CPUType},
+    lexer::{Line, Token, TokenType},
+};
+
+/*
+ * Reset value of the clock frequency (a 12 MHz crystal)
+ */
+pub const DEFAULT_CLOCK_HZ: u64 = 12_000_000;
+/*
+ * A machine cycle of the 8051 takes 12 oscillator periods
+ */
+pub const CLOCKS_PER_CYCLE: u64 = 12;
+/*
+ * Servicing an interrupt takes as long as a call (LCALL to the vector)
+ */
+pub const INTERRUPT_CYCLES: usize = 2;
+
+/*
+ * Machine cycles an opcode takes, `operands` are the tokens after the opcode.
+ * The costs follow the 8051 instruction set. The stack opcodes which only
+ * exist in russembly cost 2 cycles (a PUSH or POP) per stack access.
+ */
+pub fn opcode_cycles(opcode: &str, operands: &[Token]) -> usize {
+    match opcode {
+        "mov" => mov_cycles(operands),
+        // MUL AB and DIV AB
+        "mul" | "div" => 4,
+        // jumps, calls and returns
+        "jmp" | "sjmp" | "ljmp" | "ajmp" | "call" | "lcall" | "acall" | "ret" | "reti" | "cjne"
+        | "djnz" | "jb" | "jnb" | "jbc" | "jz" | "jnz" | "jc" | "jnc" => 2,
+        // inc DPTR
+        "inc" if matches!(operands.first(), Some(t) if t.token_type == TokenType::Dptr) => 2,
+        "push" | "pop" | "movx" | "dup" | "over" | "drop" => 2,
+        "djnzs" => 4,
+        "adds" | "subs" | "cmp" => 6,
+        "muls" | "divs" | "mods" => 8,
+        // swap A is the 8051 nibble swap
+        "swap" if matches!(operands.first(), Some(t) if t.token_type == TokenType::Accumulator) => {
+            1
+        }
+        "swap" => 8,
+        "rot" => 12,
+        // setb, clr, cpl, add, peek, ...
+        _ => 1,
+    }
+}
+
+/*
+ * MOV takes two cycles if it reads or writes a direct address
+ * together with anything but the accumulator
+ */
+fn mov_cycles(operands: &[Token]) -> usize {
+    let is_direct = |token: &Token| {
+        matches!(
+            token.token_type,
+            TokenType::Port | TokenType::Address(_) | TokenType::Sfr(_)
+        )
+    };
+    let (destination, source) = match (operands.first(), operands.get(2)) {
+        (Some(destination), Some(source)) => (destination, source),
+        _ => return 1,
+    };
+    match (&destination.token_type, &source.token_type) {
+        (TokenType::Dptr, _) => 2,
+        // bits are moved through the carry
+        (TokenType::Bit(_), _) | (_, TokenType::Bit(_)) => 1,
+        (TokenType::Accumulator, _) | (_, TokenType::Accumulator) => 1,
+        // a number as destination is a direct address
+        (TokenType::Number(_), _) => 2,
+        _ if is_direct(destination) => 2,
+        (TokenType::Register(_) | TokenType::Indirect(_), _) if is_direct(source) => 2,
+        _ => 1,
+    }
+}
+
+/*
+ * Machine cycles of a whole line, the sum of every opcode in it with
+ * the operands up to the next opcode (0 if it does not contain one)
+ */
+pub fn line_cycles(line: &Line) -> usize {
+    let opcodes: Vec<usize> = line
+        .tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| token.token_type == TokenType::OpCode)
+        .map(|(i, _)| i)
+        .collect();
+    opcodes
+        .iter()
+        .enumerate()
+        .map(|(n, &i)| {
+            let end = opcodes.get(n + 1).copied().unwrap_or(line.tokens.len());
+            opcode_cycles(&line.tokens[i].value, &line.tokens[i + 1..end])
+        })
+        .sum()
+}
+
+impl CPU<CPUType> {
+    /*
+     * Set the oscillator frequency in Hz used to compute the elapsed time
+     */
+    pub fn set_clock_frequency(&mut self, hz: u64) {
+        self.clock_hz = hz.max(1);
+    }
+    pub fn get_clock_frequency(&self) -> u64 {
+        self.clock_hz
+    }
+    /*
+     * Machine cycles executed since the CPU was created
+     */
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+    /*
+     * Elapsed virtual time in seconds
+     */
+    pub fn elapsed_time(&self) -> f64 {
+        (self.cycles * CLOCKS_PER_CYCLE) as f64 / self.clock_hz as f64
+    }
+    /*
+     * Let `cycles` machine cycles pass, the timers count along
+     */
+    #[inline]
+    pub fn run_cycles(&mut self, cycles: usize) {
+        self.cycles += cycles as u64;
+        self.tick(cycles);
+    }
+}
@@ -0,0 +1,502 @@
+use {
+    crate::{
+        cpu::{
+            bytecode::Instruction,
+            history::History,
+            main::CPU,
+            sfr::{bit_address, sfr_by_name},
+            vm::{Pause, Stop},
+            CPUType, Var,
+        },
+        lexer::parse_number,
+    },
+    std::fmt::{Display, Formatter},
+};
+
+/*
+ * A value a condition looks at: `P1`, `A`, `R3`, `CY`, `[30h]`, a
+ * variable or a number
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(CPUType),
+    Accumulator,
+    Dptr,
+    Register(usize),
+    Port(usize),
+    // a bit of a wide port which is no SFR (`P5.3`)
+    PortBit(usize, usize),
+    Bit(u8),
+    Sfr(u8),
+    // a direct address of the internal RAM (`[30h]`)
+    Address(CPUType),
+    Var(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/*
+ * Condition of a breakpoint, e.g. `P1 == 3 && A > 10`
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare(Value, Compare, Value),
+    // true if the value is not 0
+    Value(Value),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Value {
+    pub fn parse(value: &str) -> Result<Value, String> {
+        let value = value.trim();
+        if let Some(address) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            return match parse_number(address.trim()) {
+                Some(address) => Ok(Value::Address(address)),
+                None => Err(format!("Invalid address `{address}`")),
+            };
+        }
+        if let Some(number) = parse_number(value) {
+            return Ok(Value::Number(number));
+        }
+        let port = |port: &str| port.parse::<usize>().ok().filter(|port| *port < 8);
+        if let Some(register) = value
+            .strip_prefix('R')
+            .and_then(|r| r.parse::<usize>().ok())
+        {
+            if register < 8 {
+                return Ok(Value::Register(register));
+            }
+        }
+        if let Some(name) = value.strip_prefix('P') {
+            if let Some(port) = port(name) {
+                return Ok(Value::Port(port));
+            }
+            if let Some((port, bit)) = name.split_once(['.', '^']) {
+                match (port.parse::<usize>(), bit.parse::<usize>()) {
+                    (Ok(p), Ok(bit)) if p < 8 && bit < CPUType::BITS as usize => {
+                        if let Some(address) = bit_address(value) {
+                            return Ok(Value::Bit(address));
+                        }
+                        return Ok(Value::PortBit(p, bit));
+                    }
+                    _ => return Err(format!("Invalid port bit `{value}`")),
+                }
+            }
+        }
+        match value {
+            "A" | "ACC" => Ok(Value::Accumulator),
+            "DPTR" => Ok(Value::Dptr),
+            _ => {
+                if let Some(address) = bit_address(value) {
+                    return Ok(Value::Bit(address));
+                }
+                if let Some(sfr) = sfr_by_name(value) {
+                    return Ok(Value::Sfr(sfr.address));
+                }
+                let is_name = value.chars().all(|c| c.is_alphanumeric() || c == '_');
+                match !value.is_empty() && is_name {
+                    true => Ok(Value::Var(value.to_string())),
+                    false => Err(format!("Invalid value `{value}`")),
+                }
+            }
+        }
+    }
+}
+
+impl Condition {
+    /*
+     * `||` binds weaker than `&&`, both bind weaker than a comparison
+     */
+    pub fn parse(condition: &str) -> Result<Condition, String> {
+        if let Some((left, right)) = condition.split_once("||") {
+            return Ok(Condition::Or(
+                Box::new(Condition::parse(left)?),
+                Box::new(Condition::parse(right)?),
+            ));
+        }
+        if let Some((left, right)) = condition.split_once("&&") {
+            return Ok(Condition::And(
+                Box::new(Condition::parse(left)?),
+                Box::new(Condition::parse(right)?),
+            ));
+        }
+        // the two character operators first, `<=` contains `<`
+        for (operator, compare) in [
+            ("==", Compare::Equal),
+            ("!=", Compare::NotEqual),
+            ("<=", Compare::LessEqual),
+            (">=", Compare::GreaterEqual),
+            ("<", Compare::Less),
+            (">", Compare::Greater),
+        ] {
+            if let Some((left, right)) = condition.split_once(operator) {
+                return Ok(Condition::Compare(
+                    Value::parse(left)?,
+                    compare,
+                    Value::parse(right)?,
+                ));
+            }
+        }
+        Ok(Condition::Value(Value::parse(condition)?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub id: usize,
+    // the line, label or function as it was written
+    pub location: String,
+    // the `Begin` of the line inside the compiled program
+    pub address: usize,
+    pub condition: Option<(String, Condition)>,
+    pub hits: usize,
+}
+
+/*
+ * Stops once a value changed or once it matches the condition
+ * (e.g. `P2`, `A == 0` or `x > 10`)
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub expression: String,
+    pub value: Value,
+    pub condition: Option<Condition>,
+    // the value and the condition after the last line
+    pub last: Option<CPUType>,
+    pub matched: bool,
+    // an address in the line which triggered it the last time
+    pub changed_at: Option<usize>,
+    pub old: Option<CPUType>,
+    pub hits: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    // a watchpoint which stops the VM at the start of the next line
+    pub triggered: Option<usize>,
+    // breakpoints and watchpoints share their ids
+    next_id: usize,
+    pub history: History,
+}
+
+/*
+ * A line of the source file
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub function: String,
+    // line number inside the file, starting at 1
+    pub line: usize,
+    pub source: String,
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}  {}", self.function, self.line, self.source.trim())
+    }
+}
+
+impl CPU<CPUType> {
+    /*
+     * Current value of a condition operand, `None` for unknown variables
+     */
+    pub fn value(&self, value: &Value) -> Option<CPUType> {
+        match value {
+            Value::Number(number) => Some(*number),
+            Value::Accumulator => Some(self.accumulator),
+            Value::Dptr => Some(self.sfr.dptr as CPUType),
+            Value::Register(r) => Some(self.get_register(*r) as CPUType),
+            Value::Port(port) => Some(self.port[*port]),
+            Value::PortBit(port, bit) => Some((self.port[*port] >> bit) & 1),
+            Value::Bit(address) => self.read_bit_address(*address).map(|bit| bit as CPUType),
+            Value::Sfr(address) => self.read_sfr(*address),
+            Value::Address(address) => self.read_direct(*address).map(|x| x as CPUType),
+            Value::Var(name) => match self.try_get_var(name)? {
+                Var::Number(var) => Some(var.value),
+                Var::String(_) => None,
+            },
+        }
+    }
+    pub fn condition(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Compare(left, compare, right) => {
+                let (a, b) = match (self.value(left), self.value(right)) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => return false,
+                };
+                match compare {
+                    Compare::Equal => a == b,
+                    Compare::NotEqual => a != b,
+                    Compare::Less => a < b,
+                    Compare::LessEqual => a <= b,
+                    Compare::Greater => a > b,
+                    Compare::GreaterEqual => a >= b,
+                }
+            }
+            Condition::Value(value) => self.value(value).is_some_and(|value| value != 0),
+            Condition::And(a, b) => self.condition(a) && self.condition(b),
+            Condition::Or(a, b) => self.condition(a) || self.condition(b),
+        }
+    }
+
+    /*
+     * Address of the line a breakpoint is set on: a line number of the
+     * file, a function (its first line) or a jump location
+     */
+    pub fn resolve_location(&mut self, location: &str) -> Result<usize, String> {
+        let program = self.program();
+        if let Ok(line) = location.parse::<usize>() {
+            return program
+                .functions
+                .iter()
+                .find_map(|f| {
+                    let index = line.checked_sub(f.first_line + 1)?;
+                    f.lines.get(index).copied()
+                })
+                .ok_or(format!("No code at line {line}"));
+        }
+        if let Some(function) = program.function(location) {
+            return match function.lines.first() {
+                Some(&address) => Ok(address),
+                None => Err(format!("The function `{location}` is empty")),
+            };
+        }
+        program
+            .functions
+            .iter()
+            .find_map(|f| {
+                let (_, line) = f.labels.iter().find(|(name, _)| name == location)?;
+                Some(f.lines[*line])
+            })
+            .ok_or(format!("No line, function or jump location `{location}`"))
+    }
+    pub fn add_breakpoint(
+        &mut self,
+        location: &str,
+        condition: Option<&str>,
+    ) -> Result<usize, String> {
+        let address = self.resolve_location(location)?;
+        let condition = match condition {
+            Some(condition) => Some((condition.trim().to_string(), Condition::parse(condition)?)),
+            None => None,
+        };
+        self.debugger.next_id += 1;
+        let id = self.debugger.next_id;
+        self.debugger.breakpoints.push(Breakpoint {
+            id,
+            location: location.to_string(),
+            address,
+            condition,
+            hits: 0,
+        });
+        Ok(id)
+    }
+    /*
+     * Watch a value (`P2`, `P1.3`, `A`, `[30h]`, a variable) for changes or,
+     * with a condition (`A == 0`), until the condition becomes true
+     */
+    pub fn add_watchpoint(&mut self, expression: &str) -> Result<usize, String> {
+        let condition = Condition::parse(expression)?;
+        let value = match &condition {
+            Condition::Value(value) => value.clone(),
+            Condition::Compare(value, _, _) => value.clone(),
+            _ => return Err("A watchpoint can only compare a single value".to_string()),
+        };
+        let condition = match condition {
+            Condition::Value(_) => None,
+            condition => Some(condition),
+        };
+        self.debugger.next_id += 1;
+        let id = self.debugger.next_id;
+        let matched = condition.as_ref().is_some_and(|c| self.condition(c));
+        self.debugger.watchpoints.push(Watchpoint {
+            id,
+            expression: expression.trim().to_string(),
+            last: self.value(&value),
+            value,
+            condition,
+            matched,
+            changed_at: None,
+            old: None,
+            hits: 0,
+        });
+        Ok(id)
+    }
+    /*
+     * Remove a breakpoint or a watchpoint
+     */
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.debugger.breakpoints.len() + self.debugger.watchpoints.len();
+        self.debugger.breakpoints.retain(|b| b.id != id);
+        self.debugger.watchpoints.retain(|w| w.id != id);
+        self.debugger.breakpoints.len() + self.debugger.watchpoints.len() != count
+    }
+    /*
+     * Called by the VM at the end of every line (at `address`) while
+     * there are watchpoints, the first one which triggers stops the VM
+     */
+    pub fn check_watchpoints(&mut self, address: usize) {
+        let mut watchpoints = std::mem::take(&mut self.debugger.watchpoints);
+        for watchpoint in watchpoints.iter_mut() {
+            let value = self.value(&watchpoint.value);
+            let triggered = match &watchpoint.condition {
+                None => value != watchpoint.last,
+                Some(condition) => {
+                    let matched = self.condition(condition);
+                    let became_true = matched && !watchpoint.matched;
+                    watchpoint.matched = matched;
+                    became_true
+                }
+            };
+            if triggered && self.debugger.triggered.is_none() {
+                watchpoint.changed_at = Some(address);
+                watchpoint.old = watchpoint.last;
+                watchpoint.hits += 1;
+                self.debugger.triggered = Some(watchpoint.id);
+            }
+            watchpoint.last = value;
+        }
+        self.debugger.watchpoints = watchpoints;
+    }
+
+    /*
+     * Called by the VM at the start of every line
+     */
+    pub fn pause_at(&mut self, address: usize, pause: Pause) -> Option<Stop> {
+        let position = self.debugger.history.position;
+        if let Pause::Until(end) = pause {
+            return (position >= end).then_some(Stop::Paused);
+        }
+        if self.debugger.history.end.is_some_and(|end| position >= end) {
+            return Some(Stop::Paused);
+        }
+        if let Some(id) = self.debugger.triggered.take() {
+            return Some(Stop::Watchpoint(id));
+        }
+        let hit = self.debugger.breakpoints.iter().position(|b| {
+            b.address == address
+                && match &b.condition {
+                    Some((_, condition)) => self.condition(condition),
+                    None => true,
+                }
+        });
+        if let Some(index) = hit {
+            let breakpoint = &mut self.debugger.breakpoints[index];
+            breakpoint.hits += 1;
+            return Some(Stop::Breakpoint(breakpoint.id));
+        }
+        // stepping skips empty lines, comments and lines with only a label
+        let empty = match &self.program {
+            Some(program) => matches!(program.code.get(address + 1), Some(Instruction::End(_))),
+            None => false,
+        };
+        let depth = self.frames.len();
+        match pause {
+            _ if empty => None,
+            Pause::NextLine => Some(Stop::Paused),
+            Pause::Over(over) if depth <= over => Some(Stop::Paused),
+            Pause::Out(out) if depth < out => Some(Stop::Paused),
+            _ => None,
+        }
+    }
+
+    /*
+     * Start the program paused before its first line
+     */
+    pub fn debug_main(&mut self) -> Stop {
+        self.debugger.history = History::default();
+        if !self.start_main() {
+            return Stop::Finished;
+        }
+        let stop = self.step();
+        self.debugger.history.start = self.debugger.history.position;
+        stop
+    }
+    /*
+     * Run until the next line (step into)
+     */
+    pub fn step(&mut self) -> Stop {
+        self.resume_with(Pause::NextLine)
+    }
+    /*
+     * Run until the next line of this function, calls run through (step over)
+     */
+    pub fn step_over(&mut self) -> Stop {
+        self.resume_with(Pause::Over(self.frames.len()))
+    }
+    /*
+     * Run until the current function returned (step out)
+     */
+    pub fn finish(&mut self) -> Stop {
+        self.resume_with(Pause::Out(self.frames.len()))
+    }
+    /*
+     * Run until a breakpoint or the end of the program
+     */
+    pub fn resume(&mut self) -> Stop {
+        self.resume_with(Pause::Breakpoints)
+    }
+    /*
+     * Like `resume` but pauses after at most `lines` lines, the web UI
+     * runs a program in slices like this to stay responsive
+     */
+    pub fn resume_for(&mut self, lines: usize) -> Stop {
+        self.debugger.history.end = Some(self.debugger.history.position + lines);
+        let stop = self.resume_with(Pause::Breakpoints);
+        self.debugger.history.end = None;
+        stop
+    }
+    fn resume_with(&mut self, pause: Pause) -> Stop {
+        if self.frames.is_empty() {
+            return Stop::Finished;
+        }
+        let program = self.program();
+        self.run_frames(&program, 0, pause)
+    }
+
+    pub fn source_location(&mut self, address: usize) -> Option<SourceLocation> {
+        let program = self.program();
+        let (function, line) = program.source_line(address)?;
+        Some(SourceLocation {
+            function: function.name.clone(),
+            line: function.first_line + line + 1,
+            source: function.source[line].clone(),
+        })
+    }
+    /*
+     * The line the VM is paused at
+     */
+    pub fn location(&mut self) -> Option<SourceLocation> {
+        let pc = self.frames.last()?.pc;
+        self.source_location(pc)
+    }
+    /*
+     * The line of every running function, innermost first
+     */
+    pub fn backtrace(&mut self) -> Vec<SourceLocation> {
+        let count = self.frames.len();
+        (0..count)
+            .rev()
+            .filter_map(|i| {
+                // the callers are behind the instruction which called
+                let pc = match i + 1 == count {
+                    true => self.frames[i].pc,
+                    false => self.frames[i].pc.saturating_sub(1),
+                };
+                self.source_location(pc)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,104 @@
+use {
+    crate::cpu::{bytecode::Instruction, main::CPU, CPUType, Message},
+    serde::Serialize,
+};
+
+/*
+ * An error found before the program runs, `start` and `end` are the
+ * columns (in characters) of the part of the line the error is about
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    // the syntax the instruction expects
+    pub syntax: Option<String>,
+    // the line in the file (1-based), 0 if the error is not about a line
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str, line: usize, source: &str) -> Self {
+        let (start, end) = span(message, source);
+        Diagnostic {
+            message: message.to_string(),
+            syntax: None,
+            line,
+            start,
+            end,
+        }
+    }
+}
+
+/*
+ * The columns of the first token quoted in the message (`x` or 'x') which
+ * appears in the line, the whole line without its indentation otherwise
+ */
+pub fn span(message: &str, source: &str) -> (usize, usize) {
+    let quoted = message.split(['`', '\'']).skip(1).step_by(2);
+    for token in quoted.map(str::trim).filter(|token| !token.is_empty()) {
+        if let Some(byte) = source.find(token) {
+            let start = source[..byte].chars().count();
+            return (start, start + token.chars().count());
+        }
+    }
+    let start = source.chars().take_while(|c| c.is_whitespace()).count();
+    let end = source.trim_end().chars().count();
+    (start, end.max(start))
+}
+
+/*
+ * The errors the lexer reported while it parsed `source`, a syntax
+ * message belongs to the error before it
+ */
+pub fn lexer_diagnostics(messages: &[Message], source: &str) -> Vec<Diagnostic> {
+    let source = source.replace('~', "\n");
+    let lines: Vec<&str> = source.lines().collect();
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    for message in messages {
+        let line = message.line.unwrap_or(0);
+        match message.kind {
+            "error" => diagnostics.push(Diagnostic::new(
+                &message.text,
+                line,
+                lines.get(line.wrapping_sub(1)).unwrap_or(&""),
+            )),
+            "syntax" => {
+                if let Some(last) = diagnostics.last_mut().filter(|last| last.line == line) {
+                    last.syntax = Some(message.text.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    diagnostics
+}
+
+impl CPU<CPUType> {
+    /*
+     * The lines the compiler could not translate, they fail once they run
+     */
+    pub fn compile_diagnostics(&mut self) -> Vec<Diagnostic> {
+        if self.functions.is_empty() {
+            return vec![];
+        }
+        let program = self.program();
+        program
+            .code
+            .iter()
+            .enumerate()
+            .filter_map(|(address, instruction)| match instruction {
+                Instruction::Fail { error, syntax, .. } => {
+                    let (function, line) = program.source_line(address)?;
+                    let source = &function.source[line];
+                    Some(Diagnostic {
+                        syntax: syntax.clone(),
+                        ..Diagnostic::new(error, function.first_line + line + 1, source)
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -7,6 +7,14 @@ impl Display for CPU<CPUType> {
     fn fmt(&self, f: &mut Formatter) -> Result {
         let mut output: String = String::new();
         output.push_str(&format!("Stack:          {:?}\n", self.stack));
+        match self.stack_depth {
+            Some(depth) => output.push_str(&format!(
+                "SP:             0x{:x} (depth {})\n",
+                self.get_sp(),
+                depth
+            )),
+            None => output.push_str(&format!("SP:             0x{:x}\n", self.get_sp())),
+        }
         // Port
         output.push_str(&format!("Port:           {{\n"));
         self.port.iter().enumerate().for_each(|(i, x)| {
@@ -26,6 +34,51 @@ impl Display for CPU<CPUType> {
         output.push_str(&format!("}}\n"));
         // Accu
         output.push_str(&format!("Accumulator:    {:?}\n", self.get_accumulator()));
+        // Interrupts
+        output.push_str(&format!(
+            "Interrupts:     IE: 0x{:02x}, IP: 0x{:02x}, TCON: 0x{:02x}\n",
+            self.interrupts.ie, self.interrupts.ip, self.interrupts.tcon
+        ));
+        // Timers
+        output.push_str(&format!(
+            "Timers:         TMOD: 0x{:02x}, T0: 0x{:02x}{:02x}, T1: 0x{:02x}{:02x}\n",
+            self.timers.tmod, self.timers.th0, self.timers.tl0, self.timers.th1, self.timers.tl1
+        ));
+        // Clock
+        output.push_str(&format!(
+            "Clock:          {} cycles, {:.3} us @ {} Hz\n",
+            self.cycles,
+            self.elapsed_time() * 1_000_000.0,
+            self.clock_hz
+        ));
+        // Registers
+        output.push_str(&format!(
+            "SFR:            PSW: 0x{:02x}, B: 0x{:02x}, DPTR: 0x{:04x}\n",
+            self.read_sfr(0xD0).unwrap_or(0),
+            self.sfr.b,
+            self.sfr.dptr
+        ));
+        output.push_str(&format!(
+            "Registers:      {{ bank {}\n",
+            self.register_bank()
+        ));
+        for r in 0..8 {
+            if r == 0 {
+                output.push_str(&format!("      R{}: 0x{:x}", r, self.get_register(r)));
+            } else {
+                output.push_str(&format!(", R{}: 0x{:x}", r, self.get_register(r)));
+            }
+        }
+        output.push_str("\n}\n");
+        // Internal RAM
+        output.push_str("Internal RAM:   {\n");
+        self.iram.chunks(16).enumerate().for_each(|(i, row)| {
+            output.push_str(&format!("    {:02x}:", i * 16));
+            row.iter()
+                .for_each(|x| output.push_str(&format!(" {:02x}", x)));
+            output.push('\n');
+        });
+        output.push_str("}\n");
         // Vars
         output.push_str(&format!("Vars: {{\n"));
         self.vars.iter().for_each(|x| {
@@ -0,0 +1,197 @@
+use crate::cpu::{
+    debugger::Watchpoint,
+    main::CPU,
+    set_verbosity, verbosity,
+    vm::{Pause, Stop},
+    CPUType, Verbosity, CPU_ERROR_COUNT,
+};
+
+/*
+ * Lines between two checkpoints, the distance doubles whenever
+ * there are more than `MAX_CHECKPOINTS`
+ */
+pub const CHECKPOINT_INTERVAL: usize = 1000;
+pub const MAX_CHECKPOINTS: usize = 256;
+
+/*
+ * Checkpoints of a debugged program for stepping backwards, an earlier
+ * line is reached by restoring the checkpoint before it and replaying
+ * the program up to it
+ */
+#[derive(Debug, Clone)]
+pub struct History {
+    // lines started since `debug_main`
+    pub position: usize,
+    // the position of the first line
+    pub start: usize,
+    // a replay (or `resume_for`) pauses once this many lines started
+    pub end: Option<usize>,
+    pub next_checkpoint: usize,
+    interval: usize,
+    // the program is kept out of the copies of the CPU
+    checkpoints: Vec<(usize, CPU<CPUType>)>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            position: 0,
+            start: 0,
+            end: None,
+            next_checkpoint: 0,
+            interval: CHECKPOINT_INTERVAL,
+            checkpoints: vec![],
+        }
+    }
+}
+
+impl CPU<CPUType> {
+    /*
+     * Called by the VM at the start of a line (at `address`) once
+     * `next_checkpoint` lines started
+     */
+    pub fn save_checkpoint(&mut self, address: usize) {
+        let mut history = std::mem::take(&mut self.debugger.history);
+        let functions = std::mem::take(&mut self.functions);
+        let mut checkpoint = self.clone();
+        checkpoint.paused_at = Some(address);
+        self.functions = functions;
+        history.checkpoints.push((history.position, checkpoint));
+        if history.checkpoints.len() > MAX_CHECKPOINTS {
+            // keep every other checkpoint, the first one always stays
+            let mut index = 0;
+            history.checkpoints.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+            history.interval *= 2;
+        }
+        history.next_checkpoint = history.position + history.interval;
+        self.debugger.history = history;
+    }
+    /*
+     * Go back to a checkpoint, the breakpoints and watchpoints stay
+     */
+    fn restore_checkpoint(&mut self, index: usize) {
+        let (position, checkpoint) = &self.debugger.history.checkpoints[index];
+        let position = *position;
+        let mut checkpoint = checkpoint.clone();
+        std::mem::swap(&mut checkpoint.functions, &mut self.functions);
+        std::mem::swap(&mut checkpoint.debugger, &mut self.debugger);
+        *self = checkpoint;
+        self.debugger.history.position = position;
+        self.debugger.triggered = None;
+        // the watched values of the checkpoint
+        let mut watchpoints = std::mem::take(&mut self.debugger.watchpoints);
+        for watchpoint in watchpoints.iter_mut() {
+            watchpoint.last = self.value(&watchpoint.value);
+            watchpoint.matched = match &watchpoint.condition {
+                Some(condition) => self.condition(condition),
+                None => false,
+            };
+        }
+        self.debugger.watchpoints = watchpoints;
+    }
+    /*
+     * Replay the program up to the state after `position` lines started
+     */
+    fn travel_to(&mut self, position: usize) {
+        let index = self
+            .debugger
+            .history
+            .checkpoints
+            .iter()
+            .rposition(|(start, _)| *start <= position)
+            .unwrap_or(0);
+        self.restore_checkpoint(index);
+        if self.debugger.history.position < position {
+            let program = self.program();
+            self.run_frames(&program, 0, Pause::Until(position));
+        }
+    }
+    /*
+     * Replay the program from the checkpoints before the current line and
+     * find the last place where `pause` stopped it before this line
+     */
+    fn last_stop(&mut self, pause: Pause) -> Option<(usize, Stop, Vec<Watchpoint>)> {
+        let current = self.debugger.history.position;
+        let mut index = self
+            .debugger
+            .history
+            .checkpoints
+            .iter()
+            .rposition(|(start, _)| *start < current)?;
+        self.debugger.history.end = Some(current);
+        let program = self.program();
+        let found = loop {
+            self.restore_checkpoint(index);
+            let mut last = None;
+            loop {
+                let stop = self.run_frames(&program, 0, pause);
+                if stop == Stop::Finished || self.debugger.history.position >= current {
+                    break;
+                }
+                let watchpoints = self.debugger.watchpoints.clone();
+                last = Some((self.debugger.history.position, stop, watchpoints));
+            }
+            if last.is_some() || index == 0 {
+                break last;
+            }
+            index -= 1;
+        };
+        self.debugger.history.end = None;
+        found
+    }
+    /*
+     * Replay without printing and without counting the errors again,
+     * the hits of the breakpoints and watchpoints are kept
+     */
+    fn replay(&mut self, pause: Pause) -> Stop {
+        if self.debugger.history.checkpoints.is_empty() {
+            return Stop::Start;
+        }
+        let output = verbosity();
+        let errors = CPU_ERROR_COUNT.with(|count| *count.borrow());
+        let hits: Vec<usize> = self.debugger.breakpoints.iter().map(|b| b.hits).collect();
+        let watch_hits: Vec<usize> = self.debugger.watchpoints.iter().map(|w| w.hits).collect();
+        set_verbosity(Verbosity::Silent);
+
+        let stop = match self.last_stop(pause) {
+            Some((position, stop, watchpoints)) => {
+                self.travel_to(position);
+                self.debugger.watchpoints = watchpoints;
+                stop
+            }
+            None => {
+                let start = self.debugger.history.start;
+                self.travel_to(start);
+                Stop::Start
+            }
+        };
+
+        set_verbosity(output);
+        CPU_ERROR_COUNT.with(|count| *count.borrow_mut() = errors);
+        for (breakpoint, hits) in self.debugger.breakpoints.iter_mut().zip(hits) {
+            breakpoint.hits = hits;
+        }
+        for (watchpoint, hits) in self.debugger.watchpoints.iter_mut().zip(watch_hits) {
+            watchpoint.hits = hits;
+        }
+        stop
+    }
+    /*
+     * Go back to the previous line
+     */
+    pub fn step_back(&mut self) -> Stop {
+        match self.replay(Pause::NextLine) {
+            Stop::Start => Stop::Start,
+            _ => Stop::Paused,
+        }
+    }
+    /*
+     * Run backwards until the last breakpoint or watchpoint before this line
+     */
+    pub fn reverse_continue(&mut self) -> Stop {
+        self.replay(Pause::Breakpoints)
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    cpu::{
+        clock::INTERRUPT_CYCLES, cpu_error, main::CPU, printx, vm::FrameKind, CPUType, PrintT, Var,
+    },
+    dialect::Dialect,
+    log,
+};
+use serde::{Deserialize, Serialize};
+
+/*
+ * Interrupt sources of the 8051 in their natural priority order
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Interrupt {
+    External0,
+    Timer0,
+    External1,
+    Timer1,
+    Serial,
+}
+
+impl Interrupt {
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::External0,
+        Interrupt::Timer0,
+        Interrupt::External1,
+        Interrupt::Timer1,
+        Interrupt::Serial,
+    ];
+    /*
+     * Address of the interrupt vector in code memory
+     */
+    pub fn vector(&self) -> usize {
+        match self {
+            Interrupt::External0 => 0x03,
+            Interrupt::Timer0 => 0x0B,
+            Interrupt::External1 => 0x13,
+            Interrupt::Timer1 => 0x1B,
+            Interrupt::Serial => 0x23,
+        }
+    }
+    /*
+     * The bit inside IE and IP which belongs to this interrupt
+     */
+    pub fn bit(&self) -> u8 {
+        match self {
+            Interrupt::External0 => 0,
+            Interrupt::Timer0 => 1,
+            Interrupt::External1 => 2,
+            Interrupt::Timer1 => 3,
+            Interrupt::Serial => 4,
+        }
+    }
+    /*
+     * Name of the function which handles this interrupt by default
+     */
+    pub fn handler_name(&self) -> &'static str {
+        match self {
+            Interrupt::External0 => "int0",
+            Interrupt::Timer0 => "timer0",
+            Interrupt::External1 => "int1",
+            Interrupt::Timer1 => "timer1",
+            Interrupt::Serial => "serial",
+        }
+    }
+}
+
+/*
+ * Bits of the IE (interrupt enable) register
+ */
+pub const IE_EA: u8 = 7;
+/*
+ * Bits of the TCON register
+ */
+pub const TCON_TF1: u8 = 7;
+pub const TCON_TR1: u8 = 6;
+pub const TCON_TF0: u8 = 5;
+pub const TCON_TR0: u8 = 4;
+pub const TCON_IE1: u8 = 3;
+pub const TCON_IT1: u8 = 2;
+pub const TCON_IE0: u8 = 1;
+pub const TCON_IT0: u8 = 0;
+
+/*
+ * Receive and transmit interrupt flags inside SCON
+ */
+pub const SCON_RI: u8 = 0;
+pub const SCON_TI: u8 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InterruptController {
+    pub ie: u8,
+    pub ip: u8,
+    pub tcon: u8,
+    pub scon: u8,
+    // interrupts which are currently being handled (innermost last)
+    pub in_service: Vec<Interrupt>,
+    // functions bound with `set_interrupt_handler`
+    pub handlers: Vec<(usize, String)>,
+    // after reti at least one more instruction is executed before
+    // the next interrupt is serviced
+    pub returned: bool,
+}
+
+impl InterruptController {
+    pub fn is_requested(&self, interrupt: Interrupt) -> bool {
+        match interrupt {
+            Interrupt::External0 => self.tcon & (1 << TCON_IE0) != 0,
+            Interrupt::Timer0 => self.tcon & (1 << TCON_TF0) != 0,
+            Interrupt::External1 => self.tcon & (1 << TCON_IE1) != 0,
+            Interrupt::Timer1 => self.tcon & (1 << TCON_TF1) != 0,
+            Interrupt::Serial => self.scon & ((1 << SCON_RI) | (1 << SCON_TI)) != 0,
+        }
+    }
+    pub fn set_request(&mut self, interrupt: Interrupt, requested: bool) {
+        let bit = match interrupt {
+            Interrupt::External0 => TCON_IE0,
+            Interrupt::Timer0 => TCON_TF0,
+            Interrupt::External1 => TCON_IE1,
+            Interrupt::Timer1 => TCON_TF1,
+            Interrupt::Serial => {
+                if requested {
+                    self.scon |= 1 << SCON_RI;
+                } else {
+                    self.scon &= !((1 << SCON_RI) | (1 << SCON_TI));
+                }
+                return;
+            }
+        };
+        if requested {
+            self.tcon |= 1 << bit;
+        } else {
+            self.tcon &= !(1 << bit);
+        }
+    }
+    pub fn priority(&self, interrupt: Interrupt) -> u8 {
+        (self.ip >> interrupt.bit()) & 1
+    }
+    /*
+     * Find the interrupt which should be serviced next. An interrupt can
+     * only interrupt a handler of a lower priority level.
+     */
+    pub fn next_interrupt(&self) -> Option<Interrupt> {
+        if self.ie & (1 << IE_EA) == 0 {
+            return None;
+        }
+        let current_priority = self.in_service.last().map(|i| self.priority(*i));
+        let mut next: Option<Interrupt> = None;
+        for interrupt in Interrupt::ALL {
+            let enabled = self.ie & (1 << interrupt.bit()) != 0;
+            if !enabled || !self.is_requested(interrupt) {
+                continue;
+            }
+            let priority = self.priority(interrupt);
+            if let Some(current) = current_priority {
+                if priority <= current {
+                    continue;
+                }
+            }
+            match next {
+                Some(n) if self.priority(n) >= priority => {}
+                _ => next = Some(interrupt),
+            }
+        }
+        next
+    }
+}
+
+impl CPU<CPUType> {
+    /*
+     * Host API: request an interrupt (e.g. a button press on INT0)
+     */
+    pub fn raise_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupts.set_request(interrupt, true);
+    }
+    /*
+     * Host API: handle an interrupt with the given function instead
+     * of the default handler (`int0`, `timer0`, `int1`, `timer1`, `serial`)
+     */
+    pub fn set_interrupt_handler(&mut self, interrupt: Interrupt, function: &str) {
+        let vector = interrupt.vector();
+        self.interrupts.handlers.retain(|(v, _)| *v != vector);
+        self.interrupts
+            .handlers
+            .push((vector, function.to_string()));
+    }
+    pub fn get_interrupt_handler(&self, interrupt: Interrupt) -> String {
+        match self
+            .interrupts
+            .handlers
+            .iter()
+            .find(|(vector, _)| *vector == interrupt.vector())
+        {
+            Some((_, function)) => function.clone(),
+            None => interrupt.handler_name().to_string(),
+        }
+    }
+    /*
+     * Called before every instruction, runs the handler of the
+     * highest priority pending interrupt
+     */
+    pub fn poll_interrupts(&mut self) {
+        if self.interrupts.returned || self.interrupts.ie & (1 << IE_EA) == 0 {
+            return;
+        }
+        let interrupt = match self.interrupts.next_interrupt() {
+            Some(interrupt) => interrupt,
+            None => return,
+        };
+        // the hardware clears the request flag when the interrupt is vectored,
+        // RI and TI have to be cleared by the handler
+        if interrupt != Interrupt::Serial {
+            self.interrupts.set_request(interrupt, false);
+        }
+        let handler = self.get_interrupt_handler(interrupt);
+        // 8051 programs have their handler at the vector address (`ORG 03h`)
+        let vector_line = self.origin_line(interrupt.vector());
+        let found = match self.dialect {
+            Dialect::Russembly => self.program().function(&handler).is_some(),
+            Dialect::I8051 => vector_line.is_some(),
+        };
+        if !found {
+            cpu_error();
+            let vector = interrupt.vector();
+            match self.dialect {
+                Dialect::Russembly => {
+                    log!(
+                        Error,
+                        f("No handler `{handler}` for the interrupt at vector 0x{vector:02x}")
+                    );
+                }
+                Dialect::I8051 => {
+                    log!(
+                        Error,
+                        &format!("No code at the interrupt vector 0x{vector:02x}")
+                    );
+                }
+            }
+            return;
+        }
+        // vectoring to the handler takes as long as a call
+        self.run_cycles(INTERRUPT_CYCLES);
+        self.interrupts.in_service.push(interrupt);
+        let depth = self.interrupts.in_service.len();
+        // the handler must not touch the variables of the interrupted code
+        let (vars, var_slots) = (self.vars.clone(), self.var_slots.clone());
+        let program = self.program();
+        let (start, function) = match vector_line {
+            Some(line) if self.dialect == Dialect::I8051 => (
+                program
+                    .function("main")
+                    .and_then(|f| f.lines.get(line).copied()),
+                false,
+            ),
+            _ => (program.function(&handler).map(|f| f.start), true),
+        };
+        // the VM runs the handler before it continues the interrupted line
+        let kind = FrameKind::Interrupt {
+            function,
+            handler: handler.clone(),
+            depth,
+            vars: vars.clone(),
+            var_slots: var_slots.clone(),
+        };
+        if !start.is_some_and(|start| self.push_call(start, kind)) {
+            self.returned_from_interrupt(&handler, depth, vars, var_slots);
+        }
+    }
+    /*
+     * Called once the handler of an interrupt returned
+     */
+    pub fn returned_from_interrupt(
+        &mut self,
+        handler: &str,
+        depth: usize,
+        vars: Vec<Var<CPUType>>,
+        var_slots: Vec<Option<usize>>,
+    ) {
+        (self.vars, self.var_slots) = (vars, var_slots);
+        if self.interrupts.in_service.len() == depth {
+            cpu_error();
+            log!(
+                Error,
+                f("Interrupt handler `{handler}` returned without reti")
+            );
+            self.interrupts.in_service.pop();
+        }
+        self.interrupts.returned = true;
+    }
+    /*
+     * Return from an interrupt handler
+     */
+    pub fn reti(&mut self) {
+        if self.interrupts.in_service.pop().is_none() {
+            cpu_error();
+            log!(Error, "reti outside of an interrupt handler");
+        }
+        self.returning = true;
+    }
+}
@@ -0,0 +1,475 @@
+use crate::{
+    asm::{
+        disasm::{disassemble, format_disassembly},
+        hex::read_intel_hex,
+        opcodes::{decode, Opcode, Operand},
+        CODE_SIZE,
+    },
+    cpu::{
+        clock::INTERRUPT_CYCLES, cpu_error, interrupt::Interrupt, main::CPU, printx, sfr::PSW_CY,
+        verbosity, CPUType, PrintT, RuntimeError, Verbosity,
+    },
+    log,
+};
+
+/*
+ * Execution of machine code (Intel HEX or binary images) in the code memory.
+ * The return addresses live in the internal RAM like on the real chip.
+ */
+impl CPU<CPUType> {
+    /*
+     * Load an Intel HEX image into the code memory
+     */
+    pub fn load_hex(&mut self, hex: &str) -> Option<()> {
+        let segments = match read_intel_hex(hex) {
+            Ok(segments) => segments,
+            Err(error) => {
+                cpu_error();
+                log!(Error, f("Invalid Intel HEX file: {error}"));
+                return None;
+            }
+        };
+        let mut code = vec![0xFF; CODE_SIZE];
+        for (address, bytes) in segments {
+            if address + bytes.len() > CODE_SIZE {
+                cpu_error();
+                log!(
+                    Error,
+                    &format!("The record at 0x{address:04x} does not fit into the code memory")
+                );
+                return None;
+            }
+            code[address..address + bytes.len()].copy_from_slice(&bytes);
+        }
+        self.load_code(code);
+        Some(())
+    }
+    /*
+     * Load a raw binary image starting at address 0
+     */
+    pub fn load_binary(&mut self, image: &[u8]) -> Option<()> {
+        if image.len() > CODE_SIZE {
+            cpu_error();
+            log!(
+                Error,
+                f("The image ({} bytes) does not fit into 64K", image.len())
+            );
+            return None;
+        }
+        let mut code = vec![0xFF; CODE_SIZE];
+        code[..image.len()].copy_from_slice(image);
+        self.load_code(code);
+        Some(())
+    }
+    /*
+     * Load `.hex`/`.ihx` files as Intel HEX and everything else as binary
+     */
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_image_file(&mut self, path: &str) -> Option<()> {
+        let is_hex = [".hex", ".ihx"]
+            .iter()
+            .any(|extension| path.to_lowercase().ends_with(extension));
+        let result = match is_hex {
+            true => std::fs::read_to_string(path).map(|hex| self.load_hex(&hex)),
+            false => std::fs::read(path).map(|image| self.load_binary(&image)),
+        };
+        match result {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                cpu_error();
+                log!(Error, f("Unable to read `{path}`: {error}"));
+                None
+            }
+        }
+    }
+    fn load_code(&mut self, code: Vec<u8>) {
+        self.code_memory = code;
+        self.pc = 0;
+    }
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+    /*
+     * End of the loaded image, the erased memory (FFh) behind it is not counted
+     */
+    pub fn code_end(&self) -> usize {
+        match self.code_memory.iter().rposition(|byte| *byte != 0xFF) {
+            Some(position) => position + 1,
+            None => 0,
+        }
+    }
+    /*
+     * Listing of the code memory from `start` up to (excluding) `end`
+     */
+    pub fn disassemble(&self, start: usize, end: usize) -> String {
+        format_disassembly(&disassemble(&self.code_memory, start, end))
+    }
+    fn fetch(&mut self) -> u8 {
+        let byte = self.read_code(self.pc as usize);
+        self.pc = self.pc.wrapping_add(1);
+        byte
+    }
+    fn read_code(&self, address: usize) -> u8 {
+        self.code_memory.get(address).copied().unwrap_or(0xFF)
+    }
+
+    /*
+     * Run the image from the current PC until a runtime error (e.g. the cycle
+     * limit) stops it or the program hangs in a jump to itself without interrupts
+     */
+    pub fn run_image(&mut self) {
+        self.runtime_error = None;
+        self.instructions = 0;
+        let framed = verbosity() > Verbosity::Quiet;
+        if framed {
+            log!(Clear, "\nOutput:\n");
+            log!(Clear, "-------------------------\n");
+        }
+        if self.code_memory.is_empty() {
+            cpu_error();
+            log!(Error, "No image loaded");
+        }
+        let deadline = self.deadline();
+        while !self.code_memory.is_empty() && self.runtime_error.is_none() {
+            if !self.spend_instruction(deadline) {
+                break;
+            }
+            let pc = self.pc;
+            self.step_instruction();
+            if self.pc == pc && self.interrupts.next_interrupt().is_none() && !self.ea() {
+                break;
+            }
+        }
+        if framed {
+            log!(Clear, "-------------------------\n");
+        }
+        if let Some(error) = &self.runtime_error {
+            log!(Cpu, f("Program stopped: {error}"));
+        }
+    }
+    #[inline]
+    pub fn ea(&self) -> bool {
+        self.interrupts.ie & 0x80 != 0
+    }
+
+    /*
+     * Execute one machine instruction or vector to a pending interrupt
+     */
+    pub fn step_instruction(&mut self) {
+        if self.cycle_limit.is_some_and(|limit| self.cycles >= limit) {
+            self.runtime_error = Some(RuntimeError::CycleLimit);
+            return;
+        }
+        // an instruction is executed after reti before the next interrupt
+        if !self.interrupts.returned {
+            if let Some(interrupt) = self.interrupts.next_interrupt() {
+                self.vector_interrupt(interrupt);
+                return;
+            }
+        }
+        let address = self.pc;
+        let opcode = self.fetch();
+        let opcode = match decode(opcode) {
+            Some(opcode) => opcode,
+            None => {
+                cpu_error();
+                log!(
+                    Error,
+                    &format!("Invalid opcode 0x{opcode:02X} at 0x{address:04x}")
+                );
+                self.runtime_error = Some(RuntimeError::InvalidOpcode);
+                return;
+            }
+        };
+        let arguments = self.fetch_arguments(&opcode);
+        self.interrupts.returned = false;
+        self.execute(&opcode, &arguments);
+        self.run_cycles(opcode.cycles);
+    }
+    /*
+     * The hardware pushes the PC and jumps to the vector (an LCALL)
+     */
+    fn vector_interrupt(&mut self, interrupt: Interrupt) {
+        // RI and TI have to be cleared by the handler
+        if interrupt != Interrupt::Serial {
+            self.interrupts.set_request(interrupt, false);
+        }
+        self.push_pc();
+        self.interrupts.in_service.push(interrupt);
+        self.pc = interrupt.vector() as u16;
+        self.run_cycles(INTERRUPT_CYCLES);
+    }
+
+    /*
+     * Read the operand bytes, relative and 11 bit addresses are
+     * resolved to absolute jump targets
+     */
+    fn fetch_arguments(&mut self, opcode: &Opcode) -> Vec<CPUType> {
+        let mut arguments = vec![];
+        for operand in &opcode.operands {
+            let argument = match operand.size() {
+                0 => 0,
+                1 => self.fetch() as CPUType,
+                _ => (self.fetch() as CPUType) << 8 | self.fetch() as CPUType,
+            };
+            arguments.push(argument);
+        }
+        for (operand, argument) in opcode.operands.iter().zip(arguments.iter_mut()) {
+            match operand {
+                Operand::Rel => {
+                    *argument = self.pc.wrapping_add(*argument as u8 as i8 as u16) as CPUType
+                }
+                Operand::Addr11 => {
+                    *argument |=
+                        (self.pc as CPUType & 0xF800) | ((opcode.opcode as CPUType >> 5) << 8)
+                }
+                _ => {}
+            }
+        }
+        // mov direct, direct stores the source address first
+        if opcode.opcode == 0x85 {
+            arguments.swap(0, 1);
+        }
+        arguments
+    }
+
+    /*
+     * Value of a byte operand
+     */
+    fn load(&mut self, operand: Operand, argument: CPUType) -> CPUType {
+        let value = match operand {
+            Operand::A => Some(self.accumulator as u8),
+            Operand::Register(n) => Some(self.get_register(n as usize)),
+            Operand::Indirect(n) => self.read_indirect(n as usize),
+            Operand::Immediate => Some(argument as u8),
+            Operand::Direct => self.read_direct(argument),
+            _ => None,
+        };
+        value.unwrap_or(0) as CPUType
+    }
+    fn store(&mut self, operand: Operand, argument: CPUType, value: CPUType) {
+        let value = value & 0xFF;
+        match operand {
+            Operand::A => self.accumulator = value,
+            Operand::Register(n) => self.set_register(n as usize, value),
+            Operand::Indirect(n) => self.write_indirect(n as usize, value),
+            Operand::Direct => self.write_direct(argument, value),
+            _ => {}
+        }
+    }
+    /*
+     * Value of a bit operand (C, bit or /bit)
+     */
+    fn load_bit(&self, operand: Operand, argument: CPUType) -> bool {
+        match operand {
+            Operand::C => self.psw_flag(PSW_CY),
+            Operand::Bit => self.read_bit_address(argument as u8).unwrap_or(false),
+            Operand::NotBit => !self.read_bit_address(argument as u8).unwrap_or(false),
+            _ => false,
+        }
+    }
+    fn store_bit(&mut self, operand: Operand, argument: CPUType, value: bool) {
+        match operand {
+            Operand::C => self.set_psw_flag(PSW_CY, value),
+            _ => self.write_bit_address(argument as u8, value),
+        }
+    }
+
+    /*
+     * The stack grows upwards in the internal RAM, SP points to the last byte
+     */
+    fn push_byte(&mut self, value: CPUType) {
+        let sp = self.get_sp() + 1;
+        if sp >= self.iram.len() {
+            cpu_error();
+            log!(
+                Error,
+                f("Stack overflow: SP 0x{sp:x} is outside of the internal RAM")
+            );
+            self.runtime_error = Some(RuntimeError::StackOverflow);
+            return;
+        }
+        self.stack_base += 1;
+        self.write_iram(sp, value);
+    }
+    fn pop_byte(&mut self) -> CPUType {
+        let sp = self.get_sp();
+        if sp == 0 {
+            cpu_error();
+            log!(Error, "Stack underflow: SP is 0");
+            self.runtime_error = Some(RuntimeError::StackUnderflow);
+            return 0;
+        }
+        self.stack_base -= 1;
+        self.read_iram(sp).unwrap_or(0) as CPUType
+    }
+    fn push_pc(&mut self) {
+        self.push_byte(self.pc as CPUType & 0xFF);
+        self.push_byte(self.pc as CPUType >> 8);
+    }
+    fn pop_pc(&mut self) {
+        let high = self.pop_byte();
+        let low = self.pop_byte();
+        self.pc = (high << 8 | low) as u16;
+    }
+    fn jump_if(&mut self, condition: bool, target: CPUType) {
+        if condition {
+            self.pc = target as u16;
+        }
+    }
+
+    fn execute(&mut self, opcode: &Opcode, arguments: &[CPUType]) {
+        let operands = &opcode.operands;
+        let operand = |i: usize| operands.get(i).copied().unwrap_or(Operand::A);
+        let argument = |i: usize| arguments.get(i).copied().unwrap_or(0);
+        match opcode.mnemonic {
+            "nop" => {}
+            "ajmp" | "ljmp" | "sjmp" => self.pc = argument(0) as u16,
+            // jmp @A+DPTR
+            "jmp" => self.pc = self.sfr.dptr.wrapping_add(self.accumulator as u16),
+            "acall" | "lcall" => {
+                self.push_pc();
+                self.pc = argument(0) as u16;
+            }
+            "ret" => self.pop_pc(),
+            "reti" => {
+                self.pop_pc();
+                if self.interrupts.in_service.pop().is_none() {
+                    cpu_error();
+                    log!(Error, "reti outside of an interrupt handler");
+                }
+                self.interrupts.returned = true;
+            }
+            "inc" if operand(0) == Operand::Dptr => {
+                self.sfr.dptr = self.sfr.dptr.wrapping_add(1);
+            }
+            "inc" => {
+                let value = self.load(operand(0), argument(0));
+                self.store(operand(0), argument(0), value + 1);
+            }
+            "dec" => {
+                let value = self.load(operand(0), argument(0));
+                self.store(operand(0), argument(0), value.wrapping_sub(1));
+            }
+            "jb" | "jnb" | "jbc" => {
+                let bit = self.load_bit(Operand::Bit, argument(0));
+                if opcode.mnemonic == "jbc" && bit {
+                    self.store_bit(Operand::Bit, argument(0), false);
+                }
+                self.jump_if(bit == (opcode.mnemonic != "jnb"), argument(1));
+            }
+            "jc" => self.jump_if(self.psw_flag(PSW_CY), argument(0)),
+            "jnc" => self.jump_if(!self.psw_flag(PSW_CY), argument(0)),
+            "jz" => self.jump_if(self.accumulator & 0xFF == 0, argument(0)),
+            "jnz" => self.jump_if(self.accumulator & 0xFF != 0, argument(0)),
+            "add" | "addc" => {
+                let value = self.load(operand(1), argument(1));
+                self.add_accumulator(value, opcode.mnemonic == "addc");
+            }
+            "subb" => {
+                let value = self.load(operand(1), argument(1));
+                self.subb_accumulator(value);
+            }
+            "rl" => self.rotate_accumulator(true, false),
+            "rr" => self.rotate_accumulator(false, false),
+            "rlc" => self.rotate_accumulator(true, true),
+            "rrc" => self.rotate_accumulator(false, true),
+            "anl" | "orl" if operand(0) == Operand::C => {
+                let bit = self.load_bit(operand(1), argument(1));
+                let carry = self.psw_flag(PSW_CY);
+                let value = match opcode.mnemonic {
+                    "anl" => carry && bit,
+                    _ => carry || bit,
+                };
+                self.set_psw_flag(PSW_CY, value);
+            }
+            "anl" | "orl" | "xrl" => {
+                let a = self.load(operand(0), argument(0));
+                let b = self.load(operand(1), argument(1));
+                let value = match opcode.mnemonic {
+                    "anl" => a & b,
+                    "orl" => a | b,
+                    _ => a ^ b,
+                };
+                self.store(operand(0), argument(0), value);
+            }
+            "mov" => match (operand(0), operand(1)) {
+                (Operand::Dptr, _) => self.sfr.dptr = argument(1) as u16,
+                (Operand::C, _) | (_, Operand::C) => {
+                    let bit = self.load_bit(operand(1), argument(1));
+                    self.store_bit(operand(0), argument(0), bit);
+                }
+                (destination, source) => {
+                    let value = self.load(source, argument(1));
+                    self.store(destination, argument(0), value);
+                }
+            },
+            "movc" => {
+                let base = match operand(1) {
+                    Operand::AtAPc => self.pc,
+                    _ => self.sfr.dptr,
+                };
+                let address = base.wrapping_add(self.accumulator as u16);
+                self.accumulator = self.read_code(address as usize) as CPUType;
+            }
+            "movx" => {
+                let address = |cpu: &Self, operand: Operand| match operand {
+                    Operand::Indirect(n) => cpu.get_register(n as usize) as CPUType,
+                    _ => cpu.sfr.dptr as CPUType,
+                };
+                if operand(0) == Operand::A {
+                    if let Some(value) = self.read_external(address(self, operand(1))) {
+                        self.accumulator = value as CPUType;
+                    }
+                } else {
+                    self.write_external(address(self, operand(0)), self.accumulator);
+                }
+            }
+            "mul" => self.mul_ab(),
+            "div" => self.div_ab(),
+            "cpl" if operand(0) == Operand::A => self.accumulator ^= 0xFF,
+            "cpl" => {
+                let bit = self.load_bit(operand(0), argument(0));
+                self.store_bit(operand(0), argument(0), !bit);
+            }
+            "clr" if operand(0) == Operand::A => self.accumulator = 0,
+            "clr" => self.store_bit(operand(0), argument(0), false),
+            "setb" => self.store_bit(operand(0), argument(0), true),
+            "cjne" => {
+                let a = self.load(operand(0), argument(0));
+                let b = self.load(operand(1), argument(1));
+                self.set_psw_flag(PSW_CY, a < b);
+                self.jump_if(a != b, argument(2));
+            }
+            "djnz" => {
+                let value = self.load(operand(0), argument(0)).wrapping_sub(1) & 0xFF;
+                self.store(operand(0), argument(0), value);
+                self.jump_if(value != 0, argument(1));
+            }
+            "push" => {
+                let value = self.load(Operand::Direct, argument(0));
+                self.push_byte(value);
+            }
+            "pop" => {
+                let value = self.pop_byte();
+                self.store(Operand::Direct, argument(0), value);
+            }
+            "swap" => self.swap_nibbles(),
+            "da" => self.decimal_adjust(),
+            "xch" | "xchd" => {
+                let a = self.accumulator;
+                let b = self.load(operand(1), argument(1));
+                let (a, b) = match opcode.mnemonic {
+                    "xch" => (b, a),
+                    _ => ((a & !0x0F) | (b & 0x0F), (b & !0x0F) | (a & 0x0F)),
+                };
+                self.accumulator = a;
+                self.store(operand(1), argument(1), b);
+            }
+            mnemonic => {
+                cpu_error();
+                log!(Error, f("`{mnemonic}` is not implemented"));
+            }
+        }
+    }
+}
@@ -1,29 +1,48 @@
 #![allow(dead_code)]
 use {
     crate::{
+        asm::listing::{definitions, Symbol},
         cpu::{
-            cpu_error, printx, CPUType, JumpLocation, NumberVar, PrintT, StringVar, Var,
-            CPU_ERROR_COUNT, LEXER_ERROR_COUNT,
+            bytecode::{Program, Target},
+            clock::DEFAULT_CLOCK_HZ,
+            cpu_error,
+            debugger::Debugger,
+            interrupt::InterruptController,
+            memory::{IRAM_8051, IRAM_8052, XRAM_MAX},
+            printx, set_source_line,
+            sfr::SpecialRegisters,
+            timer::Timers,
+            tracer::Tracer,
+            verbosity,
+            vm::{Frame, FrameKind, Pause},
+            CPUType, JumpLocation, NumberVar, PrintT, RuntimeError, StringVar, Var, Verbosity,
+            CPU_ERROR_COUNT, LEXER_ERROR_COUNT, RETURN_ADDRESS_SIZE, STACK_BASE,
         },
-        lexer::{Function, Lexer, Line, Token, TokenType},
+        dialect::{translate_8051, Dialect},
+        lexer::{Function, Lexer},
         log,
     },
     conv::prelude::*,
+    serde::{Deserialize, Serialize},
     std::{
         fmt::Debug,
         fs::File,
         io::{self, BufRead},
-        iter::Peekable,
         num::ParseIntError,
         path::Path,
-        slice::Iter,
+        rc::Rc,
+        time::Duration,
     },
 };
 
 #[cfg(not(target_arch = "wasm32"))]
 use colored::{ColoredString, Colorize};
 
-#[derive(Debug, Clone)]
+/*
+ * The whole state is serializable (see `get_json` and `restore`), only
+ * the breakpoints and the trace belong to the session
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CPU<CPUType> {
     pub stack: Vec<CPUType>,
     pub port: [CPUType; 8],
@@ -32,10 +51,75 @@ pub struct CPU<CPUType> {
     pub jump_locations: Vec<JumpLocation>,
     pub error_count: usize,
     pub functions: Vec<Function>,
+    pub iram: Vec<u8>,
+    pub xram: Vec<u8>,
+    pub sfr: SpecialRegisters,
+    pub stack_depth: Option<usize>,
+    pub stack_base: CPUType,
+    pub call_depth: usize,
+    pub runtime_error: Option<RuntimeError>,
+    pub interrupts: InterruptController,
+    pub returning: bool,
+    pub timers: Timers,
+    pub cycles: u64,
+    pub clock_hz: u64,
+    pub cycle_limit: Option<u64>,
+    // instructions executed by the current run, see `set_instruction_limit`
+    pub instructions: u64,
+    pub instruction_limit: Option<u64>,
+    // wall-clock time a single run may take
+    pub time_limit: Option<Duration>,
+    pub dialect: Dialect,
+    pub origins: Vec<(usize, usize)>,
+    pub code_memory: Vec<u8>,
+    pub pc: u16,
+    pub program: Option<Rc<Program>>,
+    // index inside `vars` of every variable slot of the program
+    pub var_slots: Vec<Option<usize>>,
+    pub constants: Vec<Symbol>,
+    // the Accumulator, the Ports and the Stack are cut to the word width
+    pub word_mask: CPUType,
+    // the running functions of the VM (innermost last)
+    pub frames: Vec<Frame>,
+    // address of the line the VM is paused at
+    pub paused_at: Option<usize>,
+    #[serde(skip)]
+    pub debugger: Debugger,
+    // records the executed lines, see `start_trace`
+    #[serde(skip)]
+    pub tracer: Option<Tracer>,
+}
+
+/*
+ * The snapshot of `get_json` with the values the web UI shows which
+ * are derived from the state
+ */
+#[derive(Serialize)]
+struct Snapshot<'c> {
+    #[serde(flatten)]
+    cpu: &'c CPU<CPUType>,
+    sp: CPUType,
+    registers: Vec<u8>,
+    register_bank: usize,
+    psw: CPUType,
+    elapsed_seconds: f64,
 }
 
 impl CPU<CPUType> {
     pub fn new<'t>() -> Result<Self, &'t str> {
+        Self::with_memory(IRAM_8051, 0)
+    }
+    /*
+     * Create a CPU with 128 or 256 bytes of internal RAM
+     * and up to 64K of external RAM
+     */
+    pub fn with_memory<'t>(iram_size: usize, xram_size: usize) -> Result<Self, &'t str> {
+        if iram_size != IRAM_8051 && iram_size != IRAM_8052 {
+            return Err("The internal RAM has to be 128 or 256 bytes big");
+        }
+        if xram_size > XRAM_MAX {
+            return Err("The external RAM can not be bigger than 64K");
+        }
         Ok(CPU {
             stack: vec![],
             port: [0; 8],
@@ -44,6 +128,34 @@ impl CPU<CPUType> {
             jump_locations: vec![],
             error_count: 0,
             functions: vec![],
+            iram: vec![0; iram_size],
+            xram: vec![0; xram_size],
+            sfr: SpecialRegisters::default(),
+            stack_depth: None,
+            stack_base: STACK_BASE,
+            call_depth: 0,
+            runtime_error: None,
+            interrupts: InterruptController::default(),
+            returning: false,
+            timers: Timers::default(),
+            cycles: 0,
+            clock_hz: DEFAULT_CLOCK_HZ,
+            cycle_limit: None,
+            instructions: 0,
+            instruction_limit: None,
+            time_limit: None,
+            dialect: Dialect::Russembly,
+            origins: vec![],
+            code_memory: vec![],
+            pc: 0,
+            program: None,
+            var_slots: vec![],
+            constants: vec![],
+            word_mask: CPUType::MAX,
+            frames: vec![],
+            paused_at: None,
+            debugger: Debugger::default(),
+            tracer: None,
         })
     }
     /*
@@ -62,6 +174,22 @@ impl CPU<CPUType> {
     pub fn get_jump_locations(&self) -> &Vec<JumpLocation> {
         &self.jump_locations
     }
+    /*
+     * The stack pointer points to the top of the stack,
+     * like on the 8051 it starts at 07h after a reset
+     */
+    pub fn get_sp(&self) -> CPUType {
+        self.stack_base + self.stack.len() + self.call_depth * RETURN_ADDRESS_SIZE
+    }
+    pub fn get_runtime_error(&self) -> &Option<RuntimeError> {
+        &self.runtime_error
+    }
+    pub fn get_iram(&self) -> &Vec<u8> {
+        &self.iram
+    }
+    pub fn get_xram(&self) -> &Vec<u8> {
+        &self.xram
+    }
     // -------------------------------------------------------
     /*
      * -------------------------------------------------
@@ -76,6 +204,13 @@ impl CPU<CPUType> {
     /*
      * -------------------------------------------------
      */
+    /*
+     * Limit the stack to `depth` entries (bounded mode) or
+     * use an unbounded stack with `None`
+     */
+    pub fn set_stack_depth(&mut self, depth: Option<usize>) {
+        self.stack_depth = depth;
+    }
     pub fn add_jump_location(&mut self, name: String, line: usize) {
         self.jump_locations.push(JumpLocation { name, line })
     }
@@ -85,6 +220,9 @@ impl CPU<CPUType> {
     #[cfg(not(target_arch = "wasm32"))]
     pub fn load_file(&mut self, path: &str) -> Option<()> {
         self.functions = vec![];
+        self.program = None;
+        self.dialect = Dialect::Russembly;
+        self.origins = vec![];
         let mut lexer = Lexer::new();
         lexer.setup_pb();
         let mut line_count = 0;
@@ -94,10 +232,13 @@ impl CPU<CPUType> {
         }
         if let Ok(lines) = self.read_lines(path) {
             log!(Lexer, "Parsing tokens...");
+            let mut source = vec![];
             lines.for_each(|line| {
                 let l = line.unwrap();
+                source.push(l.clone());
                 lexer.run(l, line_count);
             });
+            self.constants = definitions(source.iter().map(|line| line.as_str()));
             lexer.finish_pb();
             let mut lexer_error_c = 0usize;
             LEXER_ERROR_COUNT.with(|count| {
@@ -131,16 +272,22 @@ impl CPU<CPUType> {
      */
     pub fn load_string(&mut self, string: &str) -> Option<()> {
         self.functions = vec![];
+        self.program = None;
+        self.dialect = Dialect::Russembly;
+        self.origins = vec![];
         let mut lexer = Lexer::new();
         //lexer.setup_pb(); // this is not supported on wasm
         let code = &string.replace("~", "\n");
         let line_count = code.lines().count();
+        self.constants = definitions(code.lines());
 
         if line_count != 0 {
-            code.lines().for_each(|line| {
+            code.lines().enumerate().for_each(|(i, line)| {
                 let l = line.to_string();
+                set_source_line(Some(i + 1));
                 lexer.run(l, line_count);
             });
+            set_source_line(None);
             //lexer.finish_pb();
             let mut lexer_error_c = 0usize;
             LEXER_ERROR_COUNT.with(|count| {
@@ -164,6 +311,64 @@ impl CPU<CPUType> {
         }
         None
     }
+    /*
+     * Load a file written in the given dialect
+     */
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_file_as(&mut self, path: &str, dialect: Dialect) -> Option<()> {
+        match dialect {
+            Dialect::Russembly => self.load_file(path),
+            Dialect::I8051 => match std::fs::read_to_string(path) {
+                Ok(code) => self.load_string_as(&code, dialect),
+                Err(_) => {
+                    log!(Error, "Unable to read lines");
+                    None
+                }
+            },
+        }
+    }
+    /*
+     * Load a string written in the given dialect. A 8051 program becomes
+     * the `main` function, execution starts at its first line.
+     */
+    pub fn load_string_as(&mut self, string: &str, dialect: Dialect) -> Option<()> {
+        if dialect == Dialect::Russembly {
+            return self.load_string(string);
+        }
+        self.functions = vec![];
+        self.program = None;
+        let program = translate_8051(string);
+        let line_count = program.lines.len();
+        if line_count == 0 {
+            log!(Error, "Please provide some Code");
+            return None;
+        }
+        let mut lexer = Lexer::new();
+        program.lines.into_iter().enumerate().for_each(|(i, line)| {
+            set_source_line(Some(i + 1));
+            lexer.run(line, line_count);
+        });
+        set_source_line(None);
+        let mut lexer_error_c = 0usize;
+        LEXER_ERROR_COUNT.with(|count| {
+            lexer_error_c = *count.borrow();
+        });
+        log!(
+            Lexer,
+            f("Parsing the tokens returned {} errors", lexer_error_c)
+        );
+        log!(Info, "Finished parsing tokens");
+        self.dialect = dialect;
+        self.origins = program.origins;
+        self.constants = program.symbols;
+        self.functions = vec![Function {
+            name: "main".to_string(),
+            arguments: vec![],
+            lines: lexer.get_lines()?,
+            line: 0,
+        }];
+        Some(())
+    }
 
     /*
      * Read lines function I copied from Stackoverflow
@@ -186,63 +391,76 @@ impl CPU<CPUType> {
     }
 
     /*
-     * Function for getting the stringified JSON representation
-     * for this CPU struct
+     * The complete state as JSON, `restore` loads it again
      */
     pub fn get_json(&self) -> String {
-        let mut output = String::new();
-        output.push_str("{");
-        //stack: vec![],
-        output = format!("{}\"stack\":{:?},", output, self.stack);
-        //port: [0; 8],
-        output = format!("{}\"ports\":{{", output);
-        self.port.iter().enumerate().for_each(|(i, port)| {
-            if i == 0 {
-                output = format!("{}\"{}\":\"{}\"", output, i, port);
-            } else {
-                output = format!("{},\"{}\":\"{}\"", output, i, port);
+        let snapshot = Snapshot {
+            cpu: self,
+            sp: self.get_sp(),
+            registers: (0..8).map(|r| self.get_register(r)).collect(),
+            register_bank: self.register_bank(),
+            psw: self.read_sfr(0xD0).unwrap_or(0),
+            elapsed_seconds: self.elapsed_time(),
+        };
+        // the state has no maps with keys which are not strings
+        serde_json::to_string(&snapshot).expect("the state can always be serialized")
+    }
+    /*
+     * Load the state from a snapshot of `get_json`, the breakpoints and
+     * the trace stay
+     */
+    pub fn restore(&mut self, json: &str) -> Result<(), String> {
+        let mut cpu: CPU<CPUType> =
+            serde_json::from_str(json).map_err(|error| format!("Invalid snapshot: {error}"))?;
+        cpu.validate()
+            .map_err(|error| format!("Invalid snapshot: {error}"))?;
+        cpu.debugger = std::mem::take(&mut self.debugger);
+        cpu.tracer = self.tracer.take();
+        *self = cpu;
+        Ok(())
+    }
+    /*
+     * A snapshot could be corrupted, everything the VM uses as an index
+     * has to be in range
+     */
+    fn validate(&mut self) -> Result<(), String> {
+        if self.iram.len() != IRAM_8051 && self.iram.len() != IRAM_8052 {
+            return Err(format!("{} bytes of internal RAM", self.iram.len()));
+        }
+        if self.xram.len() > XRAM_MAX {
+            return Err(format!("{} bytes of external RAM", self.xram.len()));
+        }
+        let program = self.program();
+        program.validate()?;
+        let address = |address: usize| match address < program.code.len() {
+            true => Ok(()),
+            false => Err(format!("address {address} out of range")),
+        };
+        let slots = |slots: &[Option<usize>], vars: usize| {
+            if slots.len() > program.variables.len() {
+                return Err(format!("{} variable slots", slots.len()));
             }
-        });
-        output = format!("{}}},", output);
-        //vars: vec![],
-        output = format!("{}\"vars\":[", output,);
-        self.vars.iter().enumerate().for_each(|(i, v)| {
-            match v {
-                Var::String(x) => {
-                    if i == 0 {
-                        output = format!(
-                            "{}{{\"name\":{},\"value\":\"{}\"}}",
-                            output, x.name, x.value
-                        );
-                    } else {
-                        output = format!(
-                            "{},{{\"name\":{},\"value\":\"{}\"}}",
-                            output, x.name, x.value
-                        );
-                    }
-                }
-                Var::Number(x) => {
-                    if i == 0 {
-                        output = format!(
-                            "{}{{\"name\":{},\"value\":\"{}\"}}",
-                            output, x.name, x.value
-                        );
-                    } else {
-                        output = format!(
-                            ",{}{{\"name\":{},\"value\":\"{}\"}}",
-                            output, x.name, x.value
-                        );
-                    }
-                }
+            match slots.iter().flatten().find(|&&index| index >= vars) {
+                Some(index) => Err(format!("variable {index} out of range")),
+                None => Ok(()),
             }
-            //output = format!("{}   {}: {},\n", output);
-        });
-        output.push_str("],");
-        //accumulator: 0,
-        output = format!("{}\"accumulator\":\"{}\"", output, self.accumulator);
-        //jump_locations: vec![],
-        output.push_str("}");
-        return output;
+        };
+        if let Some(paused) = self.paused_at {
+            address(paused)?;
+        }
+        for frame in &self.frames {
+            address(frame.pc)?;
+            if let Some(Target::Line(line)) = &frame.jump {
+                address(*line)?;
+            }
+            if let FrameKind::Interrupt {
+                vars, var_slots, ..
+            } = &frame.kind
+            {
+                slots(var_slots, vars.len())?;
+            }
+        }
+        slots(&self.var_slots, self.vars.len())
     }
 
     /*
@@ -284,7 +502,7 @@ impl CPU<CPUType> {
     /*
      * Function for generating a pretty error message
      */
-    fn cpu_line_error(
+    pub fn cpu_line_error(
         &self,
         error: &str,
         line_string: String,
@@ -292,54 +510,118 @@ impl CPU<CPUType> {
         error_loc: usize,
         error_line: &str,
     ) {
-        let line_len = format!("{}", line_number).len();
-        let mut space = "".to_string();
-        for _ in 0..=line_len {
-            space.push(' ');
-        }
         log!(Error, f("{}", error));
 
-        #[cfg(not(target_arch = "wasm32"))]
-        let blue_line: ColoredString = "|".blue();
+        // the web UI marks the line itself
         #[cfg(target_arch = "wasm32")]
-        let blue_line: &str = "<span class=\"blue\">|</span>";
+        {
+            let _ = error_loc;
+            printx(
+                PrintT::Syntax,
+                &format!("{line_number} | {line_string}  {error_line}"),
+            );
+        }
 
         #[cfg(not(target_arch = "wasm32"))]
-        let blue_line_number: ColoredString = format!("{}", line_number).blue();
-        #[cfg(target_arch = "wasm32")]
-        let blue_line_number: String = format!("<span class=\"blue\">{}</span>", line_number);
+        {
+            let line_len = format!("{}", line_number).len();
+            let mut space = "".to_string();
+            for _ in 0..=line_len {
+                space.push(' ');
+            }
+            let blue_line: ColoredString = "|".blue();
+            let blue_line_number: ColoredString = format!("{}", line_number).blue();
 
-        log!(Clear, f("{}{}\n", space, blue_line));
-        log!(
-            Clear,
-            f("{} {} {}\n", blue_line_number, blue_line, line_string)
-        );
-        let mut temp = "".to_string();
-        let mut arrows = "".to_string();
-        temp.push_str(&format!("{}  ", blue_line));
-        let strings: Vec<&str> = line_string.split(" ").collect();
-        for i in 0..strings.len() {
-            if i == error_loc {
-                for _ in strings[i].chars() {
-                    arrows.push('^');
-                }
-            } else {
-                for _ in strings[i].chars() {
-                    temp.push(' ');
+            log!(Clear, f("{}{}\n", space, blue_line));
+            log!(
+                Clear,
+                f("{} {} {}\n", blue_line_number, blue_line, line_string)
+            );
+            let mut temp = "".to_string();
+            let mut arrows = "".to_string();
+            temp.push_str(&format!("{}  ", blue_line));
+            let strings: Vec<&str> = line_string.split(" ").collect();
+            for i in 0..strings.len() {
+                if i == error_loc {
+                    for _ in strings[i].chars() {
+                        arrows.push('^');
+                    }
+                } else {
+                    for _ in strings[i].chars() {
+                        temp.push(' ');
+                    }
                 }
             }
+            let el_red = error_line.red();
+            let arrows_red = arrows.red();
+            log!(Clear, f("{}{}{} {}\n", space, temp, arrows_red, el_red));
+            log!(Clear, f("{}{}\n", space, blue_line));
         }
-        #[cfg(not(target_arch = "wasm32"))]
-        let el_red = error_line.red();
-        #[cfg(not(target_arch = "wasm32"))]
-        let arrows_red = arrows.red();
+    }
+    /*
+     * Push a value to the stack, in bounded mode this stops the
+     * program with a stack overflow once the stack is full
+     */
+    pub fn push_stack(&mut self, value: CPUType) {
+        if self.reserve_stack(1) {
+            self.stack.push(value & self.word_mask);
+        }
+    }
 
-        #[cfg(target_arch = "wasm32")]
-        let el_red = format!("<span class=\"red\">{}</span>", error_line);
-        #[cfg(target_arch = "wasm32")]
-        let arrows_red = format!("<span class=\"red\">{}</span>", arrows);
-        log!(Clear, f("{}{}{} {}\n", space, temp, arrows_red, el_red));
-        log!(Clear, f("{}{}\n", space, blue_line));
+    /*
+     * Check if `size` more entries fit on a bounded stack
+     */
+    pub fn reserve_stack(&mut self, size: usize) -> bool {
+        if let Some(depth) = self.stack_depth {
+            let used = self.get_sp() - self.stack_base;
+            if used + size > depth {
+                cpu_error();
+                log!(
+                    Error,
+                    f(
+                        "Stack overflow: SP 0x{:x} exceeds the stack depth of {depth}",
+                        self.get_sp() + size
+                    )
+                );
+                self.runtime_error = Some(RuntimeError::StackOverflow);
+                return false;
+            }
+        }
+        true
+    }
+
+    /*
+     * Pop a value from the stack and stop the program with a
+     * stack underflow if it is empty
+     */
+    pub fn pop_stack(&mut self) -> Option<CPUType> {
+        let value = self.stack.pop();
+        if value.is_none() {
+            self.stack_underflow("pop");
+        }
+        value
+    }
+
+    /*
+     * Pop the top 2 values (top, second) for binary stack operations
+     */
+    pub fn pop_two(&mut self, opcode: &str) -> Option<(CPUType, CPUType)> {
+        if self.stack.len() < 2 {
+            self.stack_underflow(opcode);
+            return None;
+        }
+        let a = self.stack.pop().unwrap();
+        let b = self.stack.pop().unwrap();
+        Some((a, b))
+    }
+
+    pub fn stack_underflow(&mut self, opcode: &str) {
+        cpu_error();
+        log!(
+            Error,
+            f("Stack underflow: not enough values on the Stack for `{opcode}`")
+        );
+        self.runtime_error = Some(RuntimeError::StackUnderflow);
     }
     /*
      * --------------------------------------------------------------
@@ -348,10 +630,21 @@ impl CPU<CPUType> {
      */
 
     pub fn run_main(&mut self) {
-        log!(Clear, "\nOutput:\n");
-        log!(Clear, "-------------------------\n");
-        self.run_function("main", "");
-        log!(Clear, "-------------------------\n");
+        let framed = verbosity() > Verbosity::Quiet;
+        if framed {
+            log!(Clear, "\nOutput:\n");
+            log!(Clear, "-------------------------\n");
+        }
+        if self.start_main() {
+            let program = self.program();
+            self.run_frames(&program, 0, Pause::Never);
+        }
+        if framed {
+            log!(Clear, "-------------------------\n");
+        }
+        if let Some(error) = &self.runtime_error {
+            log!(Cpu, f("Program stopped: {error}"));
+        }
         let mut error_count = 0usize;
         CPU_ERROR_COUNT.with(|count| {
             error_count = *count.borrow();
@@ -361,303 +654,103 @@ impl CPU<CPUType> {
             f("Interpreting the tokens returned {} errors", error_count)
         );
     }
-
-    fn run_function(&mut self, name: &str, _arguments: &str) {
-        for f in self.functions.clone() {
-            if name == f.name {
-                self.run_lines(f.lines);
-                // clear variables
-                self.vars = vec![];
-                return;
-            }
-        }
-        log!(Error, f("function `{name}` not found"));
-    }
-
-    fn run_lines(&mut self, lines: Vec<Line>) {
-        for i in 0..(lines.len()) {
-            let mut token_iter = lines[i].tokens.iter().peekable();
-            while token_iter.peek().is_some() {
-                let token = token_iter.next().unwrap();
-                match &token.token_type {
-                    TokenType::OpCode => {
-                        self.run_opcodes(&mut token_iter, token, lines[i].clone().as_string, i)
-                    }
-                    TokenType::JumpLocation(_jump_location) => {}
-                    TokenType::Bracket => {}
-                    TokenType::Keyword => {
-                        self.run_keywords(&mut token_iter, token, lines[i].clone().as_string, i)
-                    }
-                    TokenType::String => {}
-                    TokenType::Comment => {}
-                    // Prints a new Line
-                    TokenType::NewLine => {
-                        log!(Clear, "\n");
-                    }
-                    _ => {
-                        printx(
-                            PrintT::Error,
-                            format!("unexpected token '{}' at line {i}", token.value).as_str(),
-                        );
+    /*
+     * Prepare the VM to run `main` (the reset vector in the 8051 dialect)
+     * without running it, `run_frames` or the debugger run it
+     */
+    pub fn start_main(&mut self) -> bool {
+        self.runtime_error = None;
+        self.instructions = 0;
+        self.call_depth = 0;
+        self.frames = vec![];
+        self.paused_at = None;
+        let program = self.program();
+        match self.dialect {
+            Dialect::Russembly => match program.function("main") {
+                Some(main) => self.push_call(main.start, FrameKind::Call { function: true }),
+                None => {
+                    log!(Error, "function `main` not found");
+                    false
+                }
+            },
+            // the 8051 starts at the reset vector without a return address
+            Dialect::I8051 => {
+                let start = self.origin_line(0).unwrap_or(0);
+                match program.function("main").and_then(|f| f.lines.get(start)) {
+                    Some(&start) => {
+                        self.frames.push(Frame {
+                            pc: start,
+                            jump: None,
+                            kind: FrameKind::Entry,
+                        });
+                        true
                     }
+                    None => false,
                 }
             }
         }
     }
 
-    pub fn run_keywords(
-        &mut self,
-        token_iter: &mut Peekable<Iter<Token>>,
-        token: &Token,
-        _line: String,
-        _line_number: usize,
-    ) {
-        match token.value.as_str() {
-            // "fn" => {
-            //     token_iter.next();
-            //     token_iter.next();
-            // }
-            "let" => {
-                let nt = match token_iter.next() {
-                    Some(x) => x,
-                    None => {
-                        cpu_error();
-                        log!(Error, "Expected Arguments after let");
-                        return;
-                    }
-                };
-                let var_name = match nt.token_type {
-                    TokenType::VarName => nt.value.as_str(),
-                    _ => {
-                        log!(Error, "Expected variable name");
-                        cpu_error();
-                        return;
-                    }
-                };
-                if let Some(nt) = token_iter.next() {
-                    match nt.token_type {
-                        TokenType::Comma => { /* Do nothing, just for checking*/ }
-                        _ => {
-                            cpu_error();
-                            log!(Error, "Expected Comma");
-                        }
-                    }
-                } else {
-                    cpu_error();
-                    log!(Error, "Expected Comma");
-                }
-                if let Some(nt) = token_iter.next() {
-                    match nt.token_type {
-                        TokenType::String => {
-                            self.vars.push(Var::String(StringVar {
-                                name: var_name.to_string(),
-                                value: nt.value.clone(),
-                            }));
-                        }
-                        TokenType::Number(x) => {
-                            self.vars.push(Var::Number(NumberVar {
-                                name: var_name.to_string(),
-                                value: x,
-                            }));
-                        }
-                        _ => {
-                            printx(
-                                PrintT::Error,
-                                "You can only store Strings and Numbers inside a Variable",
-                            );
-                            cpu_error();
-                        }
-                    }
-                } else {
-                    cpu_error();
-                    log!(Error, "Expected value for let");
-                }
+    pub fn run_function(&mut self, name: &str, _arguments: &str) {
+        self.instructions = 0;
+        let program = self.program();
+        match program.functions.iter().position(|f| f.name == name) {
+            Some(index) => self.call_function(&program, index),
+            None => {
+                log!(Error, f("function `{name}` not found"));
             }
-            _ => {}
         }
     }
-
-    pub fn run_opcodes(
-        &mut self,
-        token_iter: &mut Peekable<Iter<Token>>,
-        token: &Token,
-        line: String,
-        line_number: usize,
-    ) {
-        match token.value.as_str() {
-            "push" => {
-                if let Some(nt) = token_iter.next() {
-                    match nt.token_type {
-                        TokenType::Number(x) => self.stack.push(x),
-                        _ => {
-                            cpu_error();
-                            log!(Error, "You can only push Numbers to the Stack!");
-                        }
-                    }
-                } else {
-                    cpu_error();
-                    log!(Error, "Expected Number after push");
-                }
-            }
-            "pop" => {
-                self.stack.pop();
-            }
-            // move value
-            "mov" => {
-                if let (Some(port_or_accu), Some(comma), Some(value)) =
-                    (token_iter.next(), token_iter.next(), token_iter.next())
-                {
-                    match comma.token_type {
-                        // check for comma
-                        TokenType::Comma => {}
-                        _ => {
-                            cpu_error();
-                            log!(Error, "Expected Comma");
-                        }
-                    }
-                    match port_or_accu.token_type {
-                        // push to port
-                        TokenType::Port => {
-                            if let Ok(number) = self.get_port_from_str(port_or_accu.value.clone()) {
-                                self.port[number] = match value.token_type {
-                                    TokenType::Number(x) => x,
-                                    TokenType::Port => {
-                                        let port =
-                                            self.get_port_from_str(value.value.clone()).unwrap(); // get the Port number
-                                        self.port[port]
-                                    }
-                                    _ => {
-                                        cpu_error();
-                                        log!(Error,"You can only move a number or the value of a Port to this Port");
-                                        return;
-                                    }
-                                }
-                            }
-                        }
-                        // push to accumulator
-                        TokenType::Accumulator => {
-                            self.accumulator = match value.token_type {
-                                TokenType::Number(x) => x,
-                                TokenType::Port => {
-                                    let port = self.get_port_from_str(value.value.clone()).unwrap(); // get the Port number
-                                    self.port[port]
-                                }
-                                _ => {
-                                    cpu_error();
-                                    log!(Error,"You can only move a number or the value of a Port to the Accumulator");
-                                    return;
-                                }
-                            }
-                        }
-                        _ => {
-                            cpu_error();
-                            log!(Error, "Expected Port or Accu!");
-                        }
-                    }
-                } else {
-                    cpu_error();
-                    log!(Error, "Expected more Tokens after mov");
-                    log!(Syntax, "mov <Port or Accu> <,> <value>");
-                }
-            }
-            // add top 2 number from stack together and push them on the stack
-            "adds" => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.stack.push(a + b);
-            }
-            // sub top 2 number from stack together and push them on the stack
-            "subs" => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.stack.push(a - b);
-            }
-            // mul top 2 number from stack together and push them on the stack
-            "muls" => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.stack.push(a * b);
-            }
-            // div top 2 number from stack together and push them on the stack
-            "divs" => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.stack.push(a / b);
-            }
-            "djnz" => {}
-            "jmp" => {}
-            "setb" => {}
-            "end" => {}
-            // print given string or number
-            "prnt" => {
-                if let Some(nt) = token_iter.next() {
-                    match nt.token_type {
-                        TokenType::String => {
-                            let v = &nt.value;
-                            printx(PrintT::Clear, &format!("{}", v));
-                        }
-                        TokenType::Number(x) => {
-                            printx(PrintT::Clear, &format!("{}", x));
-                        }
-                        TokenType::Accumulator => {
-                            printx(PrintT::Clear, &format!("{}", self.accumulator));
-                        }
-                        TokenType::Stack => {
-                            printx(PrintT::Clear, &format!("{:?}", self.stack));
-                        }
-                        TokenType::Port => {
-                            if let Ok(port) = self.get_port_from_str(nt.value.clone()) {
-                                printx(PrintT::Clear, &format!("{}", self.port[port]));
-                            } else {
-                                cpu_error();
-                                log!(Error, "Invalid Port");
-                            }
-                        }
-                        _ => {
-                            if let Some(var) = self.try_get_var(&nt.value) {
-                                match var {
-                                    Var::Number(x) => {
-                                        printx(PrintT::Clear, &format!("{}", x.value));
-                                    }
-                                    Var::String(x) => {
-                                        printx(PrintT::Clear, &format!("{}", x.value));
-                                    }
-                                }
-                            } else {
-                                cpu_error();
-                                let value = nt.value.clone();
-                                //log!(Error, f("Unable to find variable \"{}\"", value));
-                                self.cpu_line_error(
-                                    &format!("cannot find value `{value}` in this scope"),
-                                    line.trim().to_string(),
-                                    line_number,
-                                    1,
-                                    "not found in this scope",
-                                );
-                            }
-                        }
-                    }
-                } else {
-                    cpu_error();
-                    self.cpu_line_error(
-                        "expected token after prnt statement",
-                        line.trim().to_string(),
-                        line_number,
-                        1,
-                        "",
-                    );
-                }
-            }
-            "call" => {
-                if let Some(funcion_name) = token_iter.next() {
-                    self.run_function(&funcion_name.value, "");
-                } else {
-                    log!(Error, "Expected function name after call statement");
-                }
-            }
-            &_ => {}
+    /*
+     * Line of the `ORG` directive for a code address (8051 dialect)
+     */
+    pub fn origin_line(&self, address: usize) -> Option<usize> {
+        self.origins
+            .iter()
+            .find(|(origin, _)| *origin == address)
+            .map(|(_, line)| *line)
+    }
+    /*
+     * Call the code at the given line until `ret` or `reti` (8051 dialect)
+     */
+    pub fn call_line(&mut self, line: usize) {
+        let program = self.program();
+        if let Some(&start) = program.function("main").and_then(|f| f.lines.get(line)) {
+            self.call_code(&program, start);
+        }
+    }
+    /*
+     * Stop once the cycle counter reaches `limit` (e.g. for programs
+     * which loop forever like most microcontroller programs)
+     */
+    pub fn set_cycle_limit(&mut self, limit: Option<u64>) {
+        self.cycle_limit = limit;
+    }
+    /*
+     * Number of bits of the Accumulator, the Ports and the Stack
+     * (1 to 64), values which don't fit are cut to the lower bits
+     */
+    pub fn set_word_width(&mut self, bits: u32) -> Result<(), String> {
+        if bits == 0 || bits > CPUType::BITS {
+            return Err(format!(
+                "The word width has to be between 1 and {} bits",
+                CPUType::BITS
+            ));
+        }
+        self.word_mask = CPUType::MAX >> (CPUType::BITS - bits);
+        self.accumulator &= self.word_mask;
+        for port in self.port.iter_mut() {
+            *port &= self.word_mask;
+        }
+        for value in self.stack.iter_mut() {
+            *value &= self.word_mask;
         }
+        Ok(())
     }
+    pub fn word_width(&self) -> u32 {
+        self.word_mask.count_ones()
+    }
+
     //--------------------------------------------------------------
     /*
      * Opcodes for Debugging and testing:
@@ -704,15 +797,6 @@ impl CPU<CPUType> {
     pub fn subp(&mut self, port: usize) {
         self.accumulator -= self.port[port];
     }
-    pub fn djnz(&mut self, port: usize, jmp_loc_name: String) {
-        if self.port[port] != 0 {
-            self.port[port] -= 1;
-            self.jmp(jmp_loc_name);
-        }
-    }
-    pub fn jmp(&mut self, _jmp_loc_name: String) {
-        /* TODO */
-    }
     pub fn setb(&mut self, port_bit: String) {
         let s = port_bit.split("^");
         let vec = s.collect::<Vec<&str>>();
@@ -0,0 +1,107 @@
+use crate::cpu::{cpu_error, main::CPU, printx, sfr::PSW_RS0, CPUType, PrintT};
+
+/*
+ * Size of the internal RAM of the 8051 (128 bytes) and the 8052 (256 bytes)
+ */
+pub const IRAM_8051: usize = 128;
+pub const IRAM_8052: usize = 256;
+/*
+ * The external RAM is addressed with 16 bits
+ */
+pub const XRAM_MAX: usize = 65536;
+
+impl CPU<CPUType> {
+    /*
+     * Address of a register R0 - R7 inside the current register bank
+     */
+    pub fn register_address(&self, register: usize) -> usize {
+        self.register_bank() * 8 + register
+    }
+    pub fn get_register(&self, register: usize) -> u8 {
+        self.iram[self.register_address(register)]
+    }
+    pub fn set_register(&mut self, register: usize, value: CPUType) {
+        let address = self.register_address(register);
+        self.iram[address] = value as u8;
+    }
+    pub fn set_register_bank(&mut self, bank: usize) {
+        if bank > 3 {
+            cpu_error();
+            printx(
+                PrintT::Error,
+                &format!("Register bank {bank} out of bounds (0 - 3)"),
+            );
+            return;
+        }
+        self.sfr.psw = (self.sfr.psw & !(0b11 << PSW_RS0)) | (bank as u8) << PSW_RS0;
+    }
+    /*
+     * Direct addressing (e.g. `mov 30h, A`), the addresses 80h - FFh
+     * belong to the special function registers
+     */
+    pub fn read_direct(&self, address: CPUType) -> Option<u8> {
+        if (0x80..0x100).contains(&address) {
+            return self.read_sfr(address as u8).map(|x| x as u8);
+        }
+        self.read_iram(address)
+    }
+    pub fn write_direct(&mut self, address: CPUType, value: CPUType) {
+        if (0x80..0x100).contains(&address) {
+            self.write_sfr(address as u8, value);
+            return;
+        }
+        self.write_iram(address, value);
+    }
+    /*
+     * Indirect addressing through R0 or R1 (e.g. `mov @R0, A`),
+     * reaches the upper 128 bytes of the internal RAM on the 8052
+     */
+    pub fn read_indirect(&self, register: usize) -> Option<u8> {
+        self.read_iram(self.get_register(register) as CPUType)
+    }
+    pub fn write_indirect(&mut self, register: usize, value: CPUType) {
+        self.write_iram(self.get_register(register) as CPUType, value);
+    }
+
+    pub fn read_iram(&self, address: CPUType) -> Option<u8> {
+        match self.iram.get(address) {
+            Some(value) => Some(*value),
+            None => {
+                self.memory_error("internal RAM", address, self.iram.len());
+                None
+            }
+        }
+    }
+    pub fn write_iram(&mut self, address: CPUType, value: CPUType) {
+        match self.iram.get_mut(address) {
+            Some(byte) => *byte = value as u8,
+            None => self.memory_error("internal RAM", address, self.iram.len()),
+        }
+    }
+    /*
+     * External RAM, only reachable with `movx`
+     */
+    pub fn read_external(&self, address: CPUType) -> Option<u8> {
+        match self.xram.get(address) {
+            Some(value) => Some(*value),
+            None => {
+                self.memory_error("external RAM", address, self.xram.len());
+                None
+            }
+        }
+    }
+    pub fn write_external(&mut self, address: CPUType, value: CPUType) {
+        match self.xram.get_mut(address) {
+            Some(byte) => *byte = value as u8,
+            None => self.memory_error("external RAM", address, self.xram.len()),
+        }
+    }
+
+    fn memory_error(&self, memory: &str, address: CPUType, size: usize) {
+        cpu_error();
+        printx(
+            PrintT::Error,
+            &format!("Address 0x{address:x} is out of bounds for the {memory} ({size} bytes)"),
+        );
+    }
+}
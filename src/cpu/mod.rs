@@ -1,16 +1,51 @@
 #![allow(dead_code)]
 #[cfg(not(target_arch = "wasm32"))]
 use colored::Colorize;
-use std::cell::RefCell;
+use {
+    serde::{Deserialize, Serialize},
+    std::cell::RefCell,
+};
+pub mod alu;
+pub mod budget;
+pub mod bytecode;
+pub mod clock;
+pub mod debugger;
+pub mod diagnostic;
 pub mod display;
+pub mod history;
+pub mod interrupt;
+pub mod machine;
 pub mod main;
+pub mod memory;
+pub mod rusmc;
+pub mod sfr;
+pub mod state;
+pub mod timer;
+pub mod tracer;
+pub mod vm;
 
 pub type CPUType = usize;
 
+/*
+ * Reset value of the stack pointer
+ */
+pub const STACK_BASE: CPUType = 0x07;
+/*
+ * A call stores its return address (2 bytes) on the stack
+ */
+pub const RETURN_ADDRESS_SIZE: usize = 2;
+
 thread_local! {
     pub static GLOBAL_OUTPUT: RefCell<String> = RefCell::new(String::from(""));
     pub static CPU_ERROR_COUNT: RefCell<usize> = RefCell::new(0usize);
     pub static LEXER_ERROR_COUNT: RefCell<usize> = RefCell::new(0usize);
+    pub static VERBOSITY: RefCell<Verbosity> = const { RefCell::new(Verbosity::Normal) };
+    // the messages the web UI shows next to the output of the program
+    pub static MESSAGES: RefCell<Vec<Message>> = const { RefCell::new(vec![]) };
+    // the line of the source the messages belong to
+    pub static SOURCE_LINE: RefCell<Option<usize>> = const { RefCell::new(None) };
+    // collect the output and the messages instead of printing them (always in the browser)
+    pub static CAPTURE: RefCell<bool> = const { RefCell::new(cfg!(target_arch = "wasm32")) };
 }
 
 pub fn cpu_error() {
@@ -24,12 +59,70 @@ pub fn lexer_error() {
     });
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct JumpLocation {
     pub name: String,
     pub line: usize,
 }
 
+/*
+ * Errors which stop the execution of a program
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RuntimeError {
+    StackOverflow,
+    StackUnderflow,
+    CycleLimit,
+    InvalidOpcode,
+    // the instruction limit or the time limit of an untrusted program
+    BudgetExhausted(Budget),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Budget {
+    Instructions,
+    Time,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuntimeError::StackOverflow => write!(f, "stack overflow"),
+            RuntimeError::StackUnderflow => write!(f, "stack underflow"),
+            RuntimeError::CycleLimit => write!(f, "cycle limit reached"),
+            RuntimeError::InvalidOpcode => write!(f, "invalid opcode"),
+            RuntimeError::BudgetExhausted(Budget::Instructions) => {
+                write!(f, "budget exhausted (instruction limit reached)")
+            }
+            RuntimeError::BudgetExhausted(Budget::Time) => {
+                write!(f, "budget exhausted (time limit reached)")
+            }
+        }
+    }
+}
+
+/*
+ * How much the interpreter prints besides the output of the program
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    // nothing at all, the debugger replays the program silently
+    Silent,
+    // only errors and the output of the program
+    Quiet,
+    #[default]
+    Normal,
+    // the state of the CPU after the program
+    Verbose,
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.with(|v| *v.borrow_mut() = verbosity);
+}
+pub fn verbosity() -> Verbosity {
+    VERBOSITY.with(|v| *v.borrow())
+}
+
 pub enum PrintT {
     Error,
     Info,
@@ -39,8 +132,37 @@ pub enum PrintT {
     Clear,
 }
 
+impl PrintT {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrintT::Error => "error",
+            PrintT::Info => "info",
+            PrintT::Lexer => "lexer",
+            PrintT::Cpu => "cpu",
+            PrintT::Syntax => "syntax",
+            PrintT::Clear => "output",
+        }
+    }
+    /*
+     * Quiet mode only prints errors and the output of the program
+     */
+    fn is_muted(&self) -> bool {
+        match verbosity() {
+            Verbosity::Silent => true,
+            Verbosity::Quiet => matches!(self, PrintT::Info | PrintT::Lexer | PrintT::Cpu),
+            _ => false,
+        }
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 pub fn printx(type_: PrintT, message: &str) {
+    if type_.is_muted() {
+        return;
+    }
+    if CAPTURE.with(|capture| *capture.borrow()) {
+        return collect(type_, message);
+    }
     let prefix = match type_ {
         PrintT::Error => format!("[Error]: ").red(),
         PrintT::Info => format!("[Info]: ").green(),
@@ -61,28 +183,65 @@ pub fn printx(type_: PrintT, message: &str) {
 
 #[cfg(target_arch = "wasm32")]
 pub fn printx(type_: PrintT, message: &str) {
-    let prefix = match type_ {
-        PrintT::Error => "<span class=\"error\">[Error]:</span> ",
-        PrintT::Info => "<span class=\"info\">[Info]:</span> ",
-        PrintT::Lexer => "<span class=\"lexer\">[Lexer]:</span> ",
-        PrintT::Cpu => "<span class=\"cpu\">[CPU]:</span> ",
-        PrintT::Syntax => "<span class=\"syntax\">[Syntax]:</span> ",
-        PrintT::Clear => "",
-    };
+    if type_.is_muted() {
+        return;
+    }
+    collect(type_, message);
+}
+
+/*
+ * The output of the program is collected as text, everything else as
+ * a `Message` of its kind
+ */
+fn collect(type_: PrintT, message: &str) {
     match type_ {
         PrintT::Clear => {
-            GLOBAL_OUTPUT.with(|output| {
-                *output.borrow_mut() = format!("{}{}{}", *output.borrow(), prefix, message);
-            });
+            GLOBAL_OUTPUT.with(|output| output.borrow_mut().push_str(message));
         }
         _ => {
-            GLOBAL_OUTPUT.with(|output| {
-                *output.borrow_mut() = format!("{}{}{}\n", *output.borrow(), prefix, message);
+            let line = SOURCE_LINE.with(|line| *line.borrow());
+            MESSAGES.with(|messages| {
+                messages.borrow_mut().push(Message {
+                    kind: type_.name(),
+                    text: message.to_string(),
+                    line,
+                })
             });
         }
     };
 }
 
+/*
+ * A message of the interpreter (not the output of the program)
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Message {
+    // `error`, `info`, `lexer`, `cpu` or `syntax`
+    pub kind: &'static str,
+    pub text: String,
+    // the line (1-based) while a program is parsed or a line fails
+    pub line: Option<usize>,
+}
+
+/*
+ * Collect (or print) the output and the messages from now on, returns
+ * the previous setting
+ */
+pub fn set_capture(capture: bool) -> bool {
+    CAPTURE.with(|c| c.replace(capture))
+}
+
+pub fn set_source_line(line: Option<usize>) {
+    SOURCE_LINE.with(|l| *l.borrow_mut() = line);
+}
+
+/*
+ * The messages since the last call
+ */
+pub fn take_messages() -> Vec<Message> {
+    MESSAGES.with(|messages| std::mem::take(&mut *messages.borrow_mut()))
+}
+
 #[allow(dead_code)]
 pub fn get_global_output() -> String {
     let mut output = String::new();
@@ -92,6 +251,14 @@ pub fn get_global_output() -> String {
     output
 }
 
+/*
+ * The output since the last call (the web UI shows it piece by piece)
+ */
+#[allow(dead_code)]
+pub fn take_global_output() -> String {
+    GLOBAL_OUTPUT.with(|text| std::mem::take(&mut *text.borrow_mut()))
+}
+
 #[macro_export]
 macro_rules! log {
     (Error, f($($format:tt)*)) => {
@@ -147,19 +314,27 @@ macro_rules! log {
     };
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Var<CPUType> {
     String(StringVar),
     Number(NumberVar<CPUType>),
 }
+impl<CPUType> Var<CPUType> {
+    pub fn name(&self) -> &str {
+        match self {
+            Var::String(var) => &var.name,
+            Var::Number(var) => &var.name,
+        }
+    }
+}
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StringVar {
     pub name: String,
     pub value: String,
 }
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NumberVar<CPUType> {
     pub name: String,
     pub value: CPUType,
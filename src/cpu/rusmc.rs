@@ -0,0 +1,119 @@
+use {
+    crate::{
+        asm::listing::Symbol,
+        cpu::{bytecode::Program, main::CPU, CPUType},
+        dialect::Dialect,
+        log,
+    },
+    serde::{Deserialize, Serialize},
+    std::rc::Rc,
+};
+
+/*
+ * A `.rusmc` file starts with the magic bytes and the version (u16, little
+ * endian) of the format, followed by the program encoded with bincode
+ */
+pub const RUSMC_MAGIC: &[u8; 5] = b"RUSMC";
+pub const RUSMC_VERSION: u16 = 2;
+
+/*
+ * A compiled program, loading it skips the lexer and the compiler
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledProgram {
+    pub dialect: Dialect,
+    // (code address, line) of every `ORG` directive
+    pub origins: Vec<(usize, usize)>,
+    pub constants: Vec<Symbol>,
+    // the functions with their labels and source lines
+    pub program: Program,
+}
+
+impl CompiledProgram {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = RUSMC_MAGIC.to_vec();
+        bytes.extend(RUSMC_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self).unwrap_or_default());
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompiledProgram, String> {
+        let header = RUSMC_MAGIC.len() + 2;
+        if bytes.len() < header || &bytes[..RUSMC_MAGIC.len()] != RUSMC_MAGIC {
+            return Err("not a compiled russembly program".to_string());
+        }
+        let version = u16::from_le_bytes([bytes[header - 2], bytes[header - 1]]);
+        if version != RUSMC_VERSION {
+            return Err(format!(
+                "unsupported .rusmc version {version} (expected {RUSMC_VERSION})"
+            ));
+        }
+        let compiled: CompiledProgram =
+            bincode::deserialize(&bytes[header..]).map_err(|error| error.to_string())?;
+        compiled.program.validate()?;
+        Ok(compiled)
+    }
+    /*
+     * (function, label, code address) of every jump location
+     */
+    pub fn labels(&self) -> Vec<(&str, &str, usize)> {
+        self.program
+            .functions
+            .iter()
+            .flat_map(|f| {
+                f.labels
+                    .iter()
+                    .map(move |(name, line)| (f.name.as_str(), name.as_str(), f.lines[*line]))
+            })
+            .collect()
+    }
+}
+
+impl CPU<CPUType> {
+    pub fn compiled_program(&mut self) -> CompiledProgram {
+        CompiledProgram {
+            dialect: self.dialect,
+            origins: self.origins.clone(),
+            constants: self.constants.clone(),
+            program: self.program().as_ref().clone(),
+        }
+    }
+    /*
+     * The loaded program in the `.rusmc` format
+     */
+    pub fn compile_to_rusmc(&mut self) -> Vec<u8> {
+        self.compiled_program().to_bytes()
+    }
+    /*
+     * Load a program in the `.rusmc` format, `run_main` runs it
+     * without parsing the source again
+     */
+    pub fn load_rusmc(&mut self, bytes: &[u8]) -> Option<()> {
+        let compiled = match CompiledProgram::from_bytes(bytes) {
+            Ok(compiled) => compiled,
+            Err(error) => {
+                log!(Error, f("Invalid compiled program: {error}"));
+                return None;
+            }
+        };
+        self.functions = vec![];
+        self.dialect = compiled.dialect;
+        self.origins = compiled.origins;
+        self.constants = compiled.constants;
+        self.program = Some(Rc::new(compiled.program));
+        Some(())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_rusmc_file(&mut self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.compile_to_rusmc())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_rusmc_file(&mut self, path: &str) -> Option<()> {
+        match std::fs::read(path) {
+            Ok(bytes) => self.load_rusmc(&bytes),
+            Err(error) => {
+                log!(Error, f("Unable to read `{path}`: {error}"));
+                None
+            }
+        }
+    }
+}
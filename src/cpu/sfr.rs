@@ -0,0 +1,280 @@
+use crate::{
+    cpu::{cpu_error, main::CPU, printx, CPUType, PrintT, RETURN_ADDRESS_SIZE},
+    log,
+};
+use serde::{Deserialize, Serialize};
+
+/*
+ * A special function register with its direct address and
+ * the names of its bits (only for bit addressable registers)
+ */
+pub struct Sfr {
+    pub name: &'static str,
+    pub address: u8,
+    pub bits: Option<[&'static str; 8]>,
+}
+
+const fn sfr(name: &'static str, address: u8) -> Sfr {
+    Sfr {
+        name,
+        address,
+        bits: None,
+    }
+}
+const fn bit_sfr(name: &'static str, address: u8, bits: [&'static str; 8]) -> Sfr {
+    Sfr {
+        name,
+        address,
+        bits: Some(bits),
+    }
+}
+
+/*
+ * SFR table of the 8051, bits are listed from bit 0 to bit 7
+ * (an empty name means the bit has no name)
+ */
+pub const SFRS: [Sfr; 21] = [
+    sfr("P0", 0x80),
+    sfr("SP", 0x81),
+    sfr("DPL", 0x82),
+    sfr("DPH", 0x83),
+    sfr("PCON", 0x87),
+    bit_sfr(
+        "TCON",
+        0x88,
+        ["IT0", "IE0", "IT1", "IE1", "TR0", "TF0", "TR1", "TF1"],
+    ),
+    sfr("TMOD", 0x89),
+    sfr("TL0", 0x8A),
+    sfr("TL1", 0x8B),
+    sfr("TH0", 0x8C),
+    sfr("TH1", 0x8D),
+    sfr("P1", 0x90),
+    bit_sfr(
+        "SCON",
+        0x98,
+        ["RI", "TI", "RB8", "TB8", "REN", "SM2", "SM1", "SM0"],
+    ),
+    sfr("SBUF", 0x99),
+    sfr("P2", 0xA0),
+    bit_sfr("IE", 0xA8, ["EX0", "ET0", "EX1", "ET1", "ES", "", "", "EA"]),
+    bit_sfr(
+        "P3",
+        0xB0,
+        ["RXD", "TXD", "INT0", "INT1", "T0", "T1", "WR", "RD"],
+    ),
+    bit_sfr("IP", 0xB8, ["PX0", "PT0", "PX1", "PT1", "PS", "", "", ""]),
+    bit_sfr("PSW", 0xD0, ["P", "", "OV", "RS0", "RS1", "F0", "AC", "CY"]),
+    sfr("ACC", 0xE0),
+    sfr("B", 0xF0),
+];
+
+pub const PSW_OV: u8 = 2;
+pub const PSW_RS0: u8 = 3;
+pub const PSW_AC: u8 = 6;
+pub const PSW_CY: u8 = 7;
+
+pub fn sfr_by_name(name: &str) -> Option<&'static Sfr> {
+    SFRS.iter().find(|sfr| sfr.name == name)
+}
+pub fn sfr_by_address(address: u8) -> Option<&'static Sfr> {
+    SFRS.iter().find(|sfr| sfr.address == address)
+}
+
+/*
+ * Resolve the bit address of a named bit (`EA`), a bit of a bit addressable
+ * SFR (`P1.3`, `P1^3`, `ACC.7`) or a bit of the bit addressable RAM (`20h.1`)
+ */
+pub fn bit_address(name: &str) -> Option<u8> {
+    if let Some((register, bit)) = name.split_once(['.', '^']) {
+        let bit = bit.parse::<u8>().ok().filter(|bit| *bit < 8)?;
+        if let Some(sfr) = sfr_by_name(register) {
+            // only registers with an address divisible by 8 are bit addressable
+            return (sfr.address % 8 == 0).then_some(sfr.address + bit);
+        }
+        let address = crate::lexer::parse_number(register)?;
+        return match address {
+            0x20..=0x2F => Some(((address - 0x20) * 8) as u8 + bit),
+            _ => None,
+        };
+    }
+    SFRS.iter().find_map(|sfr| {
+        let position = sfr
+            .bits?
+            .iter()
+            .position(|bit| !bit.is_empty() && *bit == name)?;
+        Some(sfr.address + position as u8)
+    })
+}
+
+/*
+ * Name of a bit address for the Display output (e.g. `EA` or `20h.1`)
+ */
+pub fn bit_name(address: u8) -> String {
+    if address < 0x80 {
+        return format!("{:02x}h.{}", 0x20 + address / 8, address % 8);
+    }
+    match sfr_by_address(address & 0xF8) {
+        Some(sfr) => match sfr.bits {
+            Some(bits) if !bits[(address & 7) as usize].is_empty() => {
+                bits[(address & 7) as usize].to_string()
+            }
+            _ => format!("{}.{}", sfr.name, address & 7),
+        },
+        None => format!("{:02x}h", address),
+    }
+}
+
+/*
+ * SFRs which do not belong to a peripheral
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpecialRegisters {
+    pub psw: u8,
+    pub b: u8,
+    pub dptr: u16,
+    pub pcon: u8,
+    pub sbuf: u8,
+}
+
+impl CPU<CPUType> {
+    /*
+     * Read a special function register by its direct address (80h - FFh)
+     */
+    pub fn read_sfr(&self, address: u8) -> Option<CPUType> {
+        let value = match address {
+            0x80 => self.port[0],
+            0x90 => self.port[1],
+            0xA0 => self.port[2],
+            0xB0 => self.port[3],
+            0x81 => self.get_sp(),
+            0x82 => (self.sfr.dptr & 0xFF) as CPUType,
+            0x83 => (self.sfr.dptr >> 8) as CPUType,
+            0x87 => self.sfr.pcon as CPUType,
+            0x88 => self.interrupts.tcon as CPUType,
+            0x89 => self.timers.tmod as CPUType,
+            0x8A => self.timers.tl0 as CPUType,
+            0x8B => self.timers.tl1 as CPUType,
+            0x8C => self.timers.th0 as CPUType,
+            0x8D => self.timers.th1 as CPUType,
+            0x98 => self.interrupts.scon as CPUType,
+            0x99 => self.sfr.sbuf as CPUType,
+            0xA8 => self.interrupts.ie as CPUType,
+            0xB8 => self.interrupts.ip as CPUType,
+            // bit 0 of the PSW is the parity of the (8 bit) accumulator
+            0xD0 => {
+                let parity = (self.accumulator as u8).count_ones() as u8 & 1;
+                ((self.sfr.psw & 0xFE) | parity) as CPUType
+            }
+            0xE0 => self.accumulator,
+            0xF0 => self.sfr.b as CPUType,
+            _ => {
+                self.unknown_sfr(address);
+                return None;
+            }
+        };
+        Some(value)
+    }
+    /*
+     * Write a special function register by its direct address (80h - FFh)
+     */
+    pub fn write_sfr(&mut self, address: u8, value: CPUType) {
+        let byte = value as u8;
+        // the SFRs are 8 bit registers, a narrower word width cuts them further
+        let word = byte as CPUType & self.word_mask;
+        match address {
+            0x80 => self.port[0] = word,
+            0x90 => self.port[1] = word,
+            0xA0 => self.port[2] = word,
+            0xB0 => self.port[3] = word,
+            0x81 => {
+                // moving the stack pointer moves the whole stack
+                let used = self.stack.len() + self.call_depth * RETURN_ADDRESS_SIZE;
+                match value.checked_sub(used) {
+                    Some(base) => self.stack_base = base,
+                    None => {
+                        cpu_error();
+                        log!(
+                            Error,
+                            f("SP can not be lower than the {used} used stack entries")
+                        );
+                    }
+                }
+            }
+            0x82 => self.sfr.dptr = (self.sfr.dptr & 0xFF00) | byte as u16,
+            0x83 => self.sfr.dptr = (self.sfr.dptr & 0x00FF) | (byte as u16) << 8,
+            0x87 => self.sfr.pcon = byte,
+            0x88 => self.interrupts.tcon = byte,
+            0x89 => self.timers.tmod = byte,
+            0x8A => self.timers.tl0 = byte,
+            0x8B => self.timers.tl1 = byte,
+            0x8C => self.timers.th0 = byte,
+            0x8D => self.timers.th1 = byte,
+            0x98 => self.interrupts.scon = byte,
+            0x99 => self.sfr.sbuf = byte,
+            0xA8 => self.interrupts.ie = byte,
+            0xB8 => self.interrupts.ip = byte,
+            0xD0 => self.sfr.psw = byte,
+            0xE0 => self.accumulator = word,
+            0xF0 => self.sfr.b = byte,
+            _ => self.unknown_sfr(address),
+        }
+    }
+    /*
+     * Read a bit of the bit addressable RAM (00h - 7Fh) or of a SFR (80h - FFh)
+     */
+    pub fn read_bit_address(&self, address: u8) -> Option<bool> {
+        if address < 0x80 {
+            let byte = self.iram[0x20 + (address / 8) as usize];
+            Some(byte & (1 << (address % 8)) != 0)
+        } else {
+            let byte = self.read_sfr(address & 0xF8)?;
+            Some(byte & (1 << (address & 7)) != 0)
+        }
+    }
+    pub fn write_bit_address(&mut self, address: u8, value: bool) {
+        let mask = 1 << (address & 7);
+        if address < 0x80 {
+            let byte = &mut self.iram[0x20 + (address / 8) as usize];
+            if value {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        } else if let Some(byte) = self.read_sfr(address & 0xF8) {
+            let byte = if value {
+                byte | mask as CPUType
+            } else {
+                byte & !(mask as CPUType)
+            };
+            self.write_sfr(address & 0xF8, byte);
+        }
+    }
+    /*
+     * The register bank is selected with RS0 and RS1 of the PSW
+     */
+    pub fn register_bank(&self) -> usize {
+        ((self.sfr.psw >> PSW_RS0) & 0b11) as usize
+    }
+    pub fn psw_flag(&self, bit: u8) -> bool {
+        self.sfr.psw & (1 << bit) != 0
+    }
+    pub fn set_psw_flag(&mut self, bit: u8, value: bool) {
+        if value {
+            self.sfr.psw |= 1 << bit;
+        } else {
+            self.sfr.psw &= !(1 << bit);
+        }
+    }
+    pub fn get_dptr(&self) -> u16 {
+        self.sfr.dptr
+    }
+
+    fn unknown_sfr(&self, address: u8) {
+        cpu_error();
+        printx(
+            PrintT::Error,
+            &format!("There is no special function register at 0x{address:02x}"),
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use {
+    crate::cpu::{main::CPU, CPUType, Var},
+    serde::Serialize,
+};
+
+/*
+ * The values the web UI shows, unlike `get_json` it leaves out the
+ * program and the memories
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct State {
+    pub ports: Vec<CPUType>,
+    pub accumulator: CPUType,
+    // the bottom of the stack first
+    pub stack: Vec<CPUType>,
+    pub sp: CPUType,
+    // R0 - R7 of the selected bank
+    pub registers: Vec<u8>,
+    pub register_bank: usize,
+    pub psw: CPUType,
+    pub dptr: u16,
+    pub vars: Vec<VarState>,
+    pub cycles: u64,
+    pub instructions: u64,
+    pub elapsed_seconds: f64,
+    // the runtime error which stopped the program
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VarState {
+    pub name: String,
+    pub value: VarValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum VarValue {
+    Number(CPUType),
+    String(String),
+}
+
+impl From<&Var<CPUType>> for VarValue {
+    fn from(var: &Var<CPUType>) -> Self {
+        match var {
+            Var::Number(var) => VarValue::Number(var.value),
+            Var::String(var) => VarValue::String(var.value.clone()),
+        }
+    }
+}
+
+impl CPU<CPUType> {
+    pub fn state(&self) -> State {
+        State {
+            ports: self.port.to_vec(),
+            accumulator: self.accumulator,
+            stack: self.stack.clone(),
+            sp: self.get_sp(),
+            registers: (0..8).map(|r| self.get_register(r)).collect(),
+            register_bank: self.register_bank(),
+            psw: self.read_sfr(0xD0).unwrap_or(0),
+            dptr: self.get_dptr(),
+            vars: self
+                .vars
+                .iter()
+                .map(|var| VarState {
+                    name: var.name().to_string(),
+                    value: var.into(),
+                })
+                .collect(),
+            cycles: self.cycles,
+            instructions: self.instructions,
+            elapsed_seconds: self.elapsed_time(),
+            error: self.runtime_error.as_ref().map(|error| error.to_string()),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::cpu::{
+    interrupt::{Interrupt, TCON_TR0, TCON_TR1},
+    main::CPU,
+    CPUType,
+};
+use serde::{Deserialize, Serialize};
+
+/*
+ * C/T bit of one timer nibble inside TMOD
+ * (the low nibble belongs to timer 0, the high nibble to timer 1)
+ */
+pub const TMOD_CT: u8 = 2;
+
+/*
+ * Timer/counter 0 and 1 of the 8051
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timers {
+    pub tmod: u8,
+    pub th0: u8,
+    pub tl0: u8,
+    pub th1: u8,
+    pub tl1: u8,
+}
+
+impl Timers {
+    /*
+     * Mode (0 - 3) and C/T bit of the given timer
+     */
+    pub fn mode(&self, timer: usize) -> u8 {
+        (self.tmod >> (timer * 4)) & 0b11
+    }
+    pub fn is_counter(&self, timer: usize) -> bool {
+        (self.tmod >> (timer * 4)) & (1 << TMOD_CT) != 0
+    }
+    /*
+     * Count `pulses` for the 16 bit register pair TH/TL, returns how often it overflowed
+     */
+    fn count(high: &mut u8, low: &mut u8, mode: u8, pulses: usize) -> usize {
+        let mut overflows = 0;
+        for _ in 0..pulses {
+            match mode {
+                // 13 bit timer, TL only uses its lower 5 bits
+                0 => {
+                    *low = low.wrapping_add(1) & 0x1F;
+                    if *low == 0 {
+                        *high = high.wrapping_add(1);
+                        if *high == 0 {
+                            overflows += 1;
+                        }
+                    }
+                }
+                // 16 bit timer
+                1 => {
+                    *low = low.wrapping_add(1);
+                    if *low == 0 {
+                        *high = high.wrapping_add(1);
+                        if *high == 0 {
+                            overflows += 1;
+                        }
+                    }
+                }
+                // 8 bit timer, TL is reloaded from TH
+                _ => {
+                    *low = low.wrapping_add(1);
+                    if *low == 0 {
+                        *low = *high;
+                        overflows += 1;
+                    }
+                }
+            }
+        }
+        overflows
+    }
+}
+
+impl CPU<CPUType> {
+    /*
+     * Advance the timers by the given amount of machine cycles
+     */
+    #[inline]
+    pub fn tick(&mut self, cycles: usize) {
+        // nothing counts while TR0 and TR1 are cleared
+        if self.interrupts.tcon & (1 << TCON_TR0 | 1 << TCON_TR1) == 0 {
+            return;
+        }
+        for timer in 0..2 {
+            if !self.timers.is_counter(timer) {
+                self.count_timer(timer, cycles);
+            }
+        }
+    }
+    /*
+     * Host API: apply `pulses` falling edges on the T0/T1 pin of a timer
+     * which is configured as a counter (C/T = 1)
+     */
+    pub fn count_pulses(&mut self, timer: usize, pulses: usize) {
+        if timer < 2 && self.timers.is_counter(timer) {
+            self.count_timer(timer, pulses);
+        }
+    }
+
+    fn count_timer(&mut self, timer: usize, pulses: usize) {
+        let tcon = self.interrupts.tcon;
+        let running = |bit: u8| tcon & (1 << bit) != 0;
+        let timer0_mode = self.timers.mode(0);
+        let timers = &mut self.timers;
+        match (timer, timer0_mode) {
+            // mode 3: TL0 is a 8 bit timer controlled by TR0/TF0 and
+            // TH0 is a 8 bit timer controlled by TR1/TF1
+            (0, 3) => {
+                if running(TCON_TR0) && overflow_8bit(&mut timers.tl0, pulses) {
+                    self.interrupts.set_request(Interrupt::Timer0, true);
+                }
+                if running(TCON_TR1) && overflow_8bit(&mut timers.th0, pulses) {
+                    self.interrupts.set_request(Interrupt::Timer1, true);
+                }
+            }
+            (0, mode) => {
+                if running(TCON_TR0)
+                    && Timers::count(&mut timers.th0, &mut timers.tl0, mode, pulses) > 0
+                {
+                    self.interrupts.set_request(Interrupt::Timer0, true);
+                }
+            }
+            // timer 1 keeps counting while timer 0 is in mode 3,
+            // but it can no longer set TF1
+            (_, timer0_mode) => {
+                let mode = timers.mode(1);
+                // timer 1 in mode 3 is stopped
+                if mode == 3 || !running(TCON_TR1) {
+                    return;
+                }
+                let overflows = Timers::count(&mut timers.th1, &mut timers.tl1, mode, pulses);
+                if overflows > 0 && timer0_mode != 3 {
+                    self.interrupts.set_request(Interrupt::Timer1, true);
+                }
+            }
+        }
+    }
+}
+
+fn overflow_8bit(register: &mut u8, pulses: usize) -> bool {
+    let (value, overflow) = register.overflowing_add((pulses % 256) as u8);
+    *register = value;
+    overflow || pulses >= 256
+}
@@ -0,0 +1,288 @@
+use {
+    crate::cpu::{
+        bytecode::{Instruction, Program},
+        main::CPU,
+        sfr::bit_name,
+        CPUType,
+    },
+    serde::{Deserialize, Serialize},
+    std::fmt::{Display, Formatter},
+};
+
+/*
+ * The part of the state a trace compares between two lines
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceState {
+    pub accumulator: CPUType,
+    pub port: [CPUType; 8],
+    pub stack: Vec<CPUType>,
+    pub psw: u8,
+}
+
+/*
+ * A change made by a line, flags are the bits of the PSW
+ * (the parity follows the Accumulator and is left out)
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Change {
+    Accumulator {
+        old: CPUType,
+        new: CPUType,
+    },
+    Port {
+        port: usize,
+        old: CPUType,
+        new: CPUType,
+    },
+    // the values removed from and added to the top of the stack
+    Stack {
+        popped: Vec<CPUType>,
+        pushed: Vec<CPUType>,
+    },
+    Flag {
+        flag: String,
+        value: bool,
+    },
+}
+
+/*
+ * An executed line
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub step: usize,
+    pub function: String,
+    // the line in the file (1-based)
+    pub line: usize,
+    pub opcode: String,
+    pub operands: Vec<String>,
+    // machine cycles after the line
+    pub cycles: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+/*
+ * Records every line the VM executes, see `CPU::start_trace`
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Tracer {
+    pub entries: Vec<TraceEntry>,
+    // the state after the last line
+    last: TraceState,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Change::Accumulator { old, new } => write!(f, "A {old}->{new}"),
+            Change::Port { port, old, new } => write!(f, "P{port} {old}->{new}"),
+            Change::Stack { popped, pushed } => {
+                let values = |values: &[CPUType]| {
+                    values
+                        .iter()
+                        .map(|value| value.to_string())
+                        .collect::<Vec<String>>()
+                        .join(",")
+                };
+                write!(f, "stack")?;
+                if !popped.is_empty() {
+                    write!(f, " -[{}]", values(popped))?;
+                }
+                if !pushed.is_empty() {
+                    write!(f, " +[{}]", values(pushed))?;
+                }
+                Ok(())
+            }
+            Change::Flag { flag, value } => write!(f, "{flag}={}", *value as u8),
+        }
+    }
+}
+
+/*
+ * e.g. `    12  main:7  mov P2, A  | P2 0->5`
+ */
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>6}  {}:{}  {} {}",
+            self.step,
+            self.function,
+            self.line,
+            self.opcode,
+            self.operands.join(", ")
+        )?;
+        if !self.changes.is_empty() {
+            let changes: Vec<String> = self.changes.iter().map(|c| c.to_string()).collect();
+            write!(f, "  | {}", changes.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl TraceState {
+    /*
+     * What changed from `self` to `new`
+     */
+    fn changes(&self, new: &TraceState) -> Vec<Change> {
+        let mut changes = vec![];
+        if self.accumulator != new.accumulator {
+            changes.push(Change::Accumulator {
+                old: self.accumulator,
+                new: new.accumulator,
+            });
+        }
+        for (port, (&old, &new)) in self.port.iter().zip(new.port.iter()).enumerate() {
+            if old != new {
+                changes.push(Change::Port { port, old, new });
+            }
+        }
+        if self.stack != new.stack {
+            let common = self
+                .stack
+                .iter()
+                .zip(new.stack.iter())
+                .take_while(|(old, new)| old == new)
+                .count();
+            changes.push(Change::Stack {
+                popped: self.stack[common..].to_vec(),
+                pushed: new.stack[common..].to_vec(),
+            });
+        }
+        for bit in (1..8).rev() {
+            let value = new.psw & (1 << bit) != 0;
+            if (self.psw & (1 << bit) != 0) != value {
+                changes.push(Change::Flag {
+                    flag: bit_name(0xD0 + bit),
+                    value,
+                });
+            }
+        }
+        changes
+    }
+}
+
+impl Tracer {
+    /*
+     * One JSON object per line
+     */
+    pub fn to_json_lines(&self) -> String {
+        self.entries
+            .iter()
+            .filter_map(|entry| serde_json::to_string(entry).ok())
+            .map(|line| line + "\n")
+            .collect()
+    }
+    /*
+     * One line of text per executed line
+     */
+    pub fn to_text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| format!("{entry}\n"))
+            .collect()
+    }
+}
+
+/*
+ * The mnemonic and the operands of a source line, labels and comments
+ * are dropped and commas inside strings are kept
+ */
+pub fn split_instruction(source: &str) -> (String, Vec<String>) {
+    let mut code = String::new();
+    let mut quoted = false;
+    for c in source.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => break,
+            _ => {}
+        }
+        code.push(c);
+    }
+    let mut code = code.trim();
+    // `loop: inc A`
+    while let Some((label, rest)) = code.split_once(char::is_whitespace) {
+        match label.ends_with(':') {
+            true => code = rest.trim_start(),
+            false => break,
+        }
+    }
+    let (opcode, operands) = match code.split_once(char::is_whitespace) {
+        Some((opcode, operands)) => (opcode, operands),
+        None => (code, ""),
+    };
+    let mut split = vec![];
+    let mut operand = String::new();
+    quoted = false;
+    for c in operands.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                split.push(operand.trim().to_string());
+                operand.clear();
+                continue;
+            }
+            _ => {}
+        }
+        operand.push(c);
+    }
+    if !operand.trim().is_empty() {
+        split.push(operand.trim().to_string());
+    }
+    (opcode.to_string(), split)
+}
+
+impl CPU<CPUType> {
+    /*
+     * Record the lines the VM executes from now on
+     */
+    pub fn start_trace(&mut self) {
+        self.tracer = Some(Tracer {
+            entries: vec![],
+            last: self.trace_state(),
+        });
+    }
+    /*
+     * Stop recording and return the trace
+     */
+    pub fn take_trace(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+    fn trace_state(&self) -> TraceState {
+        TraceState {
+            accumulator: self.accumulator,
+            port: self.port,
+            stack: self.stack.clone(),
+            psw: self.sfr.psw,
+        }
+    }
+    /*
+     * Called by the VM at the end of every line (at `address`) while tracing
+     */
+    pub fn trace_line(&mut self, program: &Program, address: usize) {
+        let state = self.trace_state();
+        let cycles = self.cycles;
+        let tracer = match &mut self.tracer {
+            Some(tracer) => tracer,
+            None => return,
+        };
+        // lines without an instruction (labels, braces) are left out
+        let empty = address > 0 && matches!(program.code[address - 1], Instruction::Begin);
+        if let Some((function, line)) = program.source_line(address).filter(|_| !empty) {
+            let (opcode, operands) = split_instruction(&function.source[line]);
+            tracer.entries.push(TraceEntry {
+                step: tracer.entries.len(),
+                function: function.name.clone(),
+                line: function.first_line + line + 1,
+                opcode,
+                operands,
+                cycles,
+                changes: tracer.last.changes(&state),
+            });
+        }
+        tracer.last = state;
+    }
+}
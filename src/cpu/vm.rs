@@ -0,0 +1,746 @@
+use {
+    crate::{
+        cpu::{
+            bytecode::{
+                compile, BitOperand, Callee, Condition, Instruction, Logic, Operand, PrintArg,
+                Program, StackOp, Target,
+            },
+            cpu_error,
+            main::CPU,
+            printx, set_source_line,
+            sfr::PSW_CY,
+            CPUType, PrintT, RuntimeError, Var, RETURN_ADDRESS_SIZE,
+        },
+        log,
+    },
+    serde::{Deserialize, Serialize},
+    std::rc::Rc,
+};
+
+/*
+ * A function, label or interrupt handler which is running. The VM keeps
+ * them on its own stack instead of calling itself, so a program can be
+ * paused at any line and resumed later.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    // address of the next instruction
+    pub pc: usize,
+    // a jump takes effect at the end of the line like in the interpreter
+    pub jump: Option<Target>,
+    pub kind: FrameKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FrameKind {
+    // the reset vector of a 8051 program, it was not called
+    Entry,
+    // the variables are cleared when a function returns
+    Call {
+        function: bool,
+    },
+    // the interrupted code gets its variables back afterwards
+    Interrupt {
+        function: bool,
+        handler: String,
+        depth: usize,
+        vars: Vec<Var<CPUType>>,
+        var_slots: Vec<Option<usize>>,
+    },
+}
+
+/*
+ * Where `run_frames` pauses, it always stops once the code returned
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pause {
+    Never,
+    Breakpoints,
+    // at the start of the next line (step into)
+    NextLine,
+    // at the next line of a function at this depth or below (step over)
+    Over(usize),
+    // at the next line below this depth (step out)
+    Out(usize),
+    // once this many lines started, nothing else stops a replay
+    Until(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    // the code returned, `runtime_error` tells if it was stopped
+    Finished,
+    // paused at the start of a line
+    Paused,
+    Breakpoint(usize),
+    // paused at the start of the line after the one which triggered it
+    Watchpoint(usize),
+    // stepping backwards reached the first line of the program
+    Start,
+}
+
+impl CPU<CPUType> {
+    /*
+     * The compiled program, it is compiled again after loading new code
+     */
+    pub fn program(&mut self) -> Rc<Program> {
+        match &self.program {
+            Some(program) => program.clone(),
+            None => {
+                let program = Rc::new(compile(&self.functions, self.dialect));
+                self.program = Some(program.clone());
+                program
+            }
+        }
+    }
+    /*
+     * Call a compiled function, its variables are cleared afterwards
+     */
+    pub fn call_function(&mut self, program: &Program, index: usize) {
+        let base = self.frames.len();
+        let kind = FrameKind::Call { function: true };
+        if self.push_call(program.functions[index].start, kind) {
+            self.run_frames(program, base, Pause::Never);
+        }
+    }
+    /*
+     * Run the code at `start` like a subroutine, returns false
+     * if there was no room for the return address
+     */
+    pub fn call_code(&mut self, program: &Program, start: usize) -> bool {
+        let base = self.frames.len();
+        if !self.push_call(start, FrameKind::Call { function: false }) {
+            return false;
+        }
+        self.run_frames(program, base, Pause::Never);
+        true
+    }
+    /*
+     * Execute the bytecode from `start` until it returns
+     */
+    pub fn run_code(&mut self, program: &Program, start: usize) {
+        let base = self.frames.len();
+        self.frames.push(Frame {
+            pc: start,
+            jump: None,
+            kind: FrameKind::Entry,
+        });
+        self.run_frames(program, base, Pause::Never);
+    }
+    /*
+     * Push the frame of a call, it fails if there is no room for the return address
+     */
+    pub fn push_call(&mut self, start: usize, kind: FrameKind) -> bool {
+        // the return address takes up space on a bounded stack
+        if !self.reserve_stack(RETURN_ADDRESS_SIZE) {
+            return false;
+        }
+        self.call_depth += 1;
+        self.frames.push(Frame {
+            pc: start,
+            jump: None,
+            kind,
+        });
+        true
+    }
+    fn return_from_frame(&mut self) {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        match frame.kind {
+            FrameKind::Entry => {}
+            FrameKind::Call { function } => self.returned_from_call(function),
+            FrameKind::Interrupt {
+                function,
+                handler,
+                depth,
+                vars,
+                var_slots,
+            } => {
+                self.returned_from_call(function);
+                self.returned_from_interrupt(&handler, depth, vars, var_slots);
+                // the interrupted line is only continued without errors
+                if self.runtime_error.is_some() || self.returning {
+                    self.return_from_frame();
+                }
+            }
+        }
+    }
+    fn returned_from_call(&mut self, function: bool) {
+        self.call_depth -= 1;
+        self.returning = false;
+        if function {
+            self.clear_vars();
+        }
+    }
+    pub fn clear_vars(&mut self) {
+        self.vars = vec![];
+        self.var_slots = vec![];
+    }
+    /*
+     * Point the variable slots of a newly compiled program at the
+     * variables which are still alive (e.g. between two lines of the REPL)
+     */
+    pub fn bind_vars(&mut self) {
+        let program = self.program();
+        self.var_slots = program
+            .variables
+            .iter()
+            .map(|name| self.vars.iter().rposition(|var| var.name() == name))
+            .collect();
+    }
+
+    /*
+     * Execute the frames above `base` until they returned or the VM
+     * pauses at the start of a line
+     */
+    pub fn run_frames(&mut self, program: &Program, base: usize, pause: Pause) -> Stop {
+        // the line the VM paused at is not checked again
+        let mut resumed = match pause {
+            Pause::Never => None,
+            _ => self.paused_at.take(),
+        };
+        let deadline = self.deadline();
+        let debugging = pause != Pause::Never;
+        while self.frames.len() > base {
+            let frame = self.frames.last_mut().unwrap();
+            let mut pc = frame.pc;
+            let mut jump = frame.jump.take();
+            let returned = loop {
+                let instruction = &program.code[pc];
+                pc += 1;
+                match instruction {
+                    Instruction::Begin => {
+                        if debugging && resumed.take() != Some(pc - 1) {
+                            if self.debugger.history.position
+                                >= self.debugger.history.next_checkpoint
+                            {
+                                let frame = self.frames.last_mut().unwrap();
+                                frame.pc = pc - 1;
+                                frame.jump = jump.clone();
+                                self.save_checkpoint(pc - 1);
+                            }
+                            if let Some(stop) = self.pause_at(pc - 1, pause) {
+                                let frame = self.frames.last_mut().unwrap();
+                                frame.pc = pc - 1;
+                                frame.jump = jump;
+                                self.paused_at = Some(pc - 1);
+                                return stop;
+                            }
+                        }
+                        if debugging {
+                            self.debugger.history.position += 1;
+                        }
+                        if self.cycle_limit.is_some_and(|limit| self.cycles >= limit) {
+                            self.runtime_error = Some(RuntimeError::CycleLimit);
+                            break true;
+                        }
+                        // lines without an instruction are free
+                        let empty = matches!(program.code[pc], Instruction::End(_));
+                        if !empty && !self.spend_instruction(deadline) {
+                            break true;
+                        }
+                        // nothing can interrupt the line while EA is cleared
+                        if self.ea() {
+                            let depth = self.frames.len();
+                            self.poll_interrupts();
+                            // the handler runs first, the line continues once it returned
+                            if self.frames.len() > depth {
+                                self.frames[depth - 1].pc = pc;
+                                self.frames[depth - 1].jump = jump;
+                                break false;
+                            }
+                        }
+                        if self.runtime_error.is_some() || self.returning {
+                            break true;
+                        }
+                    }
+                    Instruction::End(cycles) => {
+                        // the timers count the machine cycles of every instruction
+                        if *cycles > 0 {
+                            self.run_cycles(*cycles);
+                            self.interrupts.returned = false;
+                        }
+                        if self.tracer.is_some() {
+                            self.trace_line(program, pc - 1);
+                        }
+                        if !matches!(pause, Pause::Never | Pause::Until(_))
+                            && !self.debugger.watchpoints.is_empty()
+                        {
+                            self.check_watchpoints(pc - 1);
+                        }
+                        if self.returning {
+                            break true;
+                        }
+                        match jump.take() {
+                            Some(Target::Line(line)) => pc = line,
+                            Some(Target::Missing(name)) => {
+                                cpu_error();
+                                log!(Error, &format!("jump location `{name}` not found"));
+                                break true;
+                            }
+                            None => {}
+                        }
+                    }
+                    Instruction::Return => break true,
+                    Instruction::Push(operand) => {
+                        if let Some(value) = self.load_operand(operand) {
+                            self.push_stack(value);
+                        }
+                    }
+                    Instruction::Pop(destination) => {
+                        if let Some(value) = self.pop_stack() {
+                            if let Some(destination) = destination {
+                                self.store_operand(destination, value);
+                            }
+                        }
+                    }
+                    Instruction::Peek(destination) => {
+                        if let Some(&value) = self.stack.last() {
+                            match destination {
+                                Some(destination) => self.store_operand(destination, value),
+                                None => self.accumulator = value & self.word_mask,
+                            }
+                        } else {
+                            self.stack_underflow("peek");
+                        }
+                    }
+                    // ( a -- a a )
+                    Instruction::Dup => {
+                        if let Some(&a) = self.stack.last() {
+                            self.push_stack(a);
+                        } else {
+                            self.stack_underflow("dup");
+                        }
+                    }
+                    // ( a b -- b a )
+                    Instruction::Swap => {
+                        let len = self.stack.len();
+                        if len >= 2 {
+                            self.stack.swap(len - 1, len - 2);
+                        } else {
+                            self.stack_underflow("swap");
+                        }
+                    }
+                    Instruction::SwapNibbles => self.swap_nibbles(),
+                    // ( a b -- a b a )
+                    Instruction::Over => {
+                        let len = self.stack.len();
+                        if len >= 2 {
+                            self.push_stack(self.stack[len - 2]);
+                        } else {
+                            self.stack_underflow("over");
+                        }
+                    }
+                    // ( a b c -- b c a )
+                    Instruction::Rot => {
+                        let len = self.stack.len();
+                        if len >= 3 {
+                            self.stack[len - 3..].rotate_left(1);
+                        } else {
+                            self.stack_underflow("rot");
+                        }
+                    }
+                    // ( a -- )
+                    Instruction::Drop => {
+                        self.pop_stack();
+                    }
+                    Instruction::Stack(op) => self.stack_operation(*op),
+                    Instruction::Cjne(left, right, target) => {
+                        let (a, b) = (self.load_operand(left), self.load_operand(right));
+                        if let (Some(a), Some(b)) = (a, b) {
+                            // like on the 8051 the carry flag is set if the first value is smaller
+                            self.set_psw_flag(PSW_CY, a < b);
+                            if a != b {
+                                jump = Some(target.clone());
+                            }
+                        }
+                    }
+                    Instruction::Mov(destination, source) => {
+                        if let Some(value) = self.load_operand(source) {
+                            self.store_operand(destination, value);
+                        }
+                    }
+                    Instruction::MovxWrite(register) => {
+                        let address = match register {
+                            Some(r) => self.get_register(*r) as CPUType,
+                            None => self.sfr.dptr as CPUType,
+                        };
+                        self.write_external(address, self.accumulator);
+                    }
+                    Instruction::MovxRead(register) => {
+                        let address = match register {
+                            Some(r) => self.get_register(*r) as CPUType,
+                            None => self.sfr.dptr as CPUType,
+                        };
+                        if let Some(x) = self.read_external(address) {
+                            self.accumulator = x as CPUType;
+                        }
+                    }
+                    Instruction::Djnz(operand, target) => {
+                        if let Some(value) = self.load_operand(operand) {
+                            let value = value.wrapping_sub(1) & self.word_mask;
+                            self.store_operand(operand, value);
+                            if value != 0 {
+                                jump = Some(target.clone());
+                            }
+                        }
+                    }
+                    Instruction::Jump(target) => jump = Some(target.clone()),
+                    Instruction::JumpIf(condition, target) => {
+                        let condition = match condition {
+                            Condition::Zero => self.accumulator == 0,
+                            Condition::NotZero => self.accumulator != 0,
+                            Condition::Carry => self.psw_flag(PSW_CY),
+                            Condition::NotCarry => !self.psw_flag(PSW_CY),
+                        };
+                        if condition {
+                            jump = Some(target.clone());
+                        }
+                    }
+                    Instruction::JumpBit(bit, set, target) => {
+                        if self.write_bit_operand(bit, |bit| bit) == Some(*set) {
+                            jump = Some(target.clone());
+                        }
+                    }
+                    Instruction::Jbc(bit, target) => {
+                        if self.write_bit_operand(bit, |_| false) == Some(true) {
+                            jump = Some(target.clone());
+                        }
+                    }
+                    Instruction::Xch(accu, operand) => {
+                        let (a, b) = (self.load_operand(accu), self.load_operand(operand));
+                        if let (Some(a), Some(b)) = (a, b) {
+                            self.store_operand(accu, b);
+                            self.store_operand(operand, a);
+                        }
+                    }
+                    Instruction::Xchd(accu, operand) => {
+                        let (a, b) = (self.load_operand(accu), self.load_operand(operand));
+                        if let (Some(a), Some(b)) = (a, b) {
+                            self.store_operand(accu, (a & !0x0F) | (b & 0x0F));
+                            self.store_operand(operand, (b & !0x0F) | (a & 0x0F));
+                        }
+                    }
+                    Instruction::Da => self.decimal_adjust(),
+                    Instruction::IncDec(operand, inc) => {
+                        if let Some(value) = self.load_operand(operand) {
+                            let value = match inc {
+                                true => value.wrapping_add(1),
+                                false => value.wrapping_sub(1),
+                            };
+                            match operand {
+                                Operand::Dptr => self.store_operand(operand, value & 0xFFFF),
+                                _ => self.store_operand(operand, value & self.word_mask),
+                            }
+                        }
+                    }
+                    Instruction::Add(source, carry) => {
+                        if let Some(value) = self.load_operand(source) {
+                            self.add_accumulator(value, *carry);
+                        }
+                    }
+                    Instruction::Subb(source) => {
+                        if let Some(value) = self.load_operand(source) {
+                            self.subb_accumulator(value);
+                        }
+                    }
+                    Instruction::Logic(logic, destination, source) => {
+                        let (a, b) = (self.load_operand(destination), self.load_operand(source));
+                        if let (Some(a), Some(b)) = (a, b) {
+                            let value = match logic {
+                                Logic::And => a & b,
+                                Logic::Or => a | b,
+                                Logic::Xor => a ^ b,
+                            };
+                            self.store_operand(destination, value);
+                        }
+                    }
+                    Instruction::BitLogic(logic, destination, source) => {
+                        let a = self.write_bit_operand(destination, |bit| bit);
+                        let b = self.write_bit_operand(source, |bit| bit);
+                        if let (Some(a), Some(b)) = (a, b) {
+                            let value = match logic {
+                                Logic::And => a && b,
+                                Logic::Or => a || b,
+                                Logic::Xor => a != b,
+                            };
+                            self.write_bit_operand(destination, |_| value);
+                        }
+                    }
+                    Instruction::Rotate(left, carry) => self.rotate_accumulator(*left, *carry),
+                    Instruction::Mul => self.mul_ab(),
+                    Instruction::Div => self.div_ab(),
+                    Instruction::ClearA => self.accumulator = 0,
+                    Instruction::ComplementA => {
+                        self.accumulator = !self.accumulator & self.word_mask
+                    }
+                    Instruction::WriteBit(bit, value) => {
+                        match value {
+                            Some(value) => self.write_bit_operand(bit, |_| *value),
+                            None => self.write_bit_operand(bit, |bit| !bit),
+                        };
+                    }
+                    Instruction::Ret => self.returning = true,
+                    Instruction::Reti => self.reti(),
+                    Instruction::Print(argument) => self.print(argument),
+                    Instruction::Call(callee) => {
+                        let (start, function) = match callee {
+                            Callee::Function(index) => (program.functions[*index].start, true),
+                            Callee::Label(start) => (*start, false),
+                            Callee::MissingFunction(name) => {
+                                cpu_error();
+                                log!(Error, &format!("function `{name}` not found"));
+                                continue;
+                            }
+                            Callee::MissingLabel(name) => {
+                                cpu_error();
+                                log!(Error, &format!("jump location `{name}` not found"));
+                                continue;
+                            }
+                        };
+                        let frame = self.frames.last_mut().unwrap();
+                        frame.pc = pc;
+                        frame.jump = jump.clone();
+                        if self.push_call(start, FrameKind::Call { function }) {
+                            break false;
+                        }
+                    }
+                    Instruction::Let(slot, var) => match var {
+                        Some(var) => {
+                            if self.var_slots.len() <= *slot {
+                                self.var_slots.resize(slot + 1, None);
+                            }
+                            self.var_slots[*slot] = Some(self.vars.len());
+                            self.vars.push(var.clone());
+                        }
+                        None => {
+                            printx(
+                                PrintT::Error,
+                                "You can only store Strings and Numbers inside a Variable",
+                            );
+                            cpu_error();
+                        }
+                    },
+                    // Prints a new Line
+                    Instruction::NewLine => {
+                        log!(Clear, "\n");
+                    }
+                    Instruction::Fail {
+                        count,
+                        error,
+                        syntax,
+                    } => {
+                        if *count {
+                            cpu_error();
+                        }
+                        set_source_line(program.file_line(pc - 1));
+                        printx(PrintT::Error, error);
+                        if let Some(syntax) = syntax {
+                            printx(PrintT::Syntax, syntax);
+                        }
+                        set_source_line(None);
+                    }
+                }
+            };
+            if returned {
+                self.return_from_frame();
+            }
+        }
+        // a watchpoint in the last line of the program
+        match pause {
+            Pause::Never => Stop::Finished,
+            _ => match self.debugger.triggered.take() {
+                Some(id) => Stop::Watchpoint(id),
+                None => Stop::Finished,
+            },
+        }
+    }
+
+    /*
+     * The variable in a slot of the compiled program
+     */
+    fn var(&self, slot: usize) -> Option<&Var<CPUType>> {
+        self.var_slots
+            .get(slot)
+            .copied()
+            .flatten()
+            .and_then(|index| self.vars.get(index))
+    }
+    fn var_name(&self, slot: usize) -> String {
+        match &self.program {
+            Some(program) => program.variables[slot].clone(),
+            None => String::new(),
+        }
+    }
+
+    /*
+     * Resolve the value of an operand
+     */
+    fn load_operand(&mut self, operand: &Operand) -> Option<CPUType> {
+        match operand {
+            Operand::Number(x) => Some(*x),
+            Operand::Accumulator => Some(self.accumulator),
+            Operand::Register(r) => Some(self.get_register(*r) as CPUType),
+            Operand::Indirect(r) => self.read_indirect(*r).map(|x| x as CPUType),
+            Operand::Address(address) => self.read_direct(*address).map(|x| x as CPUType),
+            Operand::Port(port) => Some(self.port[*port]),
+            Operand::InvalidPort(name) => {
+                cpu_error();
+                log!(Error, &format!("Invalid Port `{name}`"));
+                None
+            }
+            Operand::Stack => {
+                let top = self.stack.last().copied();
+                if top.is_none() {
+                    self.stack_underflow("Stack");
+                }
+                top
+            }
+            Operand::Sfr(address) => self.read_sfr(*address),
+            Operand::Bit(address) => self.read_bit_address(*address).map(|x| x as CPUType),
+            Operand::Dptr => Some(self.sfr.dptr as CPUType),
+            Operand::Var(slot) => match self.var(*slot) {
+                Some(Var::Number(x)) => Some(x.value),
+                Some(Var::String(_)) => {
+                    cpu_error();
+                    let name = self.var_name(*slot);
+                    log!(Error, &format!("`{name}` is not a number variable"));
+                    None
+                }
+                None => {
+                    cpu_error();
+                    let name = self.var_name(*slot);
+                    log!(Error, &format!("cannot find value `{name}` in this scope"));
+                    None
+                }
+            },
+        }
+    }
+
+    /*
+     * Store a value inside the Accumulator, a Port,
+     * a register or the internal RAM
+     */
+    fn store_operand(&mut self, operand: &Operand, value: CPUType) {
+        match operand {
+            Operand::Accumulator => self.accumulator = value & self.word_mask,
+            Operand::Register(r) => self.set_register(*r, value),
+            Operand::Indirect(r) => self.write_indirect(*r, value),
+            // a number as destination is always a direct address
+            Operand::Address(address) | Operand::Number(address) => {
+                self.write_direct(*address, value)
+            }
+            Operand::Sfr(address) => self.write_sfr(*address, value),
+            Operand::Bit(address) => self.write_bit_address(*address, value != 0),
+            Operand::Dptr => self.sfr.dptr = value as u16,
+            Operand::Port(port) => self.port[*port] = value & self.word_mask,
+            Operand::InvalidPort(name) => {
+                cpu_error();
+                log!(Error, &format!("Invalid Port `{name}`"));
+            }
+            Operand::Stack | Operand::Var(_) => {
+                cpu_error();
+                log!(Error, "Expected Port or Accu!");
+            }
+        }
+    }
+
+    /*
+     * Change a single bit of a port or a named bit, returns the old value
+     */
+    fn write_bit_operand(&mut self, bit: &BitOperand, f: impl Fn(bool) -> bool) -> Option<bool> {
+        match bit {
+            BitOperand::Bit(address) => {
+                let old = self.read_bit_address(*address)?;
+                self.write_bit_address(*address, f(old));
+                Some(old)
+            }
+            BitOperand::PortBit(port, bit) => {
+                let old = self.port[*port] & (1 << bit) != 0;
+                if f(old) {
+                    self.port[*port] |= (1 << bit) & self.word_mask;
+                } else {
+                    self.port[*port] &= !(1 << bit);
+                }
+                Some(old)
+            }
+            BitOperand::Unknown(name) => {
+                cpu_error();
+                log!(Error, &format!("Unknown bit `{name}`"));
+                None
+            }
+        }
+    }
+
+    /*
+     * Pop the top 2 numbers and push the result
+     */
+    fn stack_operation(&mut self, op: StackOp) {
+        let name = match op {
+            StackOp::Adds => "adds",
+            StackOp::Subs => "subs",
+            StackOp::Muls => "muls",
+            StackOp::Divs => "divs",
+            StackOp::Mods => "mods",
+            StackOp::Cmp => "cmp",
+        };
+        if let Some((a, b)) = self.pop_two(name) {
+            let value = match op {
+                StackOp::Adds => a.wrapping_add(b),
+                StackOp::Subs => a.wrapping_sub(b),
+                StackOp::Muls => a.wrapping_mul(b),
+                StackOp::Divs | StackOp::Mods if b == 0 => {
+                    cpu_error();
+                    log!(Error, &format!("Division by zero in {name}"));
+                    return;
+                }
+                StackOp::Divs => a / b,
+                StackOp::Mods => a % b,
+                // 1 if they are equal, 0 otherwise
+                StackOp::Cmp => (a == b) as CPUType,
+            };
+            self.push_stack(value);
+        }
+    }
+
+    fn print(&mut self, argument: &PrintArg) {
+        match argument {
+            PrintArg::String(value) => printx(PrintT::Clear, value),
+            PrintArg::Number(x) => printx(PrintT::Clear, &format!("{x}")),
+            PrintArg::Accumulator => printx(PrintT::Clear, &format!("{}", self.accumulator)),
+            PrintArg::Stack => printx(PrintT::Clear, &format!("{:?}", self.stack)),
+            PrintArg::Port(Some(port)) => printx(PrintT::Clear, &format!("{}", self.port[*port])),
+            PrintArg::Port(None) => {
+                cpu_error();
+                log!(Error, "Invalid Port");
+            }
+            PrintArg::Var(slot, line, line_number) => match self.var(*slot) {
+                Some(Var::Number(x)) => printx(PrintT::Clear, &format!("{}", x.value)),
+                Some(Var::String(x)) => printx(PrintT::Clear, &x.value),
+                None => {
+                    cpu_error();
+                    let name = self.var_name(*slot);
+                    self.cpu_line_error(
+                        &format!("cannot find value `{name}` in this scope"),
+                        line.clone(),
+                        *line_number,
+                        1,
+                        "not found in this scope",
+                    );
+                }
+            },
+            PrintArg::Missing(line, line_number) => {
+                cpu_error();
+                self.cpu_line_error(
+                    "expected token after prnt statement",
+                    line.clone(),
+                    *line_number,
+                    1,
+                    "",
+                );
+            }
+        }
+    }
+}
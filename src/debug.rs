@@ -0,0 +1,260 @@
+use {
+    crate::cpu::{debugger::Value, main::CPU, vm::Stop, CPUType},
+    rustyline::{error::ReadlineError, Editor},
+};
+
+pub const HELP: &str = "\
+  break <line | function | label> [if <condition>]
+                  Set a breakpoint, e.g. `break loop if P1 == 3`
+  watch <value> [<op> <number>]
+                  Stop when a value changes or the comparison becomes true,
+                  e.g. `watch P2` or `watch A == 0`
+  delete <id>     Remove a breakpoint or a watchpoint
+  info            List the breakpoints and watchpoints
+  step            Run until the next line, calls are entered
+  next            Run until the next line of this function
+  finish          Run until this function returned
+  continue        Run until a breakpoint or the end of the program
+  step-back       Go back to the previous line
+  reverse-continue
+                  Go back to the last breakpoint or watchpoint before this line
+  print [value]   Print the state of the CPU or a value (`P1`, `A`, `[30h]`, ...)
+  where           Print the running functions
+  list            Print the lines around the current line
+  run             Start the program again
+  quit            Leave the debugger
+
+Conditions compare values with ==, !=, <, <=, > and >= and are joined
+with && and ||. An empty line repeats the last command.";
+
+/*
+ * The debugger mode of the command line, the loaded program
+ * starts paused at its first line
+ */
+pub struct Session {
+    pub cpu: CPU<CPUType>,
+    // the state before the program started for `run`
+    initial: CPU<CPUType>,
+    last_command: String,
+}
+
+impl Session {
+    pub fn new(mut cpu: CPU<CPUType>) -> Session {
+        let initial = cpu.clone();
+        cpu.debug_main();
+        Session {
+            cpu,
+            initial,
+            last_command: String::new(),
+        }
+    }
+    /*
+     * Evaluate a command, returns false once the session ends
+     */
+    pub fn eval(&mut self, line: &str) -> bool {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command = line.clone();
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.as_str(), ""),
+        };
+        match command {
+            "b" | "break" => {
+                let (location, condition) = match argument.split_once(" if ") {
+                    Some((location, condition)) => (location.trim(), Some(condition)),
+                    None => (argument, None),
+                };
+                match self.cpu.add_breakpoint(location, condition) {
+                    Ok(id) => {
+                        let address = self.cpu.debugger.breakpoints.last().unwrap().address;
+                        match self.cpu.source_location(address) {
+                            Some(at) => println!("Breakpoint {id} at {at}"),
+                            None => println!("Breakpoint {id}"),
+                        }
+                    }
+                    Err(error) => println!("{error}"),
+                }
+            }
+            "wa" | "watch" => match self.cpu.add_watchpoint(argument) {
+                Ok(id) => {
+                    let watchpoint = self.cpu.debugger.watchpoints.last().unwrap();
+                    match watchpoint.last {
+                        Some(x) => println!("Watchpoint {id}: {argument} (now {x})"),
+                        None => println!("Watchpoint {id}: {argument}"),
+                    }
+                }
+                Err(error) => println!("{error}"),
+            },
+            "d" | "delete" => match argument.parse::<usize>() {
+                Ok(id) if self.cpu.remove_breakpoint(id) => println!("Deleted breakpoint {id}"),
+                _ => println!("No breakpoint `{argument}`"),
+            },
+            "i" | "info" => {
+                for breakpoint in &self.cpu.debugger.breakpoints {
+                    let condition = match &breakpoint.condition {
+                        Some((condition, _)) => format!(" if {condition}"),
+                        None => String::new(),
+                    };
+                    println!(
+                        "{:>3}  {}{condition}  ({} hits)",
+                        breakpoint.id, breakpoint.location, breakpoint.hits
+                    );
+                }
+                for watchpoint in &self.cpu.debugger.watchpoints {
+                    println!(
+                        "{:>3}  watch {}  ({} hits)",
+                        watchpoint.id, watchpoint.expression, watchpoint.hits
+                    );
+                }
+            }
+            "s" | "step" => self.stopped(|cpu| cpu.step()),
+            "n" | "next" => self.stopped(|cpu| cpu.step_over()),
+            "f" | "finish" => self.stopped(|cpu| cpu.finish()),
+            "c" | "continue" => self.stopped(|cpu| cpu.resume()),
+            "sb" | "step-back" => self.went_back(|cpu| cpu.step_back()),
+            "rc" | "reverse-continue" => self.went_back(|cpu| cpu.reverse_continue()),
+            "p" | "print" if argument.is_empty() => print!("{}", self.cpu),
+            "p" | "print" => match Value::parse(argument) {
+                Ok(value) => match self.cpu.value(&value) {
+                    Some(x) => println!("{argument} = {x} (0x{x:x})"),
+                    None => println!("{argument} is not set"),
+                },
+                Err(error) => println!("{error}"),
+            },
+            "w" | "where" | "bt" => {
+                for (i, location) in self.cpu.backtrace().iter().enumerate() {
+                    println!("#{i}  {location}");
+                }
+            }
+            "l" | "list" => self.list(),
+            "r" | "run" => {
+                let breakpoints = self.cpu.debugger.clone();
+                self.cpu = self.initial.clone();
+                self.cpu.debugger = breakpoints;
+                self.cpu.debug_main();
+                self.show_location();
+            }
+            "q" | "quit" => return false,
+            "h" | "help" => println!("{HELP}"),
+            _ => println!("Unknown command `{command}`, see `help`"),
+        }
+        true
+    }
+    fn stopped(&mut self, run: impl FnOnce(&mut CPU<CPUType>) -> Stop) {
+        if self.cpu.frames.is_empty() {
+            println!("The program is not running, `run` starts it again");
+            return;
+        }
+        let stop = run(&mut self.cpu);
+        self.show_stop(stop);
+    }
+    fn show_stop(&mut self, stop: Stop) {
+        match stop {
+            Stop::Finished => match &self.cpu.runtime_error {
+                Some(error) => println!("Program stopped: {error}"),
+                None => println!("Program finished"),
+            },
+            Stop::Breakpoint(id) => {
+                print!("Breakpoint {id}, ");
+                self.show_location();
+            }
+            Stop::Watchpoint(id) => {
+                self.show_watchpoint(id);
+                self.show_location();
+            }
+            Stop::Paused => self.show_location(),
+            Stop::Start => {
+                print!("Reached the start of the program, ");
+                self.show_location();
+            }
+        }
+    }
+    /*
+     * Stepping backwards also works after the program finished
+     */
+    fn went_back(&mut self, run: impl FnOnce(&mut CPU<CPUType>) -> Stop) {
+        let stop = run(&mut self.cpu);
+        self.show_stop(stop);
+    }
+    /*
+     * e.g. `Watchpoint 1: P2 0 -> 5 (changed by main:7  mov P2, A)`
+     */
+    fn show_watchpoint(&mut self, id: usize) {
+        let watchpoint = match self.cpu.debugger.watchpoints.iter().find(|w| w.id == id) {
+            Some(watchpoint) => watchpoint.clone(),
+            None => return,
+        };
+        let show = |value: Option<CPUType>| match value {
+            Some(x) => x.to_string(),
+            None => "unset".to_string(),
+        };
+        let changed_by = watchpoint
+            .changed_at
+            .and_then(|address| self.cpu.source_location(address))
+            .map(|at| format!(" (changed by {at})"))
+            .unwrap_or_default();
+        println!(
+            "Watchpoint {id}: {} {} -> {}{changed_by}",
+            watchpoint.expression,
+            show(watchpoint.old),
+            show(watchpoint.last)
+        );
+    }
+    fn show_location(&mut self) {
+        match self.cpu.location() {
+            Some(location) => println!("{location}"),
+            None => println!("Program finished"),
+        }
+    }
+    /*
+     * The current line with 3 lines before and after it
+     */
+    fn list(&mut self) {
+        let pc = match self.cpu.frames.last() {
+            Some(frame) => frame.pc,
+            None => return,
+        };
+        let program = self.cpu.program();
+        if let Some((function, current)) = program.source_line(pc) {
+            let start = current.saturating_sub(3);
+            let end = (current + 4).min(function.source.len());
+            for line in start..end {
+                let marker = if line == current { "=>" } else { "  " };
+                let number = function.first_line + line + 1;
+                println!("{marker} {number:>4}  {}", function.source[line]);
+            }
+        }
+    }
+}
+
+pub fn run(mut session: Session) -> i32 {
+    let mut editor = match Editor::<()>::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("Unable to start the debugger: {error}");
+            return 1;
+        }
+    };
+    println!("`help` lists the commands");
+    session.show_location();
+    loop {
+        match editor.readline("(rdb) ") {
+            Ok(line) => {
+                editor.add_history_entry(line.as_str());
+                if !session.eval(&line) {
+                    break;
+                }
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("{error}");
+                break;
+            }
+        }
+    }
+    0
+}
@@ -0,0 +1,224 @@
+use crate::{
+    asm::listing::{Symbol, SymbolKind},
+    cpu::{
+        lexer_error,
+        sfr::{bit_address, sfr_by_name},
+    },
+    lexer::parse_number,
+    log,
+};
+use serde::{Deserialize, Serialize};
+
+/*
+ * The syntax a program is written in
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Dialect {
+    // russembly with functions (`fn main() { ... }`)
+    #[default]
+    Russembly,
+    // standard 8051 assembly (`ORG`, `EQU`, `;` comments, ...)
+    I8051,
+}
+
+/*
+ * A 8051 program translated into lines the lexer understands
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Program8051 {
+    pub lines: Vec<String>,
+    // (code address, line) of every `ORG` directive
+    pub origins: Vec<(usize, usize)>,
+    // symbols defined with `EQU`, `const`, ... with their final value
+    pub symbols: Vec<Symbol>,
+}
+
+/*
+ * Translate 8051 assembly into russembly lines. Every source line becomes
+ * exactly one line, so line numbers in diagnostics stay the same.
+ *
+ * - comments start with `;`
+ * - `DB` and `DW` are left to the assembler
+ * - mnemonics, registers and SFR names are case insensitive
+ * - `ORG`, `END` and the symbol directives `EQU`, `BIT`, `DATA`, `SET`
+ * - `const NAME = value` and `global NAME = value`
+ * - `sjmp`/`ljmp`/`ajmp` and `lcall`/`acall` jump to and call labels
+ */
+pub fn translate_8051(source: &str) -> Program8051 {
+    let mut program = Program8051::default();
+    let mut symbols: Vec<(String, String)> = vec![];
+    let mut ended = false;
+    for (line_number, source_line) in source.lines().enumerate() {
+        if ended {
+            program.lines.push(String::new());
+            continue;
+        }
+        let code = strip_comment(source_line).replace('\t', " ");
+        let (label, code) = split_label(code.trim());
+        let mut words = code.splitn(2, ' ');
+        let mnemonic = words.next().unwrap_or("");
+        let operands = words.next().unwrap_or("").trim();
+        let mut line = match label {
+            Some(label) => format!("{label}: "),
+            None => String::new(),
+        };
+        // `NAME EQU value`, or `const NAME = value` like in russembly
+        let definition = match mnemonic {
+            "const" | "global" => operands
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), "EQU", value)),
+            _ => operands
+                .split_once(' ')
+                .map(|(directive, value)| (mnemonic, directive, value)),
+        };
+        if let Some((mnemonic, directive, value)) = definition {
+            if matches!(
+                directive.to_uppercase().as_str(),
+                "EQU" | "BIT" | "DATA" | "SET"
+            ) {
+                let value = substitute(value.trim(), &symbols);
+                symbols.retain(|(name, _)| name != mnemonic);
+                symbols.push((mnemonic.to_string(), value.clone()));
+                program.symbols.retain(|symbol| symbol.name != mnemonic);
+                program.symbols.push(Symbol {
+                    name: mnemonic.to_string(),
+                    kind: match code.starts_with("global ") {
+                        true => SymbolKind::Global,
+                        false => SymbolKind::Const,
+                    },
+                    value,
+                });
+                program.lines.push(line);
+                continue;
+            }
+        }
+        match mnemonic.to_uppercase().as_str() {
+            "" => {}
+            "ORG" => match parse_number(operands) {
+                Some(address) => program.origins.push((address, line_number)),
+                None => {
+                    let ln = line_number + 1;
+                    log!(
+                        Error,
+                        f("Invalid address `{operands}` after ORG at line {ln}")
+                    );
+                    lexer_error();
+                }
+            },
+            "END" => ended = true,
+            mnemonic => {
+                let mnemonic = mnemonic.to_lowercase();
+                let operands: Vec<String> = split_operands(operands)
+                    .into_iter()
+                    .map(|operand| normalize_operand(operand.trim(), &symbols))
+                    .filter(|operand| !operand.is_empty())
+                    .collect();
+                line.push_str(&format!("{} {}", mnemonic, operands.join(", ")));
+            }
+        }
+        program.lines.push(line.trim_end().to_string());
+    }
+    program
+}
+
+/*
+ * Split `label: code` into the label and the code
+ */
+fn split_label(code: &str) -> (Option<&str>, &str) {
+    match code.split_once(':') {
+        Some((label, rest))
+            if !label.is_empty()
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            (Some(label), rest.trim())
+        }
+        _ => (None, code),
+    }
+}
+
+/*
+ * The part of a line before its `;` comment, a `;` inside quotes is no comment
+ */
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            (';', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/*
+ * Split the operands at the commas which are not inside quotes (`DB 'a,b'`)
+ */
+fn split_operands(operands: &str) -> Vec<&str> {
+    let mut quote = None;
+    let mut start = 0;
+    let mut parts = vec![];
+    for (i, c) in operands.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            (',', None) => {
+                parts.push(&operands[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&operands[start..]);
+    parts
+}
+
+/*
+ * Replace symbols defined with `EQU` (also as the byte of a bit like `FLAGS.1`)
+ */
+fn substitute(operand: &str, symbols: &[(String, String)]) -> String {
+    let lookup = |name: &str| {
+        symbols
+            .iter()
+            .find(|(symbol, _)| symbol.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    };
+    if let Some(value) = lookup(operand) {
+        return value;
+    }
+    match operand.split_once('.') {
+        Some((byte, bit)) => match lookup(byte) {
+            Some(value) => format!("{value}.{bit}"),
+            None => operand.to_string(),
+        },
+        None => operand.to_string(),
+    }
+}
+
+/*
+ * Resolve symbols and write registers, SFRs and bits the way the lexer
+ * expects them (e.g. `r0` -> `R0`, `c` -> `CY`, `#COUNT` -> `#5`)
+ */
+fn normalize_operand(operand: &str, symbols: &[(String, String)]) -> String {
+    if operand.starts_with(['\'', '"']) {
+        return operand.to_string();
+    }
+    if let Some(immediate) = operand.strip_prefix('#') {
+        return format!("#{}", substitute(immediate, symbols));
+    }
+    let operand = substitute(operand, symbols);
+    let upper = operand.to_uppercase();
+    let known =
+        matches!(
+            upper.as_str(),
+            "A" | "AB" | "DPTR" | "@DPTR" | "@A+DPTR" | "@R0" | "@R1"
+        ) || (upper.len() == 2 && upper.starts_with('R') && upper.as_bytes()[1].is_ascii_digit())
+            || sfr_by_name(&upper).is_some()
+            || bit_address(&upper).is_some();
+    match upper.as_str() {
+        "C" => "CY".to_string(),
+        _ if known => upper,
+        _ => operand,
+    }
+}
@@ -1,35 +1,42 @@
 #![allow(dead_code)]
 
 use crate::{
-    cpu::{lexer_error, printx, CPUType, JumpLocation, PrintT},
+    cpu::{
+        lexer_error, printx,
+        sfr::{bit_address, sfr_by_name},
+        CPUType, JumpLocation, PrintT,
+    },
     log,
 };
 #[cfg(not(target_arch = "wasm32"))]
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 #[cfg(not(target_arch = "wasm32"))]
 use std::fmt::Write;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Line {
     pub tokens: Vec<Token>,
     pub as_string: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Function {
     pub name: String,
     pub arguments: Vec<Token>,
     pub lines: Vec<Line>,
+    // index of the first line of the body inside the source
+    pub line: usize,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Token {
     pub token_type: TokenType,
     pub value: String,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TokenType {
     OpCode,
     Accumulator,
@@ -42,6 +49,13 @@ pub enum TokenType {
     Keyword,
     String,
     Number(CPUType),
+    Register(usize),
+    Indirect(usize),
+    Address(CPUType),
+    Sfr(u8),
+    Bit(u8),
+    Dptr,
+    IndirectDptr,
     Comment,
     Comma,
     NewLine,
@@ -68,6 +82,11 @@ pub struct Lexer {
     strings: Vec<String>,
     pub syntax: HashMap<&'static str, &'static str>,
 }
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Lexer {
     #[cfg(not(target_arch = "wasm32"))]
     pub fn new() -> Lexer {
@@ -122,13 +141,18 @@ impl Lexer {
             let str = string_iter.next().unwrap();
             match str.as_str() {
                 "push" | "pop" | "mov" | "add" | "sub" | "mul" | "div" | "adds" | "subs"
-                | "muls" | "divs" | "djnzs" | "jmp" | "setb" | "end" | "prnt" => {
+                | "muls" | "divs" | "mods" | "djnzs" | "jmp" | "setb" | "end" | "prnt" | "dup"
+                | "swap" | "over" | "rot" | "drop" | "peek" | "cmp" | "cjne" | "movx" | "clr"
+                | "cpl" | "ret" | "reti" | "jb" | "jnb" | "jbc" | "djnz" | "jz" | "jnz" | "jc"
+                | "jnc" | "nop" | "inc" | "dec" | "addc" | "subb" | "anl" | "orl" | "xrl"
+                | "rl" | "rr" | "rlc" | "rrc" | "sjmp" | "ljmp" | "ajmp" | "xch" | "xchd"
+                | "da" => {
                     self.tokens.push(Token {
                         token_type: TokenType::OpCode,
                         value: str.to_string(),
                     });
                 }
-                "call" => {
+                "call" | "lcall" | "acall" => {
                     self.tokens.push(Token {
                         token_type: TokenType::OpCode,
                         value: str.to_string(),
@@ -222,12 +246,67 @@ impl Lexer {
                         value: "\n".to_string(),
                     });
                 }
+                // register R0 - R7 of the current register bank
+                "R0" | "R1" | "R2" | "R3" | "R4" | "R5" | "R6" | "R7" => {
+                    self.tokens.push(Token {
+                        token_type: TokenType::Register(str[1..].parse().unwrap()),
+                        value: str.to_string(),
+                    });
+                }
+                // indirect addressing through R0 or R1
+                "@R0" | "@R1" => {
+                    self.tokens.push(Token {
+                        token_type: TokenType::Indirect(str[2..].parse().unwrap()),
+                        value: str.to_string(),
+                    });
+                }
                 _ => {
-                    if str.starts_with("P") {
+                    if let Some(immediate) = str.strip_prefix('#') {
+                        if let Some(x) = parse_number(immediate) {
+                            self.tokens.push(Token {
+                                token_type: TokenType::Number(x),
+                                value: str.to_string(),
+                            });
+                        } else {
+                            let ln = self.line_number();
+                            log!(Error, f("Invalid immediate value `{str}` at line {ln}"));
+                            lexer_error();
+                        }
+                    } else if is_address(str) {
+                        self.tokens.push(Token {
+                            token_type: TokenType::Address(parse_number(str).unwrap()),
+                            value: str.to_string(),
+                        });
+                    } else if is_port(str) {
                         self.tokens.push(Token {
                             token_type: TokenType::Port,
                             value: str.to_string(),
                         });
+                    } else if let Some(address) = bit_address(str) {
+                        self.tokens.push(Token {
+                            token_type: TokenType::Bit(address),
+                            value: str.to_string(),
+                        });
+                    } else if let Some(sfr) = sfr_by_name(str) {
+                        self.tokens.push(Token {
+                            token_type: TokenType::Sfr(sfr.address),
+                            value: str.to_string(),
+                        });
+                    } else if str == "DPTR" || str == "@DPTR" {
+                        self.tokens.push(Token {
+                            token_type: if str == "DPTR" {
+                                TokenType::Dptr
+                            } else {
+                                TokenType::IndirectDptr
+                            },
+                            value: str.to_string(),
+                        });
+                    } else if str.starts_with('P')
+                        && str[1..].starts_with(|c: char| c.is_ascii_digit())
+                    {
+                        let ln = self.line_number();
+                        log!(Error, f("Unknown port `{str}` at line {ln}"));
+                        lexer_error();
                     } else if str.chars().nth(0) == Some(';') {
                         let mut comment = String::new();
                         while string_iter.peek().is_some() {
@@ -250,15 +329,19 @@ impl Lexer {
                             value: str.to_string(),
                         });
                     } else {
-                        match str.parse::<CPUType>() {
-                            Ok(x) => {
+                        match parse_constant(str) {
+                            Some(x) => {
                                 self.tokens.push(Token {
                                     token_type: TokenType::Number(x),
                                     value: str.to_string(),
                                 });
                             }
-                            Err(_) => {
-                                if !self.tokens.is_empty() {
+                            None => {
+                                if expects_bit(&self.tokens) {
+                                    let ln = self.line_number();
+                                    log!(Error, f("Unknown bit `{str}` at line {ln}"));
+                                    lexer_error();
+                                } else if !self.tokens.is_empty() {
                                     self.tokens.push(Token {
                                         token_type: TokenType::Generic,
                                         value: str.to_string(),
@@ -293,14 +376,16 @@ impl Lexer {
         let mut function_name = "";
         let mut function_arguments: Vec<Token> = vec![];
         let mut function_body: Vec<Line> = vec![];
+        let mut function_line = 0;
 
-        for line in &self.lines {
+        for (index, line) in self.lines.iter().enumerate() {
             if check_line_for_fn(line.clone()) {
                 if function_name != "" {
                     self.functions.push(Function {
                         name: function_name.to_string(),
                         arguments: function_arguments.clone(),
                         lines: function_body.clone(),
+                        line: function_line,
                     });
                     function_name = "";
                     function_arguments = vec![];
@@ -328,6 +413,7 @@ impl Lexer {
                                         break;
                                     }
                                     function_name = &fn_name.value;
+                                    function_line = index + 1;
                                     // generate function arguments
                                     while tokens.peek().is_some() {
                                         let temp = tokens.next().unwrap();
@@ -370,6 +456,7 @@ impl Lexer {
                 name: function_name.to_string(),
                 arguments: function_arguments.clone(),
                 lines: function_body.clone(),
+                line: function_line,
             })
         }
         //Some(self.functions.clone())
@@ -460,3 +547,76 @@ fn check_line_for_fn(line: Line) -> bool {
     }
     false
 }
+
+/*
+ * Parse a decimal number, a hexadecimal number with a `h` suffix (e.g. `30h`)
+ * or a binary number with a `b` suffix (e.g. `1010b`)
+ */
+pub fn parse_number(str: &str) -> Option<CPUType> {
+    if let Some(hex) = str.strip_suffix(['h', 'H']) {
+        return CPUType::from_str_radix(hex, 16).ok();
+    }
+    match str.strip_suffix(['b', 'B']) {
+        Some(binary) if !binary.is_empty() => CPUType::from_str_radix(binary, 2).ok(),
+        _ => str.parse::<CPUType>().ok(),
+    }
+}
+
+/*
+ * Without `#` the radix decides: direct addresses are written as hexadecimal
+ * numbers (e.g. `mov A, 0Fh` reads the internal RAM at 0Fh) while decimal and
+ * binary numbers are constants (e.g. `mov A, 15` and `mov A, 1111b` load 15).
+ * With `#` every radix is a constant (e.g. `#0Fh`).
+ */
+fn is_address(str: &str) -> bool {
+    str.starts_with(|c: char| c.is_ascii_digit())
+        && str.ends_with(['h', 'H'])
+        && parse_number(str).is_some()
+}
+
+/*
+ * A decimal or binary constant, a binary number starts with a digit so
+ * names like `fb` stay names
+ */
+fn parse_constant(str: &str) -> Option<CPUType> {
+    match str.strip_suffix(['b', 'B']) {
+        Some(binary) if binary.starts_with(|c: char| c.is_ascii_digit()) => {
+            CPUType::from_str_radix(binary, 2).ok()
+        }
+        _ => str.parse::<CPUType>().ok(),
+    }
+}
+
+/*
+ * A port is written as `P0` - `P7`, single bits of the wide ports
+ * which are no SFR bits can be accessed with `P5.3` or `P0^10`
+ */
+fn is_port(str: &str) -> bool {
+    let port = match str.strip_prefix('P') {
+        Some(port) => port,
+        None => return false,
+    };
+    let (port, bit) = match port.split_once(['.', '^']) {
+        Some((port, bit)) => (port, Some(bit)),
+        None => (port, None),
+    };
+    let valid_port = matches!(port.parse::<usize>(), Ok(p) if p < 8);
+    let valid_bit = match bit {
+        Some(bit) => matches!(bit.parse::<u32>(), Ok(b) if b < CPUType::BITS),
+        None => true,
+    };
+    valid_port && valid_bit && (bit.is_none() || bit_address(str).is_none())
+}
+
+/*
+ * Check if the last token is an opcode which expects a bit
+ */
+fn expects_bit(tokens: &[Token]) -> bool {
+    match tokens.last() {
+        Some(token) if token.token_type == TokenType::OpCode => matches!(
+            token.value.as_str(),
+            "setb" | "clr" | "cpl" | "jb" | "jnb" | "jbc"
+        ),
+        _ => false,
+    }
+}
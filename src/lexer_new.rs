@@ -98,6 +98,11 @@ pub struct Brackets {
     pub square: i32,
     pub braces: i32,
 }
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 // -----------------------------------------------------------------------
 // Lexer implementation
 // -----------------------------------------------------------------------
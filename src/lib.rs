@@ -1,36 +1,39 @@
-#![cfg(target_arch = "wasm32")]
+//! russembly as a library, see `api` for the API native Rust programs
+//! use and `wasm` for the bindings of the web UI built on top of it.
+//! A program is parsed once and runs on any number of CPUs:
+//!
+//! ```
+//! use russembly::{Cpu, Program};
+//!
+//! let program = Program::parse("fn main() {\n mov P1, #5\n}").unwrap();
+//! let mut cpu = Cpu::new(&program);
+//! cpu.run().unwrap();
+//! assert_eq!(cpu.port(1), Some(5));
+//! ```
+mod api;
+mod asm;
 mod cpu;
+mod dialect;
 mod lexer;
 mod lexer_new;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+// the `russembly` command line tool, `cli::run` is all the binary calls
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+pub mod cli;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+mod debug;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+mod repl;
+#[cfg(test)]
+mod test;
 
-use wasm_bindgen::prelude::*;
-
-#[wasm_bindgen]
-pub struct RussemblyWasm {
-    cpu_json: String,
-}
-#[wasm_bindgen]
-impl RussemblyWasm {
-    #[wasm_bindgen(constructor)]
-    pub fn new() -> RussemblyWasm {
-        RussemblyWasm {
-            cpu_json: String::from(""),
-        }
-    }
-    pub fn run_rusm(&mut self, code: &str) -> String {
-        let mut cpu = match CPU::new() {
-            Ok(cpu) => cpu,
-            Err(_) => {
-                return "".to_string();
-            }
-        };
-        if let Some(()) = cpu.load_string(code) {
-            cpu.run_main();
-        }
-        self.cpu_json = cpu.get_json();
-        return get_global_output();
-    }
-    pub fn get_cpu_json(self) -> String {
-        self.cpu_json
-    }
-}
+pub use {
+    api::{Cpu, Program, Status},
+    cpu::{
+        diagnostic::Diagnostic,
+        state::{State, VarState, VarValue},
+        Budget, CPUType, Message, RuntimeError,
+    },
+    dialect::Dialect,
+};
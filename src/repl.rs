@@ -0,0 +1,255 @@
+use {
+    crate::cpu::{main::CPU, CPUType},
+    rustyline::{error::ReadlineError, Editor},
+};
+
+/*
+ * Every line of the REPL is compiled as the body of this function,
+ * the functions defined in the session can be called from it
+ */
+const LINE_FUNCTION: &str = "repl_line";
+
+pub const HELP: &str = "\
+Enter instructions (e.g. `mov A, 5`) or define functions with `fn name() {` ... `}`.
+
+  :state          Print the state of the CPU
+  :ports          Print the ports
+  :stack          Print the stack
+  :fns            List the defined functions
+  :load <file>    Define the functions of a file (`call main` runs it)
+  :reset          Start over with a new CPU and without functions
+  :history        Print the lines entered in this session
+  :help           Print this message
+  :quit           Leave the REPL (or Ctrl-D)";
+
+/*
+ * An interactive session, one CPU stays alive between the lines
+ */
+pub struct Repl {
+    pub cpu: CPU<CPUType>,
+    // name and source of every defined function
+    functions: Vec<(String, String)>,
+    // lines of a function which is not closed yet
+    pending: Option<Vec<String>>,
+    pub history: Vec<String>,
+}
+
+impl Repl {
+    pub fn new(cpu: CPU<CPUType>) -> Repl {
+        Repl {
+            cpu,
+            functions: vec![],
+            pending: None,
+            history: vec![],
+        }
+    }
+    pub fn prompt(&self) -> &'static str {
+        match self.pending {
+            Some(_) => "...> ",
+            None => "rusm> ",
+        }
+    }
+    /*
+     * Evaluate one line of input, returns false once the session ends
+     */
+    pub fn eval(&mut self, line: &str) -> bool {
+        if line.trim().is_empty() && self.pending.is_none() {
+            return true;
+        }
+        self.history.push(line.to_string());
+        if let Some(mut pending) = self.pending.take() {
+            pending.push(line.to_string());
+            match brace_depth(&pending) > 0 {
+                true => self.pending = Some(pending),
+                false => self.define(&pending.join("\n")),
+            }
+            return true;
+        }
+        let line = line.trim();
+        match line.strip_prefix(':') {
+            Some(command) => return self.meta_command(command),
+            None if line.starts_with("fn ") => {
+                let lines = vec![line.to_string()];
+                match brace_depth(&lines) > 0 {
+                    true => self.pending = Some(lines),
+                    false => self.define(line),
+                }
+            }
+            None => self.execute(line),
+        }
+        true
+    }
+    /*
+     * Drop a function which is not closed yet (Ctrl-C)
+     */
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+    fn meta_command(&mut self, command: &str) -> bool {
+        let (command, argument) = match command.split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (command, ""),
+        };
+        match command {
+            "state" => println!("{}", self.cpu),
+            "ports" => {
+                for (i, value) in self.cpu.port.iter().enumerate() {
+                    println!("P{i}: {value} (0x{value:x})");
+                }
+            }
+            "stack" => {
+                println!("SP: 0x{:x}", self.cpu.get_sp());
+                // top of the stack first
+                for value in self.cpu.get_stack().iter().rev() {
+                    println!("  {value} (0x{value:x})");
+                }
+            }
+            "fns" => {
+                for (name, _) in &self.functions {
+                    println!("{name}");
+                }
+            }
+            "load" if !argument.is_empty() => match std::fs::read_to_string(argument) {
+                Ok(source) => {
+                    for function in split_functions(&source) {
+                        self.define(&function);
+                    }
+                }
+                Err(error) => println!("Unable to read `{argument}`: {error}"),
+            },
+            "reset" => {
+                let word_mask = self.cpu.word_mask;
+                if let Ok(cpu) = CPU::new() {
+                    self.cpu = cpu;
+                    self.cpu.word_mask = word_mask;
+                }
+                self.functions = vec![];
+                println!("Reset the CPU");
+            }
+            "history" => {
+                for (i, line) in self.history.iter().enumerate() {
+                    println!("{:>4}  {line}", i + 1);
+                }
+            }
+            "help" => println!("{HELP}"),
+            "quit" | "q" | "exit" => return false,
+            _ => println!("Unknown command `:{command}`, see `:help`"),
+        }
+        true
+    }
+    /*
+     * Add or replace a function
+     */
+    fn define(&mut self, source: &str) {
+        let name = source
+            .trim_start()
+            .trim_start_matches("fn")
+            .trim_start()
+            .split(['(', '{', ' '])
+            .next()
+            .unwrap_or("")
+            .to_string();
+        if name.is_empty() || name == LINE_FUNCTION {
+            println!("Expected a function name after `fn`");
+            return;
+        }
+        match self.functions.iter_mut().find(|(n, _)| *n == name) {
+            Some(function) => function.1 = source.to_string(),
+            None => self.functions.push((name.clone(), source.to_string())),
+        }
+        println!("Defined `{name}`");
+    }
+    /*
+     * Compile the line together with the defined functions and run it,
+     * the state of the CPU and the variables are kept
+     */
+    fn execute(&mut self, line: &str) {
+        let mut source: Vec<&str> = self.functions.iter().map(|(_, f)| f.as_str()).collect();
+        let body = format!("fn {LINE_FUNCTION}() {{\n{line}\n}}");
+        source.push(&body);
+        if self.cpu.load_string(&source.join("\n")).is_none() {
+            return;
+        }
+        self.cpu.bind_vars();
+        self.cpu.runtime_error = None;
+        let program = self.cpu.program();
+        if let Some(function) = program.function(LINE_FUNCTION) {
+            self.cpu.call_code(&program, function.start);
+        }
+        if let Some(error) = &self.cpu.runtime_error {
+            println!("Stopped: {error}");
+        }
+    }
+}
+
+/*
+ * Open minus closed braces, a function is complete at 0
+ */
+fn brace_depth(lines: &[String]) -> isize {
+    lines
+        .iter()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|code| code.chars())
+        .map(|c| match c {
+            '{' => 1,
+            '}' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/*
+ * The source of every function of a file
+ */
+fn split_functions(source: &str) -> Vec<String> {
+    let mut functions = vec![];
+    let mut current: Vec<String> = vec![];
+    for line in source.lines() {
+        if current.is_empty() && !line.trim_start().starts_with("fn ") {
+            continue;
+        }
+        current.push(line.to_string());
+        if brace_depth(&current) <= 0 {
+            functions.push(current.join("\n"));
+            current = vec![];
+        }
+    }
+    functions
+}
+
+/*
+ * Read lines until `:quit` or Ctrl-D, the history is kept in
+ * `~/.russembly_history`
+ */
+pub fn run(mut repl: Repl) -> i32 {
+    let mut editor = match Editor::<()>::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("Unable to start the REPL: {error}");
+            return 1;
+        }
+    };
+    let history = std::env::var("HOME")
+        .map(|home| format!("{home}/.russembly_history"))
+        .unwrap_or_else(|_| ".russembly_history".to_string());
+    let _ = editor.load_history(&history);
+    println!("russembly {}, `:help` for help", env!("CARGO_PKG_VERSION"));
+    loop {
+        match editor.readline(repl.prompt()) {
+            Ok(line) => {
+                editor.add_history_entry(line.as_str());
+                if !repl.eval(&line) {
+                    break;
+                }
+            }
+            Err(ReadlineError::Interrupted) => repl.cancel(),
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("{error}");
+                break;
+            }
+        }
+    }
+    let _ = editor.save_history(&history);
+    0
+}
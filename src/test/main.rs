@@ -1,6 +1,6 @@
 #![allow(unused_macros)]
 #[cfg(test)]
-use crate::cpu::main::*;
+use crate::cpu::{interrupt::Interrupt, main::*, RuntimeError};
 
 macro_rules! new {
     (let $name:ident = new $type:ty;) => {
@@ -137,3 +137,1229 @@ fn split() {
     assert_eq!(split, vec!["loop {", "prnt A", "}"]);
     //let mut braces = vec![];
 }
+
+#[test]
+fn stack_manipulation() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n push 1\n push 2\n push 3\n rot\n swap\n over\n dup\n drop\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.get_stack(), &vec![2, 1, 3, 1]);
+}
+
+#[test]
+fn push_pop_operands() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n let x, 7\n mov A, 5\n mov P0, 9\n push A\n push P0\n push x\n mods\n pop P1\n peek P2\n pop A\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.get_port(1), 7);
+    assert_eq!(cpu.get_accumulator(), &5);
+    assert_eq!(cpu.get_port(2), 5);
+    assert!(cpu.get_stack().is_empty());
+}
+
+#[test]
+fn cjne_loop() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code =
+        "fn main() {\n push 0\nloop:\n push 1\n adds\n cjne Stack, 5, loop\n dup\n push 5\n cmp\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.get_stack(), &vec![5, 1]);
+    assert_eq!(cpu.sfr.psw & 0x80, 0);
+}
+
+#[test]
+fn division_by_zero_is_an_error() {
+    use crate::cpu::CPU_ERROR_COUNT;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let errors = || CPU_ERROR_COUNT.with(|count| *count.borrow());
+    cpu.load_string("fn main() {\n push 0\n push 5\n divs\n push 0\n push 5\n mods\n}");
+    cpu.run_main();
+    assert_eq!(errors(), 2);
+    assert!(cpu.runtime_error.is_none());
+    // a call to a function which does not exist counts too
+    cpu.load_string("fn main() {\n call nowhere\n}");
+    cpu.run_main();
+    assert_eq!(errors(), 3);
+}
+
+#[test]
+fn internal_ram() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n mov A, 42\n mov 30h, A\n mov R0, 30h\n mov R1, #31h\n mov @R1, R0\n mov A, 31h\n mov P1, @R1\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.get_iram()[0x30], 42);
+    assert_eq!(cpu.get_iram()[0x31], 42);
+    assert_eq!(cpu.get_register(1), 0x31);
+    assert_eq!(cpu.get_port(1), 42);
+}
+
+#[test]
+fn number_forms() {
+    use crate::lexer::{Lexer, TokenType};
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    // without `#` only a hexadecimal number is a direct address
+    let forms = [
+        ("15", 15),
+        ("1111b", 15),
+        ("0Fh", 99),
+        ("#15", 15),
+        ("#1111b", 15),
+        ("#0Fh", 15),
+    ];
+    for (operand, value) in forms {
+        cpu.load_string(&format!(
+            "fn main() {{\n mov 0Fh, #99\n mov A, {operand}\n mov P1, A\n}}"
+        ));
+        cpu.run_main();
+        assert_eq!(cpu.get_port(1), value, "mov A, {operand}");
+    }
+
+    let mut lexer = Lexer::new();
+    lexer.run("mov A, 1010b".to_string(), 1);
+    lexer.run("mov A, fb".to_string(), 1);
+    let lines = lexer.get_lines().unwrap();
+    assert_eq!(lines[0].tokens[3].token_type, TokenType::Number(10));
+    assert_eq!(lines[1].tokens[3].token_type, TokenType::Generic);
+}
+
+#[test]
+fn register_banks_and_xram() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let mut cpu2 = CPU::with_memory(256, 1024).unwrap();
+    assert!(CPU::with_memory(100, 0).is_err());
+    assert_eq!(cpu.get_iram().len(), 128);
+
+    cpu.set_register_bank(2);
+    cpu.set_register(7, 9);
+    assert_eq!(cpu.get_iram()[2 * 8 + 7], 9);
+
+    let code = "fn main() {\n mov A, 7\n mov R0, 200\n movx @R0, A\n mov A, 0\n movx A, @R0\n}";
+    cpu2.load_string(code);
+    cpu2.run_main();
+    assert_eq!(cpu2.get_xram()[200], 7);
+    assert_eq!(cpu2.get_accumulator(), &7);
+}
+
+#[test]
+fn bounded_stack() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.set_stack_depth(Some(6));
+    cpu.load_string("fn main() {\n push 1\n push 2\n push 3\n push 4\n push 5\n prnt 10\n}");
+    cpu.run_main();
+    assert_eq!(cpu.get_runtime_error(), &Some(RuntimeError::StackOverflow));
+    assert_eq!(cpu.get_stack(), &vec![1, 2, 3, 4]);
+    assert_eq!(cpu.get_sp(), 0x07 + 4);
+
+    cpu.load_string("fn main() {\n drop\n drop\n drop\n adds\n}");
+    cpu.run_main();
+    assert_eq!(cpu.get_runtime_error(), &Some(RuntimeError::StackUnderflow));
+}
+
+#[test]
+fn recursion_overflows_bounded_stack() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.set_stack_depth(Some(16));
+    cpu.load_string("fn main() {\n call recurse\n}\nfn recurse() {\n push 1\n call recurse\n}");
+    cpu.run_main();
+    assert_eq!(cpu.get_runtime_error(), &Some(RuntimeError::StackOverflow));
+    assert_eq!(cpu.get_stack().len(), 4);
+}
+
+#[test]
+fn external_interrupt() {
+    use crate::cpu::CPU_ERROR_COUNT;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n let x, 5\n setb IT0\n setb EX0\n setb EA\n push x\n}\nfn int0() {\n cpl P1.0\n let x, 9\n reti\n push 1\n}";
+    cpu.load_string(code);
+    cpu.raise_interrupt(Interrupt::External0);
+    cpu.run_main();
+    assert_eq!(cpu.get_port(1), 1);
+    assert_eq!(cpu.get_stack(), &vec![5]);
+    assert!(cpu.interrupts.in_service.is_empty());
+    assert_eq!(cpu.interrupts.tcon, 0b0000_0001);
+
+    // a handler which returns without reti is an error
+    let errors = || CPU_ERROR_COUNT.with(|count| *count.borrow());
+    let before = errors();
+    cpu.load_string("fn main() {\n setb EX0\n setb EA\n push 1\n}\nfn int0() {\n cpl P1.0\n}");
+    cpu.raise_interrupt(Interrupt::External0);
+    cpu.run_main();
+    assert_eq!(errors() - before, 1);
+    assert!(cpu.interrupts.in_service.is_empty());
+}
+
+#[test]
+fn interrupt_priorities() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n setb EX0\n setb ET0\n setb PT0\n setb EA\n push 2\n push 3\n}\nfn int0() {\n push 0\n reti\n}\nfn tick() {\n push 1\n reti\n}";
+    cpu.load_string(code);
+    cpu.set_interrupt_handler(Interrupt::Timer0, "tick");
+    cpu.raise_interrupt(Interrupt::External0);
+    cpu.raise_interrupt(Interrupt::Timer0);
+    cpu.run_main();
+    assert_eq!(cpu.get_stack(), &vec![1, 2, 0, 3]);
+}
+
+#[test]
+fn timer_delay_loop() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n mov TMOD, #01h\n mov TH0, #0FFh\n mov TL0, #0F0h\n setb TR0\nwait:\n push 1\n drop\n jnb TF0, wait\n clr TR0\n mov P1, TL0\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    // 6 cycles per loop iteration, the timer overflows during the 3rd iteration
+    // and keeps counting until `clr TR0`
+    assert_eq!(cpu.get_port(1), 3);
+    assert_eq!(cpu.timers.th0, 0);
+    assert_eq!(cpu.interrupts.tcon & 0b0010_0000, 0b0010_0000);
+}
+
+#[test]
+fn timer_interrupt_auto_reload() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n mov TMOD, #02h\n mov TH0, #0C0h\n mov TL0, #0F0h\n setb ET0\n setb EA\n setb TR0\nloop:\n cjne R2, 3, loop\n clr TR0\n}\nfn timer0() {\n push R2\n push 1\n adds\n pop R2\n reti\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.get_register(2), 3);
+    assert_eq!(cpu.interrupts.tcon & 0b0011_0000, 0);
+}
+
+#[test]
+fn counter_and_split_timer() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    // timer 1 as 16 bit counter, timer 0 split into two 8 bit timers
+    cpu.timers.tmod = 0b0101_0011;
+    cpu.timers.tl0 = 0xFE;
+    cpu.timers.th0 = 0xF0;
+    cpu.timers.tl1 = 0xFF;
+    cpu.timers.th1 = 0xFF;
+    cpu.interrupts.tcon = 0b0101_0000; // TR1 and TR0
+    cpu.tick(2);
+    assert_eq!(cpu.timers.tl0, 0);
+    assert_eq!(cpu.timers.th0, 0xF2);
+    assert_eq!(cpu.interrupts.tcon & 0b1010_0000, 0b0010_0000);
+    // the counter only counts pulses and can not set TF1 while timer 0 is in mode 3
+    assert_eq!(cpu.timers.tl1, 0xFF);
+    cpu.count_pulses(1, 1);
+    assert_eq!((cpu.timers.th1, cpu.timers.tl1), (0, 0));
+    assert_eq!(cpu.interrupts.tcon & 0b1000_0000, 0);
+}
+
+#[test]
+fn special_function_registers() {
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "fn main() {\n mov IE, #81h\n mov 0F0h, #5\n setb RS1\n mov R0, #7\n mov DPTR, #1234h\n setb 20h.3\n setb ACC.1\n mov P1, DPL\n mov P2, SP\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.interrupts.ie, 0x81);
+    assert_eq!(cpu.sfr.b, 5);
+    assert_eq!(cpu.register_bank(), 2);
+    assert_eq!(cpu.get_iram()[0x10], 7);
+    assert_eq!(cpu.get_dptr(), 0x1234);
+    assert_eq!(cpu.get_iram()[0x20], 0b1000);
+    assert_eq!(cpu.get_accumulator(), &2);
+    assert_eq!(cpu.get_port(1), 0x34);
+    // SP points to the return address of main
+    assert_eq!(cpu.get_port(2), 0x09);
+
+    // the SFRs hold a byte, the parity is the one of the low byte
+    cpu.write_sfr(0x90, 0x1FF);
+    assert_eq!(cpu.get_port(1), 0xFF);
+    cpu.accumulator = 0x103;
+    assert_eq!(cpu.read_sfr(0xD0).unwrap() & 1, 0);
+    cpu.set_word_width(4).unwrap();
+    cpu.write_sfr(0xE0, 0x3F);
+    assert_eq!(cpu.get_accumulator(), &0x0F);
+}
+
+#[test]
+fn sfr_names_are_resolved_by_the_lexer() {
+    use crate::lexer::{Lexer, TokenType};
+
+    let mut lexer = Lexer::new();
+    lexer.run("setb EA".to_string(), 1);
+    lexer.run("mov TMOD, P1.3".to_string(), 1);
+    lexer.run("clr P4".to_string(), 1);
+    let lines = lexer.get_lines().unwrap();
+    assert_eq!(lines[0].tokens[1].token_type, TokenType::Bit(0xAF));
+    assert_eq!(lines[1].tokens[1].token_type, TokenType::Sfr(0x89));
+    assert_eq!(lines[1].tokens[3].token_type, TokenType::Bit(0x93));
+    assert_eq!(lines[2].tokens[1].token_type, TokenType::Port);
+
+    let mut lexer = Lexer::new();
+    lexer.run("clr IEO".to_string(), 1);
+    lexer.run("mov P9, 1".to_string(), 1);
+    let lines = lexer.get_lines().unwrap();
+    assert_eq!(lines[0].tokens.len(), 1);
+    assert_eq!(lines[1].tokens.len(), 3);
+}
+
+#[test]
+fn cycle_counting_and_virtual_clock() {
+    use crate::{
+        cpu::clock::line_cycles,
+        lexer::{Line, Token, TokenType},
+    };
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    // 1 cycle for the mov and 14 cycles per loop iteration
+    let code =
+        "fn main() {\n mov R2, #0\nloop:\n push R2\n push 1\n adds\n pop R2\n cjne R2, 10, loop\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.get_cycles(), 141);
+    assert!((cpu.elapsed_time() - 141e-6).abs() < 1e-12);
+
+    cpu.set_clock_frequency(6_000_000);
+    assert!((cpu.elapsed_time() - 282e-6).abs() < 1e-12);
+
+    // every opcode of a line is charged
+    let token = |token_type: TokenType, value: &str| Token {
+        token_type,
+        value: value.to_string(),
+    };
+    let line = Line {
+        tokens: vec![
+            token(TokenType::OpCode, "push"),
+            token(TokenType::Number(1), "1"),
+            token(TokenType::OpCode, "adds"),
+        ],
+        as_string: String::new(),
+    };
+    assert_eq!(line_cycles(&line), 8);
+    assert!(cpu.get_json().contains("\"cycles\":141"));
+
+    use crate::{cpu::clock::opcode_cycles, lexer::Lexer};
+    let mut lexer = Lexer::new();
+    lexer.run("mov 30h, R0".to_string(), 1);
+    lexer.run("mov A, 30h".to_string(), 1);
+    let lines = lexer.get_lines().unwrap();
+    assert_eq!(opcode_cycles("mov", &lines[0].tokens[1..]), 2);
+    assert_eq!(opcode_cycles("mov", &lines[1].tokens[1..]), 1);
+    assert_eq!(opcode_cycles("setb", &[]), 1);
+}
+
+#[test]
+fn example_asm_runs_in_8051_mode() {
+    use crate::dialect::Dialect;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_file_as("./example.asm", Dialect::I8051).unwrap();
+    // the main loop never ends
+    cpu.set_cycle_limit(Some(500));
+    cpu.run_main();
+    assert_eq!(cpu.get_runtime_error(), &Some(RuntimeError::CycleLimit));
+    assert!(cpu.get_cycles() >= 500);
+    assert_eq!(cpu.interrupts.ie, 0b1000_0001);
+    assert_eq!(cpu.interrupts.tcon, 0b0000_0001);
+    // P1.1 is low, so the loop keeps P2.1 cleared
+    assert_eq!(cpu.get_port(2), 0);
+    // the 8051 starts without a return address on the stack
+    assert_eq!(cpu.get_sp(), 0x07);
+}
+
+#[test]
+fn dialect_8051_syntax_and_vectors() {
+    use crate::dialect::Dialect;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "LED     EQU P2.1\nCOUNT   equ 5\n        ORG 0\n        LJMP start\n        ORG 03h      ; INT0\n        cpl LED\n        reti\ndouble: add a, acc\n        ret\nstart:  setb ex0\n\tsetb EA\n        mov r2, #COUNT\n        mov a, #1\nloop:   acall double\n        djnz r2, loop\n        setb c\n        subb a, #2   ; 32 - 2 - 1\n        rl a\n        mov P1, a\n        anl P1, #00001111b\n        swap a\n        mov b, #3\n        mul ab\n        END\n        mov P1, #0FFh";
+    cpu.load_string_as(code, Dialect::I8051).unwrap();
+    cpu.raise_interrupt(Interrupt::External0);
+    cpu.run_main();
+    assert_eq!(cpu.get_runtime_error(), &None);
+    assert_eq!(cpu.get_port(2), 0b10);
+    assert_eq!(cpu.get_port(1), 10);
+    assert_eq!(cpu.get_register(2), 0);
+    // swap 3Ah = A3h, A3h * 3 = 01E9h
+    assert_eq!(cpu.get_accumulator(), &0xE9);
+    assert_eq!(cpu.sfr.b, 0x01);
+    assert!(cpu.interrupts.in_service.is_empty());
+}
+
+#[test]
+fn assemble_example_asm() {
+    use crate::asm::assemble;
+    use std::fs::read_to_string;
+
+    let source = read_to_string("./example.asm").unwrap();
+    let assembly = assemble(&source);
+    assert_eq!(assembly.error_count(), 0);
+    let binary = assembly.to_binary();
+    assert_eq!(
+        binary[..0x17],
+        [
+            0x02, 0x00, 0x08, // ljmp init
+            0xB2, 0xA2, 0xC2, 0x89, 0x32, // vector 03h: cpl P2.2, clr IE0, reti
+            0xD2, 0x88, 0xD2, 0xA8, 0xD2, 0xAF, // setb IT0, EX0, EA
+            0x75, 0x90, 0x00, 0x75, 0xA0, 0x00, // mov P1, #0 and mov P2, #0
+            0x30, 0x91, 0x05, // jnb P1.1, leon
+        ]
+    );
+    assert_eq!(binary.len(), 0x24);
+    assert!(assembly.labels.contains(&("leon".to_string(), 0x1C)));
+
+    let hex = assembly.to_intel_hex();
+    let records: Vec<&str> = hex.lines().collect();
+    assert_eq!(records[0], ":10000000020008B2A2C28932D288D2A8D2AF7590BB");
+    assert_eq!(records.last(), Some(&":00000001FF"));
+
+    // `asm` writes the raw image next to the hex file and the listing
+    #[cfg(feature = "cli")]
+    {
+        let path = std::env::temp_dir().join("russembly_example.asm");
+        std::fs::write(&path, &source).unwrap();
+        let asm = vec!["asm".to_string(), path.to_str().unwrap().to_string()];
+        assert_eq!(crate::cli::run(&asm), 0);
+        let bin = std::fs::read(path.with_extension("bin")).unwrap();
+        assert_eq!(bin, binary);
+        assert_eq!(bin[..3], [0x02, 0x00, 0x08]);
+        assert!(path.with_extension("hex").exists());
+        assert!(path.with_extension("lst").exists());
+    }
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn check_reports_unknown_bits() {
+    use crate::{cli::run, dialect::Dialect, Program};
+    use std::fs::read_to_string;
+
+    // example.asm used to clear `IEO` instead of `IE0`
+    let source = read_to_string("./example.asm")
+        .unwrap()
+        .replace("IE0", "IEO");
+    let errors = Program::parse_as(&source, Dialect::I8051).unwrap_err();
+    assert_eq!(errors[0].line, 5);
+    assert!(errors[0].message.contains("Unknown bit `IEO`"));
+
+    let path = std::env::temp_dir().join("russembly_unknown_bit.asm");
+    std::fs::write(&path, source).unwrap();
+    let check = |path: &str| run(&["check".to_string(), path.to_string()]);
+    assert_eq!(check(path.to_str().unwrap()), 1);
+    assert_eq!(check("./example.asm"), 0);
+}
+
+#[test]
+fn assembler_listing_and_symbols() {
+    use crate::asm::{
+        assemble,
+        listing::{Symbol, SymbolKind},
+    };
+
+    let code = "const COUNT = 3\nglobal flags = 20h\nstart: mov r2, #COUNT\nloop:  mov flags, #1\n       djnz r2, loop\n       sjmp nowhere\n       END";
+    let assembly = assemble(code);
+    assert_eq!(assembly.error_count(), 1);
+    let symbols = assembly.symbols();
+    assert!(symbols.contains(&Symbol {
+        name: "loop".to_string(),
+        kind: SymbolKind::Label,
+        value: "0002h".to_string(),
+    }));
+    assert!(symbols.contains(&Symbol {
+        name: "COUNT".to_string(),
+        kind: SymbolKind::Const,
+        value: "3".to_string(),
+    }));
+    assert!(symbols.contains(&Symbol {
+        name: "flags".to_string(),
+        kind: SymbolKind::Global,
+        value: "20h".to_string(),
+    }));
+
+    let listing = assembly.to_listing();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[1], "   1                  const COUNT = 3");
+    assert_eq!(lines[3], "   3  0000  7A 03     start: mov r2, #COUNT");
+    assert_eq!(lines[4], "   4  0002  75 20 01  loop:  mov flags, #1");
+    assert_eq!(lines[7], "*** error: unknown label `nowhere`");
+    assert!(lines.contains(&"1 errors"));
+    assert!(lines.contains(&"COUNT  const   3"));
+    assert!(lines.contains(&"loop   label   0002h"));
+
+    // the errors of the lexer are listed too, long data continues below
+    let listing = assemble("setb FOO\nDB 1, 2, 3, 4").to_listing();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[2], "*** error: Unknown bit `FOO`");
+    assert_eq!(lines[3], "   2  0000  01 02 03  DB 1, 2, 3, 4");
+    assert_eq!(lines[4], "      0003  04");
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn disassemble_code_memory() {
+    use crate::{
+        asm::{assemble, disasm::disassemble},
+        cli::run,
+    };
+    use std::fs::read_to_string;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let assembly = assemble(&read_to_string("./example.asm").unwrap());
+    cpu.load_hex(&assembly.to_intel_hex()).unwrap();
+    assert_eq!(cpu.code_end(), 0x24);
+    let listing = cpu.disassemble(0, cpu.code_end());
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], "0000  02 00 08  ljmp L0008");
+    assert_eq!(lines[1], "0003  B2 A2     cpl P2.2");
+    assert!(lines.contains(&"L0008:"));
+    assert!(lines.contains(&"000E  75 90 00  mov P1, #00h"));
+    assert!(lines.contains(&"0014  30 91 05  jnb P1.1, L001C"));
+
+    // the disassembly assembles to the same machine code
+    let code = disassemble(&cpu.code_memory, 0, cpu.code_end());
+    let source: String = code
+        .iter()
+        .map(|line| match &line.label {
+            Some(label) => format!("ORG {}\n{label}: {}\n", line.address, line.instruction()),
+            None => format!("ORG {}\n{}\n", line.address, line.instruction()),
+        })
+        .collect();
+    assert_eq!(assemble(&source).to_binary(), assembly.to_binary());
+
+    // 0A5h and a cut off ljmp are no instructions
+    cpu.load_binary(&[0xA5, 0x85, 0x31, 0x30, 0x02, 0x00])
+        .unwrap();
+    let listing = cpu.disassemble(0, 6);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], "0000  A5        db 0A5h");
+    assert_eq!(lines[1], "0001  85 31 30  mov 30h, 31h");
+    assert_eq!(lines[2], "0004  02        db 02h");
+
+    // a range which is no range is rejected instead of ignored
+    let hex = std::env::temp_dir().join("russembly_disasm_range.hex");
+    let hex = hex.to_str().unwrap().to_string();
+    std::fs::write(&hex, assembly.to_intel_hex()).unwrap();
+    let disasm = |range: &[&str]| {
+        let mut args = vec!["disasm", hex.as_str()];
+        args.extend_from_slice(range);
+        run(&args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<String>>())
+    };
+    assert_eq!(disasm(&["3", "0Eh"]), 0);
+    assert_eq!(disasm(&["start"]), 2);
+    assert_eq!(disasm(&["10h", "3"]), 2);
+}
+
+#[test]
+fn run_intel_hex_image() {
+    use crate::asm::assemble;
+    use std::fs::read_to_string;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let hex = assemble(&read_to_string("./example.asm").unwrap()).to_intel_hex();
+    cpu.load_hex(&hex).unwrap();
+    cpu.set_cycle_limit(Some(500));
+    cpu.port[2] = 0xFF;
+    cpu.raise_interrupt(Interrupt::External0);
+    cpu.run_image();
+    // the same result as running the source in the 8051 dialect
+    assert_eq!(cpu.get_runtime_error(), &Some(RuntimeError::CycleLimit));
+    assert_eq!(cpu.interrupts.ie, 0b1000_0001);
+    assert_eq!(cpu.interrupts.tcon, 0b0000_0001);
+    assert_eq!(cpu.get_port(2), 0);
+    assert_eq!(cpu.get_sp(), 0x07);
+
+    // the interrupt handler toggles P2.2 and returns to the main loop
+    cpu.set_cycle_limit(Some(1000));
+    cpu.raise_interrupt(Interrupt::External0);
+    cpu.run_image();
+    assert_eq!(cpu.get_port(2), 0b100);
+    assert!(cpu.interrupts.in_service.is_empty());
+    assert_eq!(cpu.get_sp(), 0x07);
+    assert!(cpu.get_pc() >= 0x14 && cpu.get_pc() < 0x24);
+}
+
+#[test]
+fn run_binary_image() {
+    use crate::asm::assemble;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    let code = "        ljmp start\n        org 03h\n        cpl P2.1\n        reti\ndouble: add a, acc\n        ret\nstart:  mov r2, #5\n        mov a, #1\nloop:   acall double\n        djnz r2, loop\n        setb c\n        subb a, #2\n        rl a\n        mov P1, a\n        anl P1, #00001111b\n        swap a\n        mov b, #3\n        mul ab\n        mov 30h, #42\n        push 30h\n        pop 31h\nstop:   sjmp stop";
+    let assembly = assemble(code);
+    assert_eq!(assembly.error_count(), 0);
+    cpu.load_binary(&assembly.to_binary()).unwrap();
+    cpu.run_image();
+    // the program halts in `sjmp stop` with the interrupts disabled
+    assert_eq!(cpu.get_runtime_error(), &None);
+    assert_eq!(cpu.get_port(1), 10);
+    // swap 3Ah = A3h, A3h * 3 = 01E9h
+    assert_eq!(cpu.get_accumulator(), &0xE9);
+    assert_eq!(cpu.sfr.b, 0x01);
+    assert_eq!(cpu.get_iram()[0x31], 42);
+    assert_eq!(cpu.get_sp(), 0x07);
+
+    // mov dptr, #100h / clr a / movc a, @a+dptr / sjmp $ and a table at 100h
+    let mut image = vec![0x90, 0x01, 0x00, 0xE4, 0x93, 0x80, 0xFE];
+    image.resize(0x100, 0xFF);
+    image.push(0x5A);
+    cpu.load_binary(&image).unwrap();
+    cpu.run_image();
+    assert_eq!(cpu.get_accumulator(), &0x5A);
+    assert_eq!(cpu.get_pc(), 5);
+
+    // 0A5h is no valid opcode
+    cpu.load_binary(&[0xA5]).unwrap();
+    cpu.run_image();
+    assert_eq!(cpu.get_runtime_error(), &Some(RuntimeError::InvalidOpcode));
+    assert!(cpu.load_hex(":0100000000FE\n:00000001FF").is_none());
+}
+
+#[test]
+fn assembler_reports_errors() {
+    use crate::asm::{assemble, opcodes::decode};
+
+    // every opcode but A5h is an instruction
+    assert_eq!((0..=255).filter_map(decode).count(), 255);
+
+    let assembly = assemble("mov 30h, 31h\nsjmp far\nORG 200h\nfar: acall 1000h\ndup\nmov A, #300");
+    assert_eq!(assembly.lines[0].bytes, vec![0x85, 0x31, 0x30]);
+    assert_eq!(assembly.lines[3].address, 0x200);
+    let errors: Vec<usize> = assembly
+        .lines
+        .iter()
+        .filter(|line| !line.errors.is_empty())
+        .map(|line| line.line)
+        .collect();
+    // out of range sjmp, acall into another 2K page, no 8051 opcode, too big immediate
+    assert_eq!(errors, vec![2, 4, 5, 6]);
+
+    // errors of the lexer are errors of their line
+    let assembly = assemble("mov A, #0x12\nsetb FOO\nnop");
+    assert_eq!(assembly.error_count(), 2);
+    assert_eq!(assembly.lines[1].errors, vec!["Unknown bit `FOO`"]);
+
+    let assembly = assemble("tbl: DB 1, 'a;b', 0 ; text\nDW tbl, $\nsjmp $\nDB 300");
+    assert_eq!(assembly.lines[0].bytes, vec![1, b'a', b';', b'b', 0]);
+    assert_eq!(assembly.lines[1].bytes, vec![0x00, 0x00, 0x00, 0x05]);
+    assert_eq!(assembly.lines[2].bytes, vec![0x80, 0xFE]);
+    assert_eq!(assembly.error_count(), 1);
+}
+
+#[test]
+fn bytecode_resolves_labels_and_variables() {
+    use crate::cpu::bytecode::{Instruction, Target};
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string(
+        "fn main() {\n let x, 3\n mov R3, #4\nloop:\n inc A\n djnz R3, loop\n prnt x\n jmp nowhere\n}",
+    );
+    let program = cpu.program();
+    let main = program.function("main").unwrap();
+    // labels point to the first instruction of their line
+    assert!(program.code.iter().any(|instruction| matches!(
+        instruction,
+        Instruction::Djnz(_, Target::Line(line)) if *line == main.lines[2]
+    )));
+    assert_eq!(program.variables, vec!["x".to_string()]);
+
+    cpu.run_main();
+    assert_eq!(cpu.get_accumulator(), &4);
+    assert_eq!(cpu.get_register(3), 0);
+}
+
+#[test]
+fn compiled_program_round_trip() {
+    use crate::cpu::rusmc::{CompiledProgram, RUSMC_MAGIC, RUSMC_VERSION};
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string(
+        "const SIZE = 3\nfn main() {\n mov R3, #3\nloop:\n inc A\n djnz R3, loop\n call store\n}\nfn store() {\n mov P1, A\n}",
+    );
+    let bytes = cpu.compile_to_rusmc();
+    assert_eq!(&bytes[..5], RUSMC_MAGIC);
+
+    let compiled = CompiledProgram::from_bytes(&bytes).unwrap();
+    assert_eq!(compiled.constants[0].name, "SIZE");
+    let (function, label, address) = compiled.labels()[0];
+    assert_eq!((function, label), ("main", "loop"));
+    // the source map leads back to the line of the label
+    let (function, line) = compiled.program.source_line(address).unwrap();
+    assert_eq!(function.source[line].trim(), "loop:");
+
+    new! {
+        let mut loaded = new CPU<usize>;
+    };
+    loaded.load_rusmc(&bytes).unwrap();
+    loaded.run_main();
+    assert_eq!(loaded.get_port(1), 3);
+    assert_eq!(loaded.get_register(3), 0);
+
+    // other versions and broken files are rejected
+    let mut newer = bytes.clone();
+    newer[5] = RUSMC_VERSION as u8 + 1;
+    assert!(loaded.load_rusmc(&newer).is_none());
+    assert!(loaded.load_rusmc(&bytes[..bytes.len() - 4]).is_none());
+    assert!(loaded.load_rusmc(b"hello").is_none());
+
+    // the source and the labels have to match the lines
+    let mut truncated = compiled.clone();
+    truncated.program.functions[0].source.pop();
+    let truncated = truncated.to_bytes();
+    assert!(CompiledProgram::from_bytes(&truncated)
+        .unwrap_err()
+        .contains("source lines"));
+    assert!(loaded.load_rusmc(&truncated).is_none());
+    let mut label = compiled.clone();
+    label.program.functions[0].labels[0].1 = 100;
+    let label = label.to_bytes();
+    assert!(CompiledProgram::from_bytes(&label)
+        .unwrap_err()
+        .contains("label `loop`"));
+    assert!(loaded.load_rusmc(&label).is_none());
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn command_line_options() {
+    use crate::{
+        asm::assemble,
+        cli::{parse_args, run},
+    };
+
+    let args: Vec<String> = "run prog.rusm -p 1=0FFh --port=P2=3 --width 8 -l 1000 -q --json"
+        .split(' ')
+        .map(|arg| arg.to_string())
+        .collect();
+    let options = parse_args(&args).unwrap();
+    assert_eq!(options.command, "run");
+    assert_eq!(options.arguments, vec!["prog.rusm"]);
+    assert_eq!(options.ports, vec![(1, 0xFF), (2, 3)]);
+    assert_eq!((options.width, options.cycle_limit), (Some(8), Some(1000)));
+    assert!(options.json);
+    assert!(parse_args(&["run".to_string(), "-p".to_string(), "9=1".to_string()]).is_err());
+    assert!(parse_args(&["run".to_string(), "--width".to_string()]).is_err());
+
+    // dump-tokens fails on lines the lexer rejects
+    let source = std::env::temp_dir().join("russembly_dump_tokens.rusm");
+    let source = source.to_str().unwrap().to_string();
+    let dump_tokens = |code: &str| {
+        std::fs::write(&source, code).unwrap();
+        run(&["dump-tokens".to_string(), source.clone()])
+    };
+    assert_eq!(dump_tokens("fn main() {\n mov A, #3\n}"), 0);
+    assert_eq!(dump_tokens("fn main() {\n clr IEO\n}"), 1);
+
+    // a limit stops the program with an error, images have a step limit
+    let hex = std::env::temp_dir().join("russembly_wait_for_interrupts.hex");
+    let hex = hex.to_str().unwrap().to_string();
+    std::fs::write(&hex, assemble("setb EA\nsjmp $").to_intel_hex()).unwrap();
+    let run = |limit: &[&str]| {
+        let mut args = vec!["run", hex.as_str(), "-q"];
+        args.extend_from_slice(limit);
+        run(&args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<String>>())
+    };
+    assert_eq!(run(&[]), 1);
+    assert_eq!(run(&["--step-limit", "100"]), 1);
+    assert_eq!(
+        parse_args(&["-s".to_string(), "100".to_string()])
+            .unwrap()
+            .instruction_limit,
+        Some(100)
+    );
+
+    // the Accumulator, the Ports and the Stack wrap at the word width
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.set_word_width(8).unwrap();
+    cpu.load_string("fn main() {\n push 250\n push 10\n adds\n pop P1\n mov A, 300\n cpl A\n}");
+    cpu.run_main();
+    assert_eq!(cpu.get_port(1), 4);
+    assert_eq!(cpu.get_accumulator(), &0xD3);
+    assert!(cpu.set_word_width(65).is_err());
+
+    // inc, dec, djnz and jz count with the whole word
+    cpu.set_word_width(16).unwrap();
+    let code = "fn main() {\n mov A, 255\n inc A\n mov P1, A\n jz done\n mov A, 0\n djnz A, done\n mov P2, 1\ndone:\n mov P3, A\n}";
+    cpu.load_string(code);
+    cpu.run_main();
+    assert_eq!(cpu.get_port(1), 0x100);
+    assert_eq!(cpu.get_port(2), 0);
+    assert_eq!(cpu.get_port(3), 0xFFFF);
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn repl_keeps_state_between_lines() {
+    use crate::repl::Repl;
+
+    new! {
+        let cpu = new CPU<usize>;
+    };
+    let mut repl = Repl::new(cpu);
+    for line in [
+        "mov A, 5",
+        "let x, 7",
+        "push x",
+        "fn twice() {",
+        " push A",
+        " push A",
+        " adds",
+        "}",
+        "call twice",
+    ] {
+        assert!(repl.eval(line));
+    }
+    assert_eq!(repl.cpu.get_stack(), &vec![7, 10]);
+    assert!(repl.eval(":reset"));
+    assert!(repl.cpu.get_stack().is_empty());
+    assert_eq!(repl.history.len(), 10);
+    assert!(!repl.eval(":quit"));
+}
+
+#[test]
+fn debugger_breakpoints_and_stepping() {
+    use crate::cpu::vm::Stop;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string(
+        "fn main() {\n mov R3, #4\nloop:\n inc P1\n call show\n djnz R3, loop\n}\nfn show() {\n mov A, P1\n}",
+    );
+    let id = cpu
+        .add_breakpoint("loop", Some("P1 == 2 && R3 < 4"))
+        .unwrap();
+    assert!(cpu.add_breakpoint("nowhere", None).is_err());
+    assert!(cpu.add_breakpoint("loop", Some("P1 ==")).is_err());
+
+    assert_eq!(cpu.debug_main(), Stop::Paused);
+    assert_eq!(cpu.location().unwrap().line, 2);
+    assert_eq!(cpu.resume(), Stop::Breakpoint(id));
+    assert_eq!(cpu.get_port(1), 2);
+    // the label line is empty, `step` stops at the next instruction
+    assert_eq!(cpu.step(), Stop::Paused);
+    assert_eq!(cpu.location().unwrap().source.trim(), "inc P1");
+    assert_eq!(cpu.step_over(), Stop::Paused);
+    // `call show` is entered by `step` but not by `next`
+    assert_eq!(cpu.step(), Stop::Paused);
+    let backtrace = cpu.backtrace();
+    assert_eq!(
+        (backtrace[0].function.as_str(), backtrace[0].line),
+        ("show", 9)
+    );
+    assert_eq!(
+        (backtrace[1].function.as_str(), backtrace[1].line),
+        ("main", 5)
+    );
+    assert_eq!(cpu.finish(), Stop::Paused);
+    assert_eq!(cpu.location().unwrap().function, "main");
+
+    assert!(cpu.remove_breakpoint(id));
+    assert_eq!(cpu.resume(), Stop::Finished);
+    assert_eq!(cpu.get_port(1), 4);
+    assert_eq!(cpu.get_accumulator(), &4);
+}
+
+#[test]
+fn debugger_watchpoints() {
+    use crate::cpu::vm::Stop;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string(
+        "fn main() {\n mov R3, #3\nloop:\n inc A\n mov P2, A\n djnz R3, loop\n mov A, #0\n}",
+    );
+    let port = cpu.add_watchpoint("P2").unwrap();
+    let zero = cpu.add_watchpoint("A == 0").unwrap();
+    assert!(cpu.add_watchpoint("P2 == 1 && A == 1").is_err());
+
+    assert_eq!(cpu.debug_main(), Stop::Paused);
+    assert_eq!(cpu.resume(), Stop::Watchpoint(port));
+    let watchpoint = &cpu.debugger.watchpoints[0];
+    assert_eq!((watchpoint.old, watchpoint.last), (Some(0), Some(1)));
+    let changed_by = cpu.source_location(watchpoint.changed_at.unwrap()).unwrap();
+    assert_eq!(changed_by.source.trim(), "mov P2, A");
+    // paused after the line which changed it
+    assert_eq!(cpu.location().unwrap().source.trim(), "djnz R3, loop");
+
+    assert!(cpu.remove_breakpoint(port));
+    // A starts at 0, only the change back to 0 matches
+    assert_eq!(cpu.resume(), Stop::Watchpoint(zero));
+    assert_eq!(cpu.get_port(2), 3);
+    assert_eq!(cpu.resume(), Stop::Finished);
+}
+
+#[test]
+fn trace_records_lines_and_changes() {
+    use crate::cpu::tracer::{split_instruction, Change, TraceEntry};
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string(
+        "fn main() {\n mov R3, #2\nloop: ; count down\n inc A\n push A\n djnz R3, loop\n}",
+    );
+    cpu.start_trace();
+    cpu.run_main();
+    let trace = cpu.take_trace().unwrap();
+    // the label line and the braces are left out
+    let lines: Vec<usize> = trace.entries.iter().map(|entry| entry.line).collect();
+    assert_eq!(lines, vec![2, 4, 5, 6, 4, 5, 6]);
+    let push = &trace.entries[5];
+    assert_eq!(
+        (push.opcode.as_str(), push.operands.clone()),
+        ("push", vec!["A".to_string()])
+    );
+    assert_eq!(
+        push.changes,
+        vec![Change::Stack {
+            popped: vec![],
+            pushed: vec![2]
+        }]
+    );
+    assert_eq!(
+        trace.entries[4].to_string(),
+        "     4  main:4  inc A  | A 1->2"
+    );
+    // every JSON line reads back as the entry
+    let json = trace.to_json_lines();
+    let entries: Vec<TraceEntry> = json
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries, trace.entries);
+
+    assert_eq!(
+        split_instruction("end: print \"a, b\", A ; done"),
+        (
+            "print".to_string(),
+            vec!["\"a, b\"".to_string(), "A".to_string()]
+        )
+    );
+}
+
+#[test]
+fn debugger_steps_backwards() {
+    use crate::cpu::vm::Stop;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    // more lines than between two checkpoints
+    cpu.load_string(
+        "fn main() {\n mov R2, #3\nouter:\n mov R3, #200\ninner:\n inc A\n mov P2, A\n djnz R3, inner\n djnz R2, outer\n mov P1, #7\n}",
+    );
+    let end = cpu.add_breakpoint("10", None).unwrap();
+    assert_eq!(cpu.debug_main(), Stop::Paused);
+    assert_eq!(cpu.step_back(), Stop::Start);
+    assert_eq!(cpu.resume(), Stop::Breakpoint(end));
+    let last = cpu.get_port(2);
+
+    assert_eq!(cpu.step_back(), Stop::Paused);
+    assert_eq!(cpu.location().unwrap().source.trim(), "djnz R2, outer");
+    let watch = cpu.add_watchpoint("P2").unwrap();
+    assert_eq!(cpu.reverse_continue(), Stop::Watchpoint(watch));
+    assert_eq!(cpu.location().unwrap().source.trim(), "djnz R3, inner");
+    let watchpoint = &cpu.debugger.watchpoints[0];
+    assert_eq!(
+        (watchpoint.old, watchpoint.last),
+        (Some(last - 1), Some(last))
+    );
+    // back to the write which caused it
+    assert_eq!(cpu.step_back(), Stop::Paused);
+    assert_eq!(cpu.location().unwrap().source.trim(), "mov P2, A");
+    assert_eq!(cpu.get_port(2), last - 1);
+    assert_eq!(cpu.reverse_continue(), Stop::Watchpoint(watch));
+    assert_eq!(cpu.get_port(2), last - 1);
+
+    // forwards again from the past
+    cpu.remove_breakpoint(watch);
+    assert_eq!(cpu.resume(), Stop::Breakpoint(end));
+    assert_eq!(cpu.get_port(2), last);
+    assert_eq!(cpu.resume(), Stop::Finished);
+    assert_eq!(cpu.get_port(1), 7);
+}
+
+#[test]
+fn snapshot_round_trips() {
+    use crate::cpu::vm::Stop;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+        let mut restored = new CPU<usize>;
+    };
+    cpu.load_string(
+        "fn main() {\n let x, 3\n let name, \"rusm\"\n mov R3, x\nloop:\n inc A\n push x\n djnz R3, loop\n mov P2, A\n}",
+    );
+    let middle = cpu.add_breakpoint("loop", Some("A == 2")).unwrap();
+    cpu.debug_main();
+    assert_eq!(cpu.resume(), Stop::Breakpoint(middle));
+
+    // valid JSON with the variables, jump locations and functions
+    let json = cpu.get_json();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["vars"][1]["String"]["name"], "name");
+    assert_eq!(value["vars"][0]["Number"]["name"], "x");
+    assert_eq!(value["functions"][0]["name"], "main");
+    assert_eq!(value["accumulator"], 2);
+
+    // the restored CPU continues where the snapshot was taken
+    restored.restore(&json).unwrap();
+    assert_eq!(restored.get_json(), json);
+    assert!(restored.restore("{\"stack\": 1}").is_err());
+    assert_eq!(restored.resume(), Stop::Finished);
+    assert_eq!(cpu.resume(), Stop::Finished);
+    assert_eq!(restored.get_json(), cpu.get_json());
+    assert_eq!(restored.get_port(2), 3);
+
+    // a corrupted snapshot is rejected instead of crashing the VM
+    let corrupt = |pointer: &str, bad: serde_json::Value| {
+        let mut value = value.clone();
+        *value.pointer_mut(pointer).unwrap() = bad;
+        value.to_string()
+    };
+    assert!(restored
+        .restore(&corrupt("/frames/0/pc", 100_000.into()))
+        .is_err());
+    assert!(restored
+        .restore(&corrupt("/var_slots/0", 7.into()))
+        .is_err());
+    assert!(restored
+        .restore(&corrupt("/iram", serde_json::json!([0])))
+        .is_err());
+    let source = value["program"]["functions"][0]["source"]
+        .as_array()
+        .unwrap();
+    let truncated = serde_json::json!(source[..source.len() - 1]);
+    assert!(restored
+        .restore(&corrupt("/program/functions/0/source", truncated))
+        .unwrap_err()
+        .contains("source lines"));
+    assert!(restored
+        .restore(&corrupt("/program/functions/0/labels/0/1", 100.into()))
+        .is_err());
+    assert!(restored.restore(&json).is_ok());
+}
+
+#[test]
+fn budget_stops_endless_programs() {
+    use crate::cpu::{Budget, RuntimeError};
+    use std::time::Duration;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+        let mut recursive = new CPU<usize>;
+    };
+    cpu.load_string("fn main() {\nloop:\n inc A\n jmp loop\n}");
+    cpu.set_instruction_limit(Some(1000));
+    cpu.run_main();
+    assert_eq!(
+        cpu.runtime_error,
+        Some(RuntimeError::BudgetExhausted(Budget::Instructions))
+    );
+    assert_eq!(cpu.get_instructions(), 1000);
+    // the state at the end of the budget stays
+    assert_eq!(*cpu.get_accumulator(), 500);
+
+    recursive.load_string("fn main() {\n call main\n}");
+    recursive.set_time_limit(Some(Duration::from_millis(50)));
+    recursive.run_main();
+    assert_eq!(
+        recursive.runtime_error,
+        Some(RuntimeError::BudgetExhausted(Budget::Time))
+    );
+
+    // every run gets the whole budget
+    cpu.load_string("fn main() {\n mov R3, #100\nloop:\n djnz R3, loop\n}");
+    cpu.set_instruction_limit(Some(150));
+    cpu.run_main();
+    assert_eq!(cpu.runtime_error, None);
+    cpu.run_main();
+    assert_eq!(cpu.runtime_error, None);
+    assert_eq!(cpu.get_instructions(), 101);
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    ignore = "benchmark, run with `cargo test --release`"
+)]
+fn vm_loop_benchmark() {
+    use std::time::{Duration, Instant};
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string("fn main() {\n push 0\nloop:\n push 1\n adds\n cjne Stack, 3000000, loop\n}");
+    let start = Instant::now();
+    cpu.run_main();
+    let elapsed = start.elapsed();
+    println!("3000000 iterations in {elapsed:?}");
+    assert_eq!(cpu.runtime_error, None);
+    assert_eq!(cpu.get_stack(), &vec![3000000]);
+    assert_eq!(cpu.get_instructions(), 1 + 3 * 3000000);
+    // the line interpreter took about 2s for this loop
+    assert!(elapsed < Duration::from_secs(1));
+}
+
+#[test]
+fn debugger_runs_in_slices() {
+    use crate::cpu::vm::Stop;
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string("fn main() {\n mov R3, #50\nloop:\n inc A\n djnz R3, loop\n mov P1, A\n}");
+    let done = cpu.add_breakpoint("6", None).unwrap();
+    cpu.debug_main();
+    // 10 lines per slice, the breakpoint still stops it
+    let mut slices = 0;
+    let stop = loop {
+        slices += 1;
+        match cpu.resume_for(10) {
+            Stop::Paused => cpu.port[2] += 1,
+            stop => break stop,
+        }
+    };
+    assert_eq!(stop, Stop::Breakpoint(done));
+    assert!(slices > 10);
+    assert_eq!(cpu.get_port(2), slices - 1);
+    assert_eq!(cpu.resume_for(10), Stop::Finished);
+    assert_eq!(cpu.get_port(1), 50);
+}
+
+#[test]
+fn diagnostics_and_state() {
+    use crate::cpu::{
+        diagnostic::{lexer_diagnostics, span},
+        state::VarValue,
+        Message,
+    };
+
+    new! {
+        let mut cpu = new CPU<usize>;
+    };
+    cpu.load_string("fn main() {\n mov A, #3\n  A\n}");
+    let diagnostics = cpu.compile_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].line, 3);
+    assert_eq!((diagnostics[0].start, diagnostics[0].end), (2, 3));
+
+    let messages = vec![
+        Message {
+            kind: "error",
+            text: "Unknown port `P9` at line 1".to_string(),
+            line: Some(2),
+        },
+        Message {
+            kind: "syntax",
+            text: "mov `port`, A".to_string(),
+            line: Some(2),
+        },
+    ];
+    let diagnostics = lexer_diagnostics(&messages, "fn main() {~ mov P9, A~}");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].start, diagnostics[0].end), (5, 7));
+    assert_eq!(diagnostics[0].syntax.as_deref(), Some("mov `port`, A"));
+    // without a quoted token the whole line is marked
+    assert_eq!(span("Expected closing breackets", "  fn x( {  "), (2, 9));
+
+    cpu.load_string("fn main() {\n let x, 5\n push #7\n pop A\n}");
+    cpu.debug_main();
+    cpu.step();
+    cpu.step();
+    let state = cpu.state();
+    assert_eq!(state.stack, vec![7]);
+    assert_eq!(state.ports.len(), 8);
+    assert_eq!(state.vars.len(), 1);
+    assert_eq!(state.vars[0].name, "x");
+    assert_eq!(state.vars[0].value, VarValue::Number(5));
+}
+
+#[test]
+fn library_api() {
+    use crate::{Cpu, Program, RuntimeError, Status, VarValue};
+
+    let program =
+        Program::parse("fn main() {\n let x, 5\n mov A, #3\n mov P1, A\n prnt \"done\"\n}")
+            .unwrap();
+    let mut cpu = Cpu::new(&program);
+    assert_eq!(cpu.status(), Status::Paused);
+    assert_eq!(cpu.line(), Some(2));
+    assert_eq!(cpu.step(), Ok(Status::Paused));
+    assert_eq!(cpu.var("x"), Some(VarValue::Number(5)));
+    assert_eq!(cpu.run(), Ok(()));
+    assert_eq!(cpu.status(), Status::Finished);
+    assert_eq!(cpu.port(1), Some(3));
+    assert_eq!(cpu.accumulator(), 3);
+    assert_eq!(cpu.take_output().trim(), "done");
+
+    // every CPU starts from the parsed program
+    let mut other = Cpu::new(&program);
+    other.set_instruction_limit(Some(2));
+    assert!(matches!(other.run(), Err(RuntimeError::BudgetExhausted(_))));
+    assert_eq!(other.port(1), Some(0));
+
+    // a snapshot is checked before the CPU takes it over
+    let paused = Cpu::new(&program).snapshot();
+    let mut value: serde_json::Value = serde_json::from_str(&paused).unwrap();
+    *value.pointer_mut("/frames/0/pc").unwrap() = serde_json::json!(1000);
+    assert!(other.restore(&value.to_string()).is_err());
+    assert_eq!(other.restore(&paused), Ok(()));
+    assert_eq!(other.status(), Status::Paused);
+    assert_eq!(other.line(), Some(2));
+
+    let errors = Program::parse("fn main() {\n mov A, #3\n  A\n}").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].line, 3);
+}
@@ -0,0 +1,283 @@
+use {
+    crate::{
+        api::{Cpu, Program, Status},
+        cpu::{diagnostic::Diagnostic, main::CPU, state::State, take_messages, Message},
+    },
+    serde::Serialize,
+    std::time::Duration,
+    wasm_bindgen::prelude::*,
+};
+
+/*
+ * Programs of the playground are stopped before they hang the tab
+ */
+const INSTRUCTION_LIMIT: u64 = 50_000_000;
+const TIME_LIMIT: Duration = Duration::from_secs(5);
+
+fn limit(cpu: &mut Cpu) {
+    cpu.set_instruction_limit(Some(INSTRUCTION_LIMIT));
+    cpu.set_time_limit(Some(TIME_LIMIT));
+}
+
+/*
+ * What happened since the last call, returned by every method which
+ * loads or runs a program
+ */
+#[derive(Serialize)]
+struct Report {
+    // the complete lines the program printed
+    output: Vec<String>,
+    messages: Vec<Message>,
+    // the errors of a program which could not be loaded
+    diagnostics: Vec<Diagnostic>,
+    status: &'static str,
+    line: Option<usize>,
+}
+
+/*
+ * `State` with the position of the program
+ */
+#[derive(Serialize)]
+struct PausedState {
+    #[serde(flatten)]
+    state: State,
+    status: &'static str,
+    line: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Disassembly {
+    lines: Vec<String>,
+    messages: Vec<Message>,
+}
+
+fn to_js<T: Serialize>(value: &T) -> JsValue {
+    serde_wasm_bindgen::to_value(value).unwrap_or(JsValue::NULL)
+}
+
+#[wasm_bindgen]
+pub struct RussemblyWasm {
+    // the program of `load` which runs piece by piece (or the last one `run_rusm` ran)
+    cpu: Option<Cpu>,
+    // printed text without a newline yet
+    pending: String,
+}
+#[wasm_bindgen]
+impl RussemblyWasm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> RussemblyWasm {
+        RussemblyWasm {
+            cpu: None,
+            pending: String::new(),
+        }
+    }
+    /*
+     * Load a program and pause it before its first line
+     */
+    pub fn load(&mut self, code: &str) -> JsValue {
+        self.reset();
+        match Program::parse(code) {
+            Ok(program) => {
+                let mut cpu = Cpu::new(&program);
+                limit(&mut cpu);
+                self.cpu = Some(cpu);
+                self.report(vec![])
+            }
+            Err(diagnostics) => self.report(diagnostics),
+        }
+    }
+    /*
+     * Execute up to `lines` lines (less at a breakpoint or the end)
+     */
+    pub fn step(&mut self, lines: u32) -> JsValue {
+        if let Some(cpu) = &mut self.cpu {
+            for _ in 0..lines {
+                if cpu.step() != Ok(Status::Paused) {
+                    break;
+                }
+            }
+        }
+        self.report(vec![])
+    }
+    /*
+     * Run until a breakpoint or the end but at most `lines` lines, the UI
+     * calls it again (e.g. every frame) while `status` is `paused`
+     */
+    pub fn run_until_breakpoint(&mut self, lines: u32) -> JsValue {
+        if let Some(cpu) = &mut self.cpu {
+            // the error is part of the report
+            let _ = cpu.run_for(lines as usize);
+        }
+        self.report(vec![])
+    }
+    /*
+     * `paused`, `breakpoint`, `watchpoint`, `finished` or `stopped` (after
+     * a runtime error like an exhausted budget)
+     */
+    pub fn status(&self) -> String {
+        self.status_name().to_string()
+    }
+    /*
+     * The line (1-based) the program is paused at
+     */
+    pub fn current_line(&self) -> Option<usize> {
+        self.cpu.as_ref()?.line()
+    }
+    /*
+     * The ports, the stack, the variables, ... of the program, `null`
+     * without a program
+     */
+    pub fn get_state(&self) -> JsValue {
+        let status = self.status_name();
+        let line = self.current_line();
+        match &self.cpu {
+            Some(cpu) => to_js(&PausedState {
+                state: cpu.state(),
+                status,
+                line,
+            }),
+            None => JsValue::NULL,
+        }
+    }
+    /*
+     * The whole state of the CPU as JSON (see `Cpu::restore`)
+     */
+    pub fn get_snapshot(&self) -> Option<String> {
+        self.cpu.as_ref().map(|cpu| cpu.snapshot())
+    }
+    /*
+     * Change an input pin while the program is paused
+     */
+    pub fn set_port(&mut self, port: usize, value: usize) -> Result<(), JsValue> {
+        let cpu = self.cpu.as_mut().ok_or("No program is loaded")?;
+        Ok(cpu.set_port(port, value)?)
+    }
+    /*
+     * A breakpoint at a line, function or label with an optional
+     * condition (`P1 == 3`), returns its id
+     */
+    pub fn set_breakpoint(
+        &mut self,
+        location: &str,
+        condition: Option<String>,
+    ) -> Result<usize, JsValue> {
+        let cpu = self.cpu.as_mut().ok_or("No program is loaded")?;
+        Ok(cpu.add_breakpoint(location, condition.as_deref())?)
+    }
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        match &mut self.cpu {
+            Some(cpu) => cpu.remove_breakpoint(id),
+            None => false,
+        }
+    }
+    /*
+     * Run a whole program, `get_state` shows the state after it
+     */
+    pub fn run_rusm(&mut self, code: &str) -> JsValue {
+        self.reset();
+        match Program::parse(code) {
+            Ok(program) => self.run(&program),
+            Err(diagnostics) => self.report(diagnostics),
+        }
+    }
+    /*
+     * Disassembly of an Intel HEX image, one instruction per line
+     */
+    pub fn disassemble_hex(&mut self, hex: &str) -> JsValue {
+        self.reset();
+        let mut lines = vec![];
+        if let Ok(mut cpu) = CPU::new() {
+            if cpu.load_hex(hex).is_some() {
+                let listing = cpu.disassemble(0, cpu.code_end());
+                lines = listing.lines().map(|line| line.to_string()).collect();
+            }
+        }
+        to_js(&Disassembly {
+            lines,
+            messages: take_messages(),
+        })
+    }
+    /*
+     * Compile a program into the `.rusmc` format
+     */
+    pub fn compile_rusm(&mut self, code: &str) -> Vec<u8> {
+        match Program::parse(code) {
+            Ok(program) => program.to_rusmc(),
+            Err(_) => vec![],
+        }
+    }
+    /*
+     * Run a program in the `.rusmc` format without parsing it
+     */
+    pub fn run_rusmc(&mut self, bytes: &[u8]) -> JsValue {
+        self.reset();
+        match Program::from_rusmc(bytes) {
+            Ok(program) => self.run(&program),
+            Err(diagnostics) => self.report(diagnostics),
+        }
+    }
+}
+
+impl RussemblyWasm {
+    /*
+     * Forget the last program and what it printed
+     */
+    fn reset(&mut self) {
+        self.cpu = None;
+        self.pending.clear();
+        take_messages();
+    }
+    fn run(&mut self, program: &Program) -> JsValue {
+        let mut cpu = Cpu::new(program);
+        limit(&mut cpu);
+        // the error is part of the report
+        let _ = cpu.run();
+        self.cpu = Some(cpu);
+        self.report(vec![])
+    }
+    fn status_name(&self) -> &'static str {
+        let cpu = match &self.cpu {
+            Some(cpu) => cpu,
+            None => return "finished",
+        };
+        match cpu.status() {
+            Status::Paused => "paused",
+            Status::Breakpoint(_) => "breakpoint",
+            Status::Watchpoint(_) => "watchpoint",
+            Status::Finished if cpu.error().is_some() => "stopped",
+            Status::Finished => "finished",
+        }
+    }
+    /*
+     * The output is split into lines, an unfinished line waits for the
+     * rest unless the program ended
+     */
+    fn output_lines(&mut self) -> Vec<String> {
+        let cpu = match &mut self.cpu {
+            Some(cpu) => cpu,
+            None => return vec![],
+        };
+        self.pending.push_str(&cpu.take_output());
+        let complete = match cpu.status() {
+            Status::Finished => self.pending.len(),
+            _ => self.pending.rfind('\n').map_or(0, |end| end + 1),
+        };
+        let text: String = self.pending.drain(..complete).collect();
+        text.lines().map(|line| line.to_string()).collect()
+    }
+    fn report(&mut self, diagnostics: Vec<Diagnostic>) -> JsValue {
+        let output = self.output_lines();
+        let line = self.current_line();
+        let messages = match &mut self.cpu {
+            Some(cpu) => cpu.take_messages(),
+            None => vec![],
+        };
+        to_js(&Report {
+            output,
+            messages,
+            diagnostics,
+            status: self.status_name(),
+            line,
+        })
+    }
+}